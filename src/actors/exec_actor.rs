@@ -1,19 +1,225 @@
 use crate::actors::install::PipeResult;
-use crate::contracts::Actor;
+use crate::actors::script_env::exit_code;
+use crate::actors::{InstallActor, InstallMode, PackageType, ScriptEnv};
+use crate::cache::DLX_CACHE_FOLDER;
+use crate::conf::NpmConfig;
+use crate::contracts::{Actor, Registry as _, LOCK_FILE_NAME};
 use crate::errors::ExecutionError;
+use crate::fs::get_config_dir;
+use crate::logger::CraftLogger;
+use crate::network::Http;
+use crate::package::{Package, Registry};
+use crate::registry::NpmRegistry;
 use async_trait::async_trait;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
 use std::env;
-use std::path::PathBuf;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
+use std::time::Duration;
+
+/// How long an exec / dlx prefix is reused before its packages are installed again
+const PREFIX_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Moves into a directory and back when dropped, also when the work in between fails
+struct CurrentDir {
+    previous: PathBuf,
+}
+
+impl CurrentDir {
+    fn enter(dir: &Path) -> std::io::Result<Self> {
+        let previous = env::current_dir()?;
+        env::set_current_dir(dir)?;
+        Ok(Self { previous })
+    }
+}
+
+impl Drop for CurrentDir {
+    fn drop(&mut self) {
+        let _ = env::set_current_dir(&self.previous);
+    }
+}
 
 pub struct ExecActor {
     pub script: String,
     pub args: Option<Vec<String>>,
+    /// Packages installed into a temporary prefix before the command runs
+    pub packages: Vec<String>,
+    config: NpmConfig,
 }
 
 impl ExecActor {
-    pub fn new(script: String, args: Option<Vec<String>>) -> Self {
-        Self { script, args }
+    pub fn new(
+        script: String,
+        args: Option<Vec<String>>,
+        packages: Vec<String>,
+        config: NpmConfig,
+    ) -> Self {
+        Self {
+            script,
+            args,
+            packages,
+            config,
+        }
+    }
+
+    /// The prefix holding `packages`, shared by every exec asking for the same packages
+    fn prefix_dir(cache: &Path, packages: &[String]) -> PathBuf {
+        let mut specs = packages.to_vec();
+        specs.sort();
+        let digest = hex::encode(Sha256::digest(specs.join("\n")));
+        cache.join(&digest[..16])
+    }
+
+    /// The version installed in the prefix for a package
+    fn installed_version(prefix: &Path, package: &Package) -> Option<String> {
+        let folder = package.alias.as_deref().unwrap_or(&package.name);
+        let manifest = fs::read_to_string(
+            prefix
+                .join("node_modules")
+                .join(folder)
+                .join("package.json"),
+        );
+        let manifest: Value = serde_json::from_str(&manifest.ok()?).ok()?;
+        manifest.get("version")?.as_str().map(str::to_string)
+    }
+
+    /// Whether a recent install in the prefix can be reused. Dist-tags such as `latest` move
+    /// with new releases, they are checked against the registry first
+    async fn is_current(&self, prefix: &Path) -> bool {
+        let installed = fs::metadata(prefix.join(LOCK_FILE_NAME)).and_then(|meta| meta.modified());
+        if !installed.is_ok_and(|at| at.elapsed().is_ok_and(|age| age < PREFIX_MAX_AGE)) {
+            return false;
+        }
+
+        let Ok(http) = Http::new(&self.config) else {
+            return true;
+        };
+        let registry = NpmRegistry::new(&self.config, http);
+        for spec in &self.packages {
+            let package = Package::new(PackageType::Prod(spec.clone()));
+            if package.registry != Registry::Npm || package.dist_tag(&self.config.tag).is_none() {
+                continue;
+            }
+            // Unreachable registries keep the installed version
+            let Ok(tagged) = registry.fetch(&package).await else {
+                continue;
+            };
+            if Self::installed_version(prefix, &package).as_ref() != Some(&tagged.version) {
+                CraftLogger::verbose(format!("{} moved to {}", package, tagged.version));
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Installs the packages into their prefix with the install pipeline, unless a recent
+    /// install is still current
+    async fn install_prefix(&self) -> Result<PathBuf, ExecutionError> {
+        let prefix = Self::prefix_dir(&get_config_dir(DLX_CACHE_FOLDER.clone()), &self.packages);
+        if self.is_current(&prefix).await {
+            return Ok(prefix);
+        }
+
+        let failed = |e: std::io::Error| {
+            ExecutionError::JobExecutionFailed(prefix.display().to_string(), e.to_string())
+        };
+        let _ = fs::remove_dir_all(&prefix);
+        fs::create_dir_all(&prefix).map_err(failed)?;
+        fs::write(prefix.join("package.json"), "{}").map_err(failed)?;
+
+        // The install pipeline works on the current directory
+        let current_dir = CurrentDir::enter(&prefix).map_err(failed)?;
+        let packages = self
+            .packages
+            .iter()
+            .map(|package| PackageType::Prod(package.clone()))
+            .collect();
+        let result = InstallActor::new(packages, InstallMode::Add, self.config.clone())
+            .start()
+            .await;
+        drop(current_dir);
+
+        if result.is_err() {
+            let _ = fs::remove_dir_all(&prefix);
+        }
+        result.map(|_| prefix)
+    }
+
+    /// The bin a package runs when the command names the package itself, like npm
+    /// its only bin or the one called like the package
+    fn default_bin(prefix: &Path, spec: &str) -> Option<String> {
+        let (name, _) = PackageType::Prod(spec.to_string()).get_parts();
+        let manifest =
+            fs::read_to_string(prefix.join("node_modules").join(&name).join("package.json"));
+        let manifest: Value = serde_json::from_str(&manifest.ok()?).ok()?;
+        let unscoped = name.rsplit('/').next().unwrap_or(&name).to_string();
+
+        match manifest.get("bin")? {
+            Value::String(_) => Some(unscoped),
+            Value::Object(bins) if bins.len() == 1 => bins.keys().next().cloned(),
+            Value::Object(bins) => bins.contains_key(&unscoped).then_some(unscoped),
+            _ => None,
+        }
+    }
+
+    /// Runs the command from the prefix or the node_modules/.bin folders of the project,
+    /// the arguments reach it exactly as given
+    fn run(&self, cwd: &Path, prefix: Option<&Path>) -> PipeResult {
+        let mut script_env = ScriptEnv::new(&self.config);
+        let mut bin_dirs = ScriptEnv::bin_paths(cwd);
+        let mut names = vec![self.script.clone()];
+        if let Some(prefix) = prefix {
+            let bin_dir = prefix.join("node_modules").join(".bin");
+            bin_dirs.insert(0, bin_dir.clone());
+            script_env = script_env.with_bin_dir(bin_dir);
+            if self.packages.contains(&self.script) {
+                names.extend(Self::default_bin(prefix, &self.script));
+            }
+        }
+
+        let found = names.iter().find_map(|name| {
+            bin_dirs
+                .iter()
+                .find_map(|dir| find_file_to_execute(name, dir))
+        });
+        let Some((shell, script)) = found else {
+            return Err(ExecutionError::NoScriptsFound);
+        };
+
+        let mut command_to_execute = match shell {
+            ScriptType::Bash | ScriptType::Cmd => Command::new(&script),
+            ScriptType::Pwsh => {
+                let mut command = Command::new("pwsh");
+                command.arg("-File").arg(&script);
+                command
+            }
+        };
+        let args = self.args.clone().unwrap_or_default();
+
+        // npm runs exec commands as the `npx` event of the current package
+        let line = [self.script.clone()]
+            .into_iter()
+            .chain(args.iter().cloned())
+            .collect::<Vec<_>>()
+            .join(" ");
+        let status = command_to_execute
+            .args(&args)
+            .current_dir(cwd)
+            .envs(script_env.vars(cwd, "npx", &line))
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .status()
+            .map_err(|e| ExecutionError::JobExecutionFailed(self.script.clone(), e.to_string()))?;
+
+        if !status.success() {
+            return Err(ExecutionError::ScriptExited(
+                self.script.clone(),
+                exit_code(&status),
+            ));
+        }
+        Ok(())
     }
 }
 
@@ -46,106 +252,124 @@ fn get_file_ending_for_running() -> ScriptType {
     }
 }
 
-fn find_file_to_execute(script: &str, path_to_scan: &PathBuf) -> Option<(ScriptType, PathBuf)> {
+fn find_file_to_execute(script: &str, path_to_scan: &Path) -> Option<(ScriptType, PathBuf)> {
     let file_ending = get_file_ending_for_running();
+    let path_to_file = path_to_scan.join(file_ending.get_script_file_command(script));
 
-    let file_name_to_find = file_ending.get_script_file_command(script);
+    // .bin entries are symlinks, is_file follows them
+    path_to_file
+        .is_file()
+        .then_some((file_ending, path_to_file))
+}
+
+#[async_trait]
+impl Actor<PipeResult> for ExecActor {
+    async fn start(&mut self) -> PipeResult {
+        let exec_path = env::current_dir().expect("Error getting cwd");
 
-    let bin_dir = std::fs::read_dir(path_to_scan).unwrap();
+        let prefix = match self.packages.is_empty() {
+            true => None,
+            false => Some(self.install_prefix().await?),
+        };
 
-    for entry in bin_dir.flatten() {
-        let path_to_file = entry.path();
-        let file_name = entry.file_name();
-        if let Some(file_name) = file_name.to_str() {
-            if file_name_to_find == file_name && entry.metadata().unwrap().is_file() {
-                return Some((file_ending, path_to_file));
-            }
-        }
+        self.run(&exec_path, prefix.as_deref())
     }
-    None
 }
 
-fn get_possible_script_paths() -> Vec<PathBuf> {
-    let mut paths = vec![];
+// ─── Tests ───────────────────────────────────────────────────────────────────
 
-    let bin_path = env::current_dir()
-        .unwrap()
-        .join("node_modules")
-        .join(".bin");
-    let bin_dir = std::fs::metadata(&bin_path);
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    if bin_dir.is_ok() {
-        paths.push(bin_path);
+    fn manifest(prefix: &Path, name: &str, bin: &str) {
+        let dir = prefix.join("node_modules").join(name);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("package.json"), format!(r#"{{"bin":{}}}"#, bin)).unwrap();
     }
 
-    paths
-}
+    #[test]
+    fn test_default_bin() {
+        let prefix = tempfile::tempdir().unwrap();
+        manifest(
+            prefix.path(),
+            "create-vite",
+            r#"{"create-vite":"a","cva":"a"}"#,
+        );
+        manifest(prefix.path(), "@scope/tool", r#""cli.js""#);
+        manifest(prefix.path(), "multi", r#"{"one":"a","two":"b"}"#);
 
-#[async_trait]
-impl Actor<PipeResult> for ExecActor {
-    async fn start(&mut self) -> PipeResult {
-        let exec_path = env::current_dir().expect("Error getting cwd");
-
-        let mut possible_scripts = vec![];
+        let bin = |spec: &str| ExecActor::default_bin(prefix.path(), spec);
+        assert_eq!(bin("create-vite@5").as_deref(), Some("create-vite"));
+        assert_eq!(bin("@scope/tool").as_deref(), Some("tool"));
+        assert_eq!(bin("multi"), None);
+    }
 
-        get_possible_script_paths().iter().for_each(|path| {
-            if let Some(p) = find_file_to_execute(self.script.as_str(), path) {
-                possible_scripts.push(p);
-            }
-        });
+    #[test]
+    fn test_installed_version() {
+        let prefix = tempfile::tempdir().unwrap();
+        let dir = prefix.path().join("node_modules").join("vite5");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("package.json"), r#"{"version":"5.1.0"}"#).unwrap();
 
-        if possible_scripts.is_empty() {
-            return Err(ExecutionError::NoScriptsFound);
-        }
+        let version = |spec: &str| {
+            let package = Package::new(PackageType::Prod(spec.to_string()));
+            ExecActor::installed_version(prefix.path(), &package)
+        };
+        assert_eq!(version("vite5@npm:vite@latest").as_deref(), Some("5.1.0"));
+        assert_eq!(version("vite@latest"), None);
+    }
 
-        let mut command_to_execute;
-
-        let (shell, script) = &possible_scripts[0];
-        match shell {
-            ScriptType::Bash => {
-                let args = match &self.args {
-                    Some(args) => args,
-                    None => &Vec::<String>::new(),
-                };
-
-                let arg_full = format!("{} {}", script.to_str().unwrap(), args.join(" "));
-
-                command_to_execute = Command::new("sh");
-                command_to_execute
-                    .arg("-c")
-                    .stdout(Stdio::inherit())
-                    .current_dir(exec_path)
-                    .stderr(Stdio::inherit())
-                    .arg(arg_full);
-            }
-            ScriptType::Cmd => {
-                command_to_execute = Command::new("cmd");
-                command_to_execute
-                    .args(["/C", script.to_str().unwrap()])
-                    .current_dir(exec_path)
-                    .stdout(Stdio::inherit())
-                    .stderr(Stdio::inherit());
-            }
-            ScriptType::Pwsh => {
-                command_to_execute = Command::new("pwsh");
-                command_to_execute
-                    .args(["-File", script.to_str().unwrap()])
-                    .current_dir(exec_path)
-                    .stdout(Stdio::inherit())
-                    .stderr(Stdio::inherit());
-            }
+    #[test]
+    fn test_prefix_dir() {
+        let cache = Path::new("/cache");
+        let prefix = |packages: &[&str]| {
+            let packages: Vec<String> = packages.iter().map(|p| p.to_string()).collect();
+            ExecActor::prefix_dir(cache, &packages)
         };
+        assert_eq!(prefix(&["a", "b@1"]), prefix(&["b@1", "a"]));
+        assert_ne!(prefix(&["a"]), prefix(&["a@2"]));
+    }
 
-        if let Some(args) = &self.args {
-            command_to_execute.args(args);
-        }
+    #[cfg(unix)]
+    #[test]
+    fn test_run_passes_args_verbatim() {
+        use std::os::unix::fs::PermissionsExt;
 
-        let mut child = command_to_execute
-            .spawn()
-            .expect("failed to execute process");
+        let project = tempfile::tempdir().unwrap();
+        let prefix = tempfile::tempdir().unwrap();
+        let bin_dir = prefix.path().join("node_modules").join(".bin");
+        fs::create_dir_all(&bin_dir).unwrap();
+        let out = project.path().join("out");
+        let tool = bin_dir.join("tool");
+        let script = format!(
+            "#!/bin/sh\nprintf '%s\\n' \"$@\" > {}\nexit 4\n",
+            out.display()
+        );
+        fs::write(&tool, script).unwrap();
+        fs::set_permissions(&tool, fs::Permissions::from_mode(0o755)).unwrap();
+        manifest(prefix.path(), "tool-pkg", r#"{"tool":"tool.js"}"#);
 
-        let _ = child.wait().expect("child process wasn't running");
+        let actor = ExecActor::new(
+            "tool-pkg@1".to_string(),
+            Some(vec![
+                "a b".to_string(),
+                "$HOME".to_string(),
+                "--x".to_string(),
+            ]),
+            vec!["tool-pkg@1".to_string()],
+            NpmConfig::new(Default::default()),
+        );
+        let error = actor.run(project.path(), Some(prefix.path())).unwrap_err();
+        assert_eq!(error.exit_code(), 4);
+        assert_eq!(fs::read_to_string(&out).unwrap(), "a b\n$HOME\n--x\n");
 
-        Ok(())
+        let missing = ExecActor::new(
+            "nothing".to_string(),
+            None,
+            vec![],
+            NpmConfig::new(Default::default()),
+        );
+        assert!(missing.run(project.path(), None).is_err());
     }
 }
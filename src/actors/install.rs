@@ -1,20 +1,30 @@
 use std::{
-    sync::mpsc::Receiver,
+    env, fs,
+    path::Path,
+    sync::{mpsc::Receiver, Arc},
     thread::{self, JoinHandle},
 };
 
 use async_trait::async_trait;
 
 use crate::actors::peer_resolver::PeerResolver;
+use crate::actors::script_env::exit_code;
+use crate::actors::ScriptEnv;
 use crate::cache::PackagesCache;
-use crate::contracts::{Lockfile, PersistentCache};
+use crate::conf::NpmConfig;
+use crate::contracts::{Lockfile, PersistentCache, LOCK_FILE_NAME};
 use crate::lockfile::lock_file_actor::LockFileActor;
-use crate::pipeline::ConfigReader;
+use crate::network::Http;
+use crate::package::PackageJson;
+use crate::registry::{NpmRegistry, Registries};
 use crate::{
     contracts::{Actor, Pipe, PipeArtifact, Progress, ProgressAction},
     errors::ExecutionError,
     logger::CraftLogger,
-    pipeline::{DownloaderPipe, ExtractorPipe, LinkerPipe, ResolverPipe},
+    pipeline::{
+        BuildPolicy, BuilderPipe, DownloaderPipe, ExtractorPipe, LinkerPipe, LockfileResolverPipe,
+        ResolverPipe,
+    },
     ui::UIProgress,
 };
 
@@ -30,33 +40,22 @@ pub enum PackageType {
 impl PackageType {
     pub fn get_parts(&self) -> (String, String) {
         fn split_name(key: &str) -> (String, String) {
-            let nums_of_ats = key.chars().filter(|c| *c == '@').count();
-            let is_scoped_package = key.starts_with("@");
-
-            // Normal case like is-even@1
-            if !is_scoped_package && nums_of_ats == 1 {
-                let parts = key.rsplitn(2, '@').collect::<Vec<_>>();
-                return (parts[1].to_string(), parts[0].to_string());
-            }
-
-            if is_scoped_package && nums_of_ats == 2 {
-                let parts = key.rsplitn(2, '@').collect::<Vec<_>>();
-                return (parts[1].to_string(), parts[0].to_string());
-            }
-
-            // Normal case like is-even => no version
-            if nums_of_ats == 0 {
-                return (key.to_string(), "*".to_string());
+            // Name-less specs like github:owner/repo, git+ssh://git@host/repo.git or ./vendor/x.tgz,
+            // the real name comes from the manifest of the package
+            let rest = key.get(1..).unwrap_or_default();
+            let name_part = rest.split('@').next().unwrap_or_default();
+            let is_path = key.starts_with('.') || key.starts_with('/') || key.starts_with('~');
+            if is_path || key.starts_with("git@") || name_part.contains(':') {
+                return (key.to_string(), key.to_string());
             }
 
-            // Normal case like @babel/transform@1
-            if is_scoped_package && nums_of_ats == 1 {
-                // There is no version available
-                return (key.to_string(), "*".to_string());
+            // The version starts at the first @ after the optional scope,
+            // so is-even@1, @babel/transform@1 and name@git+ssh://git@host/repo all split correctly
+            match rest.find('@') {
+                Some(index) => (key[..=index].to_string(), key[index + 2..].to_string()),
+                // Normal case like is-even => no version
+                None => (key.to_string(), "*".to_string()),
             }
-
-            // We can't have like 3 @s
-            panic!("Too many @s")
         }
         match self {
             PackageType::Dev(d) => split_name(d),
@@ -68,13 +67,71 @@ impl PackageType {
     }
 }
 
+/// Decides how the install treats craft-lock.yaml
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InstallMode {
+    /// `craft install <packages>`, adds the packages to the lockfile
+    Add,
+    /// `craft install`, syncs the lockfile with package.json
+    PackageJson,
+    /// `craft install --frozen-lockfile`, installs exactly what the lockfile describes
+    Frozen,
+}
+
 pub struct InstallActor {
     packages: Vec<PackageType>,
+    mode: InstallMode,
+    config: NpmConfig,
 }
 
 impl InstallActor {
-    pub fn new(packages: Vec<PackageType>) -> Self {
-        Self { packages }
+    pub fn new(packages: Vec<PackageType>, mode: InstallMode, config: NpmConfig) -> Self {
+        Self {
+            packages,
+            mode,
+            config,
+        }
+    }
+
+    /// Runs the lifecycle scripts of the package.json in `root` that exist, in the given order.
+    /// npm only runs them for installs without package arguments.
+    fn run_root_hooks(&self, root: &Path, events: &[&str]) -> PipeResult {
+        if self.mode == InstallMode::Add || self.config.ignore_scripts {
+            return Ok(());
+        }
+        // Without a package.json there is nothing to hook into
+        let Ok(manifest) = fs::read_to_string(root.join("package.json")) else {
+            return Ok(());
+        };
+        let package_json: PackageJson = manifest.into();
+        let scripts = package_json.scripts.unwrap_or_default();
+        let script_env = ScriptEnv::new(&self.config);
+
+        for event in events {
+            let Some(script) = scripts.get(*event) else {
+                continue;
+            };
+            println!("> {}: {}", event, script);
+
+            let status = script_env
+                .command(root, event, script)
+                .status()
+                .map_err(|e| {
+                    ExecutionError::ScriptFailed(
+                        ROOT_PACKAGE.to_string(),
+                        event.to_string(),
+                        e.to_string(),
+                    )
+                })?;
+            if !status.success() {
+                return Err(ExecutionError::ScriptExited(
+                    event.to_string(),
+                    exit_code(&status),
+                ));
+            }
+        }
+
+        Ok(())
     }
 
     fn start_progress(&self, rx: Receiver<ProgressAction>) -> JoinHandle<()> {
@@ -88,35 +145,55 @@ impl InstallActor {
 
 pub(crate) type PipeResult = Result<(), ExecutionError>;
 
+/// How the project itself is named in script errors
+const ROOT_PACKAGE: &str = "package.json";
+
+/// Root scripts npm runs once dependencies are in place
+const ROOT_HOOKS_AFTER: [&str; 5] = [
+    "install",
+    "postinstall",
+    "preprepare",
+    "prepare",
+    "postprepare",
+];
+
 #[async_trait]
 impl Actor<PipeResult> for InstallActor {
     async fn start(&mut self) -> PipeResult {
+        let root = env::current_dir().expect("Error getting cwd");
+        self.run_root_hooks(&root, &["preinstall"])?;
+
         let (tx, rx) = std::sync::mpsc::channel();
         let mut cache = PackagesCache::default();
         cache.init().await.unwrap();
         let ui_thread = self.start_progress(rx);
 
-        // ─── Read configuration ─────────────────────────
-        let _conf = ConfigReader::new().run().await?;
+        let http =
+            Http::new(&self.config).map_err(|e| ExecutionError::ConfigError(e.to_string()))?;
+        let registry = Arc::new(NpmRegistry::new(&self.config, http.clone()));
 
         // ─── Start Resolving ─────────────────────────
 
         CraftLogger::verbose("Resolving dependencies");
-        let resolve_artifacts = ResolverPipe::new(self.packages.clone(), tx.clone())
-            .run()
-            .await?;
+        let resolve_artifacts = if self.mode == InstallMode::Frozen {
+            LockfileResolverPipe::new(self.packages.clone(), registry.clone(), tx.clone())
+                .run()
+                .await?
+        } else {
+            let registries = Arc::new(Registries::new(registry.clone(), http.clone()));
+            ResolverPipe::new(self.packages.clone(), registries, tx.clone())
+                .run()
+                .await?
+        };
         CraftLogger::verbose(format!(
             "Resolved: {:?}",
             resolve_artifacts.0.get_artifacts().len()
         ));
 
-        // ─── Start Mutating ───────────────────────
-        let recorder = PeerResolver::new(resolve_artifacts.1).run().await?;
-
         // ─── Start Downloading ──────────────────────
 
         CraftLogger::verbose("Downloading dependencies");
-        let download_artifacts = DownloaderPipe::new(&resolve_artifacts.0, tx.clone())
+        let download_artifacts = DownloaderPipe::new(&resolve_artifacts.0, http, tx.clone())
             .run()
             .await?;
 
@@ -137,22 +214,42 @@ impl Actor<PipeResult> for InstallActor {
             extracted_artifacts.get_artifacts().len()
         ));
 
+        // ─── Start Mutating ───────────────────────
+        let mut recorder = resolve_artifacts.1;
+        LockfileResolverPipe::restore_binaries(&mut recorder, &extracted_artifacts.get_artifacts());
+        let recorder = PeerResolver::new(recorder).run().await?;
+
         // ─── Start Linking ──────────────────────────
 
         CraftLogger::verbose("Linking dependencies");
-        LinkerPipe::new(
+        let linked = LinkerPipe::new(
             tx.clone(),
             resolve_artifacts.0.get_artifacts(),
             extracted_artifacts.get_artifacts(),
             recorder.clone(),
+            self.config.install_strategy.clone(),
+            self.config.package_import_method.clone(),
         )
         .run()
         .await?;
 
         // ─── Sync Lock File ────────────────────────
-        LockFileActor::new(resolve_artifacts.0.get_artifacts(), recorder)
+        if self.mode != InstallMode::Frozen {
+            let prune = self.mode == InstallMode::PackageJson;
+            LockFileActor::new(resolve_artifacts.0.get_artifacts(), recorder.clone(), prune)
+                .run()
+                .expect("Error writing lockfile");
+        }
+
+        // ─── Start Building ─────────────────────────
+
+        CraftLogger::verbose("Running lifecycle scripts of dependencies");
+        let lockfile = LockFileActor::read_lock_file(Path::new(LOCK_FILE_NAME)).ok();
+        let policy = BuildPolicy::new(&self.config, lockfile.as_ref());
+        let script_env = ScriptEnv::new(&self.config);
+        BuilderPipe::new(tx.clone(), linked, recorder, policy, script_env)
             .run()
-            .expect("Error writing lockfile");
+            .await?;
 
         // ─── Cleanup ────────────────────────────────
 
@@ -162,12 +259,16 @@ impl Actor<PipeResult> for InstallActor {
 
         drop(tx);
         ui_thread.join().unwrap();
-        Ok(())
+
+        // ─── Root lifecycle hooks ───────────────────
+
+        self.run_root_hooks(&root, &ROOT_HOOKS_AFTER)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
     use crate::actors::PackageType;
     use std::collections::HashMap;
 
@@ -179,6 +280,13 @@ mod tests {
         mappings.insert("is-even@~1", ("is-even", "~1"));
         mappings.insert("is-even@~1.2.0", ("is-even", "~1.2.0"));
         mappings.insert("is-even", ("is-even", "*"));
+        mappings.insert(
+            "lib@git+ssh://git@github.com/owner/lib.git#v1",
+            ("lib", "git+ssh://git@github.com/owner/lib.git#v1"),
+        );
+        mappings.insert("github:owner/lib", ("github:owner/lib", "github:owner/lib"));
+        mappings.insert("./vendor/x.tgz", ("./vendor/x.tgz", "./vendor/x.tgz"));
+        mappings.insert("shared@file:../shared", ("shared", "file:../shared"));
 
         mappings.iter().for_each(|(k, v)| {
             let pkg_type = PackageType::Dev(k.to_string());
@@ -188,4 +296,44 @@ mod tests {
             assert_eq!(parts.1, v.1);
         })
     }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_root_hooks() {
+        let root = tempfile::tempdir().unwrap();
+        let log = root.path().join("log");
+        let append = |event: &str| format!("echo {} >> {}", event, log.display());
+        let manifest = format!(
+            r#"{{"scripts":{{"preinstall":"{}","postinstall":"{}","prepare":"exit 3"}}}}"#,
+            append("$npm_lifecycle_event"),
+            append("$npm_lifecycle_event")
+        );
+        fs::write(root.path().join("package.json"), manifest).unwrap();
+        let config = NpmConfig::new(Default::default());
+        let actor = InstallActor::new(vec![], InstallMode::PackageJson, config.clone());
+
+        actor.run_root_hooks(root.path(), &["preinstall"]).unwrap();
+        assert_eq!(fs::read_to_string(&log).unwrap(), "preinstall\n");
+
+        let error = actor
+            .run_root_hooks(root.path(), &ROOT_HOOKS_AFTER)
+            .unwrap_err();
+        assert_eq!(error.exit_code(), 3);
+        assert_eq!(
+            fs::read_to_string(&log).unwrap(),
+            "preinstall\npostinstall\n"
+        );
+
+        // Installing named packages leaves the project hooks alone
+        InstallActor::new(vec![], InstallMode::Add, config)
+            .run_root_hooks(root.path(), &ROOT_HOOKS_AFTER)
+            .unwrap();
+        InstallActor::new(
+            vec![],
+            InstallMode::PackageJson,
+            NpmConfig::new(Default::default()),
+        )
+        .run_root_hooks(&root.path().join("missing"), &ROOT_HOOKS_AFTER)
+        .unwrap();
+    }
 }
@@ -4,10 +4,13 @@ mod install;
 mod peer_resolver;
 mod preprocesse_dependency_install;
 mod run;
+mod script_env;
 
 pub use cache_clean::CacheCleanActor;
 pub use exec_actor::ExecActor;
 pub use install::InstallActor;
+pub use install::InstallMode;
 pub use install::PackageType;
 pub use preprocesse_dependency_install::PreprocessDependencyInstall;
 pub use run::RunActor;
+pub use script_env::ScriptEnv;
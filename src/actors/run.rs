@@ -1,52 +1,193 @@
+use crate::actors::install::PipeResult;
+use crate::actors::script_env::{exit_code, is_cmd};
+use crate::actors::{PreprocessDependencyInstall, ScriptEnv};
+use crate::conf::NpmConfig;
 use crate::contracts::Actor;
+use crate::errors::ExecutionError;
 use async_trait::async_trait;
+use std::collections::HashMap;
 use std::env;
-use std::process::{Command, Stdio};
+use std::path::Path;
 
 pub struct RunActor {
     pub script: String,
+    pub args: Vec<String>,
     pub cwd: Option<String>,
+    config: NpmConfig,
 }
 
 impl RunActor {
-    pub fn new(script: String, cwd: Option<String>) -> Self {
-        Self { script, cwd }
+    pub fn new(script: String, args: Vec<String>, cwd: Option<String>, config: NpmConfig) -> Self {
+        Self {
+            script,
+            args,
+            cwd,
+            config,
+        }
+    }
+
+    /// Quotes an argument appended to a script line so the shell hands it over unchanged
+    fn quote(shell: &str, arg: &str) -> String {
+        let plain = !arg.is_empty()
+            && arg
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-_=./:@,+".contains(c));
+        if plain {
+            arg.to_string()
+        } else if is_cmd(shell) {
+            format!("\"{}\"", arg.replace('"', "\"\""))
+        } else {
+            format!("'{}'", arg.replace('\'', "'\\''"))
+        }
+    }
+
+    /// Runs `pre<script>`, the script with the forwarded arguments and `post<script>`.
+    /// `ignore-scripts` skips the hooks only, the script itself was asked for.
+    fn run_scripts(&self, scripts: &HashMap<String, String>, dir: &Path) -> PipeResult {
+        let Some(script) = scripts.get(&self.script) else {
+            if self.config.if_present {
+                return Ok(());
+            }
+            return Err(ExecutionError::ScriptNotFound(self.script.clone()));
+        };
+
+        let env = ScriptEnv::new(&self.config);
+        let shell = env.shell();
+        let mut line = script.clone();
+        for arg in &self.args {
+            line.push(' ');
+            line.push_str(&Self::quote(shell, arg));
+        }
+
+        let pre = format!("pre{}", self.script);
+        let post = format!("post{}", self.script);
+        let hook = |event: &String| match self.config.ignore_scripts {
+            true => None,
+            false => Some((event.clone(), scripts.get(event)?.clone())),
+        };
+        let steps = [hook(&pre), Some((self.script.clone(), line)), hook(&post)];
+
+        for (event, line) in steps.into_iter().flatten() {
+            println!("> {}: {}", event, line);
+
+            let status = env
+                .command(dir, &event, &line)
+                .status()
+                .map_err(|e| ExecutionError::JobExecutionFailed(event.clone(), e.to_string()))?;
+            if !status.success() {
+                return Err(ExecutionError::ScriptExited(event, exit_code(&status)));
+            }
+        }
+
+        Ok(())
     }
 }
 
 #[async_trait]
-impl Actor<crate::actors::install::PipeResult> for RunActor {
-    async fn start(&mut self) -> crate::actors::install::PipeResult {
+impl Actor<PipeResult> for RunActor {
+    async fn start(&mut self) -> PipeResult {
         let mut exec_path = env::current_dir().expect("Error getting cwd");
 
         if let Some(c) = self.cwd.clone() {
             exec_path = exec_path.join(c);
         }
 
-        let mut child = if cfg!(target_os = "windows") {
-            Command::new("cmd")
-                .args(["/C", self.script.as_str()])
-                .current_dir(exec_path)
-                .stdout(Stdio::inherit())
-                // creating a pipe to capture the standard error (stderr) of the child process.
-                .stderr(Stdio::inherit())
-                // output() executes the child process synchronously and captures its output.
-                //It returns a std::process::Output struct containing information about the process's exit status, stdout, and stderr.
-                .spawn()
-                .expect("failed to execute process")
-        } else {
-            Command::new("sh")
-                .arg("-c")
-                .stdout(Stdio::inherit())
-                .current_dir(exec_path)
-                // creating a pipe to capture the standard error (stderr) of the child process.
-                .stderr(Stdio::inherit())
-                .arg(self.script.as_str())
-                .spawn()
-                .expect("failed to execute process")
+        let scripts = PreprocessDependencyInstall::get_script()?;
+        self.run_scripts(&scripts, &exec_path)
+    }
+}
+
+// ─── Tests ───────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn actor(script: &str, args: &[&str], config: &[(&str, &str)]) -> RunActor {
+        let config = config
+            .iter()
+            .map(|(key, value)| (key.to_string(), Some(value.to_string())))
+            .collect();
+        RunActor::new(
+            script.to_string(),
+            args.iter().map(|arg| arg.to_string()).collect(),
+            None,
+            NpmConfig::new(config),
+        )
+    }
+
+    #[test]
+    fn test_parse_args() {
+        use crate::command::{Command, SubCommand};
+        use clap::Parser;
+
+        let command =
+            Command::try_parse_from(["craft", "run", "build", "--", "--watch", "a b"]).unwrap();
+        let SubCommand::Run(run) = command.command else {
+            panic!("Expected the run command");
         };
-        let _ = child.wait().expect("child process wasn't running");
+        assert_eq!(run.script, "build");
+        assert_eq!(run.args, vec!["--watch", "a b"]);
+    }
 
-        Ok(())
+    #[test]
+    fn test_quote() {
+        assert_eq!(RunActor::quote("/bin/sh", "--watch"), "--watch");
+        assert_eq!(RunActor::quote("/bin/sh", "a b"), "'a b'");
+        assert_eq!(RunActor::quote("/bin/sh", "it's"), "'it'\\''s'");
+        assert_eq!(RunActor::quote("/bin/sh", ""), "''");
+        assert_eq!(
+            RunActor::quote("cmd.exe", "say \"hi\""),
+            "\"say \"\"hi\"\"\""
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_scripts() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("log");
+        let append = |text: &str| format!("echo {} >> {}", text, log.display());
+        let scripts: HashMap<String, String> = [
+            ("pretest", append("pre")),
+            ("test", append("test")),
+            ("posttest", append("post")),
+            ("fail", "exit 3".to_string()),
+            ("postfail", append("postfail")),
+        ]
+        .into_iter()
+        .map(|(name, script)| (name.to_string(), script))
+        .collect();
+
+        actor("test", &["a b"], &[])
+            .run_scripts(&scripts, dir.path())
+            .unwrap();
+        assert_eq!(fs_log(&log), "pre\ntest a b\npost\n");
+
+        actor("test", &[], &[("ignore-scripts", "true")])
+            .run_scripts(&scripts, dir.path())
+            .unwrap();
+        assert_eq!(fs_log(&log), "test\n");
+
+        let error = actor("fail", &[], &[])
+            .run_scripts(&scripts, dir.path())
+            .unwrap_err();
+        assert_eq!(error.exit_code(), 3);
+        assert!(!log.exists());
+
+        assert!(actor("lint", &[], &[])
+            .run_scripts(&scripts, dir.path())
+            .is_err());
+        actor("lint", &[], &[("if-present", "true")])
+            .run_scripts(&scripts, dir.path())
+            .unwrap();
+    }
+
+    /// Reads and clears the script log
+    #[cfg(unix)]
+    fn fs_log(log: &Path) -> String {
+        let content = std::fs::read_to_string(log).unwrap();
+        std::fs::remove_file(log).unwrap();
+        content
     }
 }
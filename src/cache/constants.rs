@@ -7,6 +7,9 @@ lazy_static! {
     pub static ref PACKAGES_CACHE_FOLDER: PathBuf = PathBuf::from(".craft/cache/packages");
     pub static ref REGISTRY_CACHE_FOLDER: PathBuf = PathBuf::from(".craft/cache/registry");
     pub static ref DEP_CACHE_FOLDER: PathBuf = PathBuf::from(".craft/cache/node_modules");
+    pub static ref GIT_CACHE_FOLDER: PathBuf = PathBuf::from(".craft/cache/git");
+    pub static ref DLX_CACHE_FOLDER: PathBuf = PathBuf::from(".craft/cache/dlx");
+    pub static ref STORE_FOLDER: PathBuf = PathBuf::from(".craft/store");
 }
 
 // ─── Files ───────────────────────────────────────────────────────────────────
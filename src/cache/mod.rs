@@ -1,8 +1,10 @@
 mod constants;
 mod packages;
 mod registry;
+mod store;
 
-pub use constants::DEP_CACHE_FOLDER;
+pub use constants::{DEP_CACHE_FOLDER, DLX_CACHE_FOLDER, GIT_CACHE_FOLDER};
 pub use packages::PackagesCache;
-pub use registry::RegistryCache;
 pub use registry::RegistryKey;
+pub use registry::{CachedPackument, RegistryCache};
+pub use store::ContentStore;
@@ -89,7 +89,7 @@ impl Default for PackagesCache {
 // ───────────────────────────────────────────────────────────────────────────────
 
 #[async_trait]
-impl PersistentCache<PathBuf> for PackagesCache {
+impl PersistentCache for PackagesCache {
     async fn init(&mut self) -> Result<(), CacheError> {
         self.cache = Self::read_cache_directory(&self.directory).await?;
         self.downloaded_modules = Self::read_node_modules(&self.directory).await?;
@@ -110,15 +110,4 @@ impl PersistentCache<PathBuf> for PackagesCache {
     async fn has(&mut self, key: &RegistryKey) -> bool {
         self.cache.contains(&key.to_owned())
     }
-    async fn get(&mut self, key: &RegistryKey) -> Option<PathBuf> {
-        if self.has(key).await {
-            return Some(self.directory.join::<PathBuf>(key.clone().into()));
-        }
-
-        None
-    }
-
-    async fn set(&mut self, key: &RegistryKey, _: PathBuf) -> () {
-        self.cache.insert(key.clone());
-    }
 }
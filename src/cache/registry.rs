@@ -1,11 +1,16 @@
 use super::constants::REGISTRY_CACHE_FOLDER;
+use super::store::write_atomic;
 use crate::fs::get_config_dir;
-use crate::{contracts::PersistentCache, errors::CacheError, package::NpmPackage};
-use async_trait::async_trait;
-use nodejs_semver::{Range, Version};
+use crate::{
+    errors::CacheError,
+    lockfile::lockfile_structure::LockfileResolution,
+    package::{FullPackage, NpmPackage},
+};
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::fmt::Display;
-use std::{collections::HashMap, fs::File, io, path::PathBuf};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 //
 #[derive(Eq, Debug, Hash, PartialEq, Clone, Serialize, Deserialize)]
@@ -20,6 +25,21 @@ impl Display for RegistryKey {
     }
 }
 
+impl RegistryKey {
+    /// Key a package is stored under, the source of git, tarball and local packages is
+    /// part of the version so they never take the place of a registry release
+    pub fn stored(name: &str, version: &str, resolution: Option<&LockfileResolution>) -> Self {
+        let version = match resolution.and_then(LockfileResolution::source_id) {
+            Some(id) => format!("{}+{}", version, id),
+            None => version.to_string(),
+        };
+        RegistryKey {
+            name: name.to_string(),
+            version,
+        }
+    }
+}
+
 impl From<RegistryKey> for PathBuf {
     fn from(val: RegistryKey) -> Self {
         PathBuf::from(format!("{}@{}", val.name, val.version))
@@ -76,137 +96,118 @@ mod tests {
     }
 }
 
-// ─── RegistryCache ───────────────────────────────────────────────────────────────
-
-#[derive(Debug, Clone)]
-pub struct RegistryCache {
-    pub directory: PathBuf,
-    // express -> 4.17.1 -> NpmPackage
-    pub cache: HashMap<String, HashMap<String, NpmPackage>>,
+// ─── CachedPackument ─────────────────────────────────────────────────────────
+
+/// An abbreviated packument with the validators it was served with
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedPackument {
+    #[serde(flatten)]
+    pub packument: FullPackage,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(
+        rename = "last-modified",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub last_modified: Option<String>,
+    /// Seconds since the epoch of the last fetch or revalidation
+    #[serde(rename = "fetched-at")]
+    pub fetched_at: u64,
 }
 
-// ───────────────────────────────────────────────────────────────────────────────
-
-impl RegistryCache {
-    /// Update to a given versions also updates the complete file
-    pub async fn persist(&self, key: &RegistryKey) -> Result<(), CacheError> {
-        let path_to_use: PathBuf;
-
-        // @types/node -> Should go to a separate folder @types and contain a file node
-        if key.name.contains("/") {
-            let splitted_val = key.name.split("/").collect::<Vec<&str>>();
-            let dir_to_create = self.directory.join(splitted_val[0]);
-            if !dir_to_create.exists() {
-                tokio::fs::create_dir_all(&dir_to_create).await?;
-            }
-
-            path_to_use = dir_to_create.join(format!("{}.json", splitted_val[1]));
-        } else {
-            path_to_use = self.directory.join(format!("{}.json", key.name));
+impl CachedPackument {
+    pub fn new(
+        packument: FullPackage,
+        etag: Option<String>,
+        last_modified: Option<String>,
+    ) -> Self {
+        Self {
+            packument,
+            etag,
+            last_modified,
+            fetched_at: Self::now(),
         }
+    }
 
-        let cache_file = File::create(path_to_use);
-
-        if cache_file.is_err() {
-            println!("Error for key {}", key)
-        }
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+    }
 
-        let key_to_save = self.cache.get(&key.name).unwrap();
-        serde_json::to_writer(cache_file.unwrap(), key_to_save).unwrap();
+    pub fn age(&self) -> Duration {
+        Duration::from_secs(Self::now().saturating_sub(self.fetched_at))
+    }
 
-        Ok(())
+    /// The registry answered 304, the content is current again
+    pub fn touch(&mut self) {
+        self.fetched_at = Self::now();
     }
 }
 
-// ─────────────────────────────────────────────────────────────────────────────
+// ─── RegistryCache ───────────────────────────────────────────────────────────
 
-impl Default for RegistryCache {
-    fn default() -> Self {
-        let directory = { get_config_dir(REGISTRY_CACHE_FOLDER.clone()) };
+/// Packuments by package name, one file each, e.g. @types/node -> @types/node.json
+#[derive(Debug, Clone)]
+pub struct RegistryCache {
+    pub directory: PathBuf,
+    packuments: HashMap<String, CachedPackument>,
+    /// Fetched or revalidated during this run, asking the registry again is pointless
+    validated: HashSet<String>,
+}
 
+impl RegistryCache {
+    pub fn at(directory: PathBuf) -> Self {
         Self {
             directory,
-            cache: HashMap::new(),
+            packuments: HashMap::new(),
+            validated: HashSet::new(),
         }
     }
-}
-
-// ───────────────────────────────────────────────────────────────────────────────
-
-impl RegistryCache {
-    fn load_file(&self, key: &RegistryKey) -> Result<HashMap<String, NpmPackage>, io::Error> {
-        // Not yet loaded into cache
-        let cache_file = File::open(self.directory.join(format!("{}.json", key.name))).unwrap();
-
-        // Loads complete configuration
-        let cache: HashMap<String, NpmPackage> = serde_json::from_reader(cache_file)?;
-        Ok(cache)
-    }
 
-    fn perform_preload(&mut self, key: &RegistryKey) {
-        if let Some(cache_key) = self.cache.get(&key.name) {
-            // We already have information about the package
-            if cache_key.is_empty() {
-                let loaded_key = self.load_file(key);
-                match loaded_key {
-                    Ok(loaded_key) => {
-                        self.cache.insert(key.name.clone(), loaded_key);
-                    }
-                    Err(e) => {
-                        log::error!(
-                            "Failed to load cache file for {} with {}.",
-                            key.name,
-                            e.to_string()
-                        );
-                    }
-                }
-            }
-        }
+    fn path(&self, name: &str) -> PathBuf {
+        self.directory.join(format!("{}.json", name))
     }
-}
 
-#[async_trait]
-impl PersistentCache<NpmPackage> for RegistryCache {
-    async fn init(&mut self) -> Result<(), CacheError> {
-        if !self.directory.exists() {
-            tokio::fs::create_dir_all(&self.directory)
-                .await
-                .map_err(CacheError::FileSystemError)?;
-            return Ok(());
+    /// The cached packument, read from disk on first access.
+    /// Files written by older versions, without a fetch time, are ignored.
+    pub fn get(&mut self, name: &str) -> Option<CachedPackument> {
+        if let Some(packument) = self.packuments.get(name) {
+            return Some(packument.clone());
         }
 
-        // load cache
-        let mut entries = tokio::fs::read_dir(&self.directory).await?;
-
-        while let Some(entry) = entries.next_entry().await? {
-            let file_or_dir_name = entry.file_name().to_string_lossy().to_string();
-            // There is hopefully only one / in the name @babel/core
-            if entry.file_type().await?.is_dir() {
-                // We have a directory
-                let mut sub_entries = tokio::fs::read_dir(entry.path()).await?;
-                while let Some(sub_entry) = sub_entries.next_entry().await? {
-                    let file_name = sub_entry.file_name().to_string_lossy().to_string();
-                    self.cache.insert(
-                        format!("{}/{}", file_or_dir_name, file_name.replace(".json", "")),
-                        HashMap::new(),
-                    );
-                }
+        let content = std::fs::read_to_string(self.path(name)).ok()?;
+        let packument: CachedPackument = match serde_json::from_str(&content) {
+            Ok(packument) => packument,
+            Err(e) => {
+                log::info!("Ignoring cached packument of {}: {}", name, e);
+                return None;
             }
+        };
+        self.packuments.insert(name.to_string(), packument.clone());
+        Some(packument)
+    }
 
-            self.cache.insert(
-                entry
-                    .file_name()
-                    .to_string_lossy()
-                    .replace(".json", "")
-                    .to_string(),
-                HashMap::new(),
-            );
+    pub fn is_validated(&self, name: &str) -> bool {
+        self.validated.contains(name)
+    }
+
+    pub fn set(&mut self, name: &str, packument: CachedPackument) -> Result<(), CacheError> {
+        let path = self.path(name);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
         }
+        let content = serde_json::to_vec(&packument).map_err(std::io::Error::from)?;
+        write_atomic(&path, &content)?;
 
+        self.validated.insert(name.to_string());
+        self.packuments.insert(name.to_string(), packument);
         Ok(())
     }
 
-    async fn clean(&self) -> Result<(), CacheError> {
+    pub async fn clean(&self) -> Result<(), CacheError> {
         if self.directory.exists() {
             tokio::fs::remove_dir_all(&self.directory)
                 .await
@@ -215,164 +216,70 @@ impl PersistentCache<NpmPackage> for RegistryCache {
 
         Ok(())
     }
+}
 
-    async fn get(&mut self, key: &RegistryKey) -> Option<NpmPackage> {
-        self.perform_preload(key);
-
-        log::info!("Getting key: {}", key);
-
-        // We have a range
-        let range: Range = key.version.parse().unwrap();
-        let mut selected_version: Option<NpmPackage> = None;
-        for (_, v) in self.cache.get(&key.name)?.iter() {
-            let v_package: Version = v.version.parse().unwrap();
-
-            // Continue if too new or too old
-            if !range.satisfies(&v_package) {
-                continue;
-            }
-
-            match &mut selected_version {
-                Some(sv) => {
-                    let selected_version = Version::parse(&sv.version).unwrap();
-                    if v_package > selected_version {
-                        *sv = v.clone();
-                    }
-                }
-                None => {
-                    selected_version = Some(v.clone());
-                }
-            }
-        }
-        selected_version
-    }
-
-    async fn set(&mut self, key: &RegistryKey, value: NpmPackage) -> () {
-        match self.cache.get_mut(&key.name) {
-            Some(cache) => {
-                cache.insert(key.version.clone(), value);
-            }
-            None => {
-                let mut cache = HashMap::new();
-                cache.insert(key.version.clone(), value);
-                self.cache.insert(key.name.clone(), cache);
-            }
-        }
-        self.persist(key).await.unwrap();
-    }
-
-    async fn has(&mut self, key: &RegistryKey) -> bool {
-        self.perform_preload(key);
-
-        self.cache.contains_key(&key.name)
-            && self
-                .cache
-                .get(&key.name)
-                .unwrap()
-                .contains_key(&key.version)
+impl Default for RegistryCache {
+    fn default() -> Self {
+        Self::at(get_config_dir(REGISTRY_CACHE_FOLDER.clone()))
     }
 }
 
 #[cfg(test)]
 mod tests_registry {
     use super::*;
-    use crate::cache::RegistryCache;
-    use crate::contracts::PersistentCache;
-    static UNKNOWN_PACKAGE: &str = "Test12323234234";
-
-    #[tokio::test]
-    async fn test_get_empty() {
-        let mut cache = RegistryCache::default();
-        let key = super::RegistryKey {
-            version: "1.0.0".to_string(),
-            name: "lodash".to_string(),
-        };
-        let retrieved_key = cache.get(&key).await;
-        assert_eq!(retrieved_key, None);
-    }
-
-    #[tokio::test]
-    async fn test_get_empty_head() {
-        let mut cache = RegistryCache::default();
-        let key = super::RegistryKey {
-            version: "^1.0.0".to_string(),
-            name: "lodash".to_string(),
-        };
-        let retrieved_key = cache.get(&key).await;
-        assert_eq!(retrieved_key, None);
-    }
 
-    #[tokio::test]
-    async fn test_get_empty_wildcard() {
-        let mut cache = RegistryCache::default();
-        let key = super::RegistryKey {
-            version: "*".to_string(),
-            name: "lodash".to_string(),
-        };
-        let retrieved_key = cache.get(&key).await;
-        assert_eq!(retrieved_key, None);
-    }
-
-    #[tokio::test]
-    async fn test_get_none_matching() {
-        let mut cache = RegistryCache::default();
-        let key = RegistryKey {
-            version: "1.0.0".to_string(),
-            name: UNKNOWN_PACKAGE.to_string(),
-        };
-        let inserted_key = RegistryKey {
-            version: "0.9.0".to_string(),
-            name: UNKNOWN_PACKAGE.to_string(),
-        };
-        let npm_package = NpmPackage {
-            name: inserted_key.name.to_string(),
-            version: inserted_key.version.to_string(),
-            ..Default::default()
-        };
-        cache.set(&inserted_key, npm_package).await;
-        let retrieved_key = cache.get(&key).await;
-        assert_eq!(retrieved_key, None);
+    fn packument(versions: &[&str]) -> FullPackage {
+        FullPackage {
+            versions: versions
+                .iter()
+                .map(|version| {
+                    let package = NpmPackage {
+                        name: "@scope/lib".to_string(),
+                        version: version.to_string(),
+                        ..Default::default()
+                    };
+                    (version.to_string(), package)
+                })
+                .collect(),
+            dist_tags: HashMap::from([("latest".to_string(), "1.0.0".to_string())]),
+        }
     }
 
-    #[tokio::test]
-    async fn test_get_none_matching_head() {
-        let mut cache = RegistryCache::default();
-        let key = RegistryKey {
-            version: "^1.0.0".to_string(),
-            name: UNKNOWN_PACKAGE.to_string(),
-        };
-        let inserted_key = RegistryKey {
-            version: "0.9.0".to_string(),
-            name: UNKNOWN_PACKAGE.to_string(),
-        };
-        let npm_package = NpmPackage {
-            name: inserted_key.name.to_string(),
-            version: inserted_key.version.to_string(),
-            ..Default::default()
-        };
-        cache.set(&inserted_key, npm_package).await;
-        let retrieved_key = cache.get(&key).await;
-        assert_eq!(retrieved_key, None);
+    #[test]
+    fn test_set_and_reload() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut cache = RegistryCache::at(directory.path().to_path_buf());
+        assert!(cache.get("@scope/lib").is_none());
+
+        let cached = CachedPackument::new(
+            packument(&["1.0.0", "1.1.0"]),
+            Some("\"abc\"".to_string()),
+            None,
+        );
+        cache.set("@scope/lib", cached).unwrap();
+        assert!(cache.is_validated("@scope/lib"));
+        assert!(directory.path().join("@scope/lib.json").exists());
+
+        // A new run reads the file again but has not validated it yet
+        let mut reloaded = RegistryCache::at(directory.path().to_path_buf());
+        let cached = reloaded.get("@scope/lib").unwrap();
+        assert!(!reloaded.is_validated("@scope/lib"));
+        assert_eq!(cached.etag.as_deref(), Some("\"abc\""));
+        assert_eq!(cached.packument.versions.len(), 2);
+        assert_eq!(cached.packument.dist_tags["latest"], "1.0.0");
+        assert!(cached.age() < Duration::from_secs(60));
     }
 
-    #[tokio::test]
-    async fn test_get_none_matching_wildcard() {
-        let mut cache = RegistryCache::default();
-        let key = RegistryKey {
-            version: "*".to_string(),
-            name: UNKNOWN_PACKAGE.to_string(),
-        };
-        let inserted_key = RegistryKey {
-            version: "0.9.0".to_string(),
-            name: UNKNOWN_PACKAGE.to_string(),
-        };
-        let npm_package = NpmPackage {
-            name: inserted_key.name.to_string(),
-            version: inserted_key.version.to_string(),
-            ..Default::default()
-        };
-        cache.set(&inserted_key, npm_package).await;
-        let retrieved_key = cache.get(&key).await;
-        assert!(retrieved_key.is_some());
+    #[test]
+    fn test_ignore_legacy_files() {
+        let directory = tempfile::tempdir().unwrap();
+        std::fs::write(
+            directory.path().join("lib.json"),
+            r#"{"1.0.0":{"name":"lib","version":"1.0.0"}}"#,
+        )
+        .unwrap();
+
+        let mut cache = RegistryCache::at(directory.path().to_path_buf());
+        assert!(cache.get("lib").is_none());
     }
 }
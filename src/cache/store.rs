@@ -0,0 +1,260 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+
+use super::constants::STORE_FOLDER;
+use super::RegistryKey;
+use crate::conf::PackageImportMethod;
+use crate::fs::get_config_dir;
+use crate::network::sha512_integrity;
+
+const FILES_FOLDER: &str = "files";
+const INDEX_FOLDER: &str = "index";
+const EXECUTABLE_SUFFIX: &str = "-exec";
+
+/// Distinguishes temporary files written concurrently
+static TMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Writes next to the target and renames, so readers never see a partial file
+pub(super) fn write_atomic(path: &Path, content: &[u8]) -> io::Result<()> {
+    let tmp = path.with_extension(format!(
+        "tmp-{}-{}",
+        std::process::id(),
+        TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::write(&tmp, content)?;
+    fs::rename(&tmp, path)
+}
+
+// ─── PackageIndex ────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoredFile {
+    pub integrity: String,
+    pub mode: u32,
+    pub size: u64,
+}
+
+/// Files of a package, by path relative to the package root
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PackageIndex {
+    pub files: BTreeMap<String, StoredFile>,
+}
+
+// ─── ContentStore ────────────────────────────────────────────────────────────
+
+/// Global store keeping every file once, addressed by its sha512.
+/// Packages are described by index files and imported into projects from there.
+#[derive(Debug, Clone)]
+pub struct ContentStore {
+    directory: PathBuf,
+}
+
+impl ContentStore {
+    pub fn new() -> Self {
+        Self {
+            directory: get_config_dir(STORE_FOLDER.clone()),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn at(directory: PathBuf) -> Self {
+        Self { directory }
+    }
+
+    /// files/<first two hex digits>/<rest>, executables are kept apart since links share modes
+    fn file_path(&self, file: &StoredFile) -> PathBuf {
+        let digest = file.integrity.split_once('-').map_or("", |(_, d)| d);
+        let hex = hex::encode(STANDARD.decode(digest).unwrap_or_default());
+        let (prefix, rest) = hex.split_at(hex.len().min(2));
+        let name = match Self::is_executable(file.mode) {
+            true => format!("{}{}", rest, EXECUTABLE_SUFFIX),
+            false => rest.to_string(),
+        };
+
+        self.directory.join(FILES_FOLDER).join(prefix).join(name)
+    }
+
+    fn index_path(&self, key: &RegistryKey) -> PathBuf {
+        let name = key.to_string().replace('/', "+");
+        self.directory
+            .join(INDEX_FOLDER)
+            .join(format!("{}.json", name))
+    }
+
+    fn is_executable(mode: u32) -> bool {
+        mode & 0o111 != 0
+    }
+
+    #[cfg(unix)]
+    fn mode(meta: &fs::Metadata) -> u32 {
+        use std::os::unix::fs::PermissionsExt;
+        meta.permissions().mode() & 0o777
+    }
+
+    #[cfg(not(unix))]
+    fn mode(_: &fs::Metadata) -> u32 {
+        0o644
+    }
+
+    pub fn index(&self, key: &RegistryKey) -> Option<PackageIndex> {
+        let content = fs::read_to_string(self.index_path(key)).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    /// Moves the files of an unpacked package into the store and records its index
+    pub fn add_package(&self, key: &RegistryKey, dir: &Path) -> io::Result<PackageIndex> {
+        let mut index = PackageIndex::default();
+        self.add_dir(dir, dir, &mut index)?;
+
+        let path = self.index_path(key);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        write_atomic(&path, serde_json::to_string(&index)?.as_bytes())?;
+
+        Ok(index)
+    }
+
+    fn add_dir(&self, root: &Path, dir: &Path, index: &mut PackageIndex) -> io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+
+            if file_type.is_dir() {
+                self.add_dir(root, &entry.path(), index)?;
+                continue;
+            }
+            if !file_type.is_file() {
+                continue;
+            }
+
+            let meta = entry.metadata()?;
+            let content = fs::read(entry.path())?;
+            let file = StoredFile {
+                integrity: sha512_integrity(&content),
+                mode: Self::mode(&meta),
+                size: meta.len(),
+            };
+
+            let stored = self.file_path(&file);
+            if !stored.exists() {
+                if let Some(parent) = stored.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                // Linking keeps a single copy on disk, the unpacked file becomes a view of the store
+                if fs::hard_link(entry.path(), &stored).is_err() && !stored.exists() {
+                    write_atomic(&stored, &content)?;
+                    fs::set_permissions(&stored, meta.permissions())?;
+                }
+            }
+
+            let relative = entry.path().strip_prefix(root).unwrap().to_path_buf();
+            let relative = relative.to_string_lossy().replace('\\', "/");
+            index.files.insert(relative, file);
+        }
+
+        Ok(())
+    }
+
+    /// Populates `to` with the files of a package
+    pub fn import(
+        &self,
+        index: &PackageIndex,
+        to: &Path,
+        method: &PackageImportMethod,
+    ) -> io::Result<()> {
+        fs::create_dir_all(to)?;
+
+        for (relative, file) in &index.files {
+            let target = to.join(relative);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            if fs::symlink_metadata(&target).is_ok() {
+                fs::remove_file(&target)?;
+            }
+            Self::import_file(&self.file_path(file), &target, method)?;
+        }
+
+        Ok(())
+    }
+
+    fn import_file(from: &Path, to: &Path, method: &PackageImportMethod) -> io::Result<()> {
+        match method {
+            PackageImportMethod::Auto => match fs::hard_link(from, to) {
+                Ok(()) => Ok(()),
+                // Hard links can not cross file systems
+                Err(_) => reflink_copy::reflink_or_copy(from, to).map(|_| ()),
+            },
+            PackageImportMethod::Hardlink => fs::hard_link(from, to),
+            PackageImportMethod::Clone => reflink_copy::reflink(from, to),
+            PackageImportMethod::CloneOrCopy => reflink_copy::reflink_or_copy(from, to).map(|_| ()),
+            PackageImportMethod::Copy => fs::copy(from, to).map(|_| ()),
+        }
+    }
+}
+
+impl Default for ContentStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// ─── Tests ───────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(version: &str) -> RegistryKey {
+        RegistryKey {
+            name: "@scope/lib".to_string(),
+            version: version.to_string(),
+        }
+    }
+
+    fn unpack(dir: &Path, index: &str) {
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        fs::write(dir.join("package.json"), "{}").unwrap();
+        fs::write(dir.join("lib/index.js"), index).unwrap();
+    }
+
+    #[test]
+    fn test_add_and_import() {
+        let root = tempfile::tempdir().unwrap();
+        let store = ContentStore::at(root.path().join("store"));
+
+        let first = root.path().join("first");
+        let second = root.path().join("second");
+        unpack(&first, "module.exports = 1");
+        unpack(&second, "module.exports = 2");
+
+        let index = store.add_package(&key("1.0.0"), &first).unwrap();
+        store.add_package(&key("2.0.0"), &second).unwrap();
+        assert_eq!(store.index(&key("1.0.0")), Some(index.clone()));
+        assert_eq!(index.files.len(), 2);
+
+        // package.json is identical in both versions and stored once
+        let stored = |f: &str| fs::read_dir(root.path().join("store").join(f)).unwrap();
+        let files: usize = stored(FILES_FOLDER)
+            .map(|d| fs::read_dir(d.unwrap().path()).unwrap().count())
+            .sum();
+        assert_eq!(files, 3);
+        assert_eq!(stored(INDEX_FOLDER).count(), 2);
+
+        for method in [PackageImportMethod::Hardlink, PackageImportMethod::Copy] {
+            let project = root.path().join(format!("{:?}", method));
+            store.import(&index, &project, &method).unwrap();
+            assert_eq!(
+                fs::read_to_string(project.join("lib/index.js")).unwrap(),
+                "module.exports = 1"
+            );
+        }
+    }
+}
@@ -1,5 +1,5 @@
 use clap::Parser;
-use std::{env, fs};
+use std::{collections::BTreeMap, env, fs};
 /// Command line arguments
 ///
 /// # Example
@@ -16,9 +16,43 @@ use std::{env, fs};
 pub struct Command {
     #[clap(subcommand)]
     pub command: SubCommand,
+
+    /// Override a configuration value for this run, e.g. --config registry=http://localhost:4873
+    #[arg(long = "config", global = true, value_name = "KEY=VALUE")]
+    pub config: Vec<String>,
 }
 
 impl Command {
+    /// Configuration values passed on the command line, they win over every .npmrc
+    pub fn config_overrides(&self) -> BTreeMap<String, Option<String>> {
+        let mut overrides: BTreeMap<String, Option<String>> = self
+            .config
+            .iter()
+            .map(|entry| match entry.split_once('=') {
+                Some((key, value)) => (key.trim().to_string(), Some(value.trim().to_string())),
+                None => (entry.trim().to_string(), Some("true".to_string())),
+            })
+            .collect();
+
+        let flags = match &self.command {
+            SubCommand::Install(install) => vec![
+                ("offline", install.offline),
+                ("prefer-offline", install.prefer_offline),
+                ("ignore-scripts", install.ignore_scripts),
+            ],
+            SubCommand::Run(run) => vec![
+                ("if-present", run.if_present),
+                ("ignore-scripts", run.ignore_scripts),
+            ],
+            _ => vec![],
+        };
+        for (key, _) in flags.iter().filter(|(_, set)| *set) {
+            overrides.insert(key.to_string(), Some("true".to_string()));
+        }
+
+        overrides
+    }
+
     pub fn is_install_without_args(&self) -> bool {
         if let SubCommand::Install(install) = self.command.clone() {
             return install.packages.is_none();
@@ -36,6 +70,7 @@ impl From<Install> for ProgramDesire {
             optional_install: true,
             package_json_available: false,
             craft_lock_available: false,
+            frozen_lockfile: val.frozen_lockfile,
         };
 
         // This needs to be done before all the other checks
@@ -87,6 +122,7 @@ pub struct ProgramDesire {
     pub optional_install: bool,
     pub package_json_available: bool,
     pub craft_lock_available: bool,
+    pub frozen_lockfile: bool,
 }
 
 /// Sub commands
@@ -111,6 +147,9 @@ pub enum SubCommand {
     Cache(CacheAction),
     #[clap(name = "exec")]
     Exec(Exec),
+    /// Runs a package binary from a temporary install, like `npx`
+    #[clap(name = "dlx")]
+    Dlx(Exec),
     #[clap(subcommand)]
     Config(ConfigSubCommand),
 }
@@ -122,7 +161,7 @@ pub enum ConfigSubCommand {
     #[clap(name = "get")]
     Get(ConfigGet),
     #[clap(name = "delete")]
-    Delete,
+    Delete(ConfigDelete),
     #[clap(name = "list")]
     List(ConfigList),
 }
@@ -142,11 +181,22 @@ pub struct ConfigList {
 pub struct ConfigSet {
     pub key: String,
     pub value: String,
-    #[clap(name = "--location")]
+    /// Config file to write to: project, user or global
+    #[arg(long)]
     pub location: Option<String>,
-    #[clap(name = "--json")]
+    #[arg(long, default_missing_value = "true", num_args = 0..=1)]
     pub json: Option<String>,
-    #[clap(name = "--global")]
+    #[arg(long, short, default_missing_value = "true", num_args = 0..=1)]
+    pub global: Option<String>,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct ConfigDelete {
+    pub key: String,
+    /// Config file to remove the key from: project, user or global
+    #[arg(long)]
+    pub location: Option<String>,
+    #[arg(long, short, default_missing_value = "true", num_args = 0..=1)]
     pub global: Option<String>,
 }
 
@@ -176,8 +226,15 @@ pub struct Install {
     #[arg(long)]
     pub save_dev: bool,
 
+    /// Install from the local caches only, without touching the network
     #[arg(long)]
     pub offline: bool,
+    /// Use cached metadata whenever it satisfies the range
+    #[arg(long)]
+    pub prefer_offline: bool,
+    /// Skip the lifecycle scripts of the project and its dependencies
+    #[arg(long)]
+    pub ignore_scripts: bool,
     #[arg(long)]
     pub prod: bool,
     #[arg(long)]
@@ -186,6 +243,9 @@ pub struct Install {
     pub no_optional: bool,
     #[arg(long)]
     pub no_peers: bool,
+    /// Install exactly what craft-lock.yaml describes and fail if it is outdated
+    #[arg(long)]
+    pub frozen_lockfile: bool,
     /// Save as production dependency
     #[arg(long)]
     pub save_prod: bool,
@@ -205,10 +265,22 @@ pub struct Run {
     pub directory: Option<String>,
     #[arg(required = true, name = "--script", index = 1)]
     pub script: String,
+    /// Succeed without running anything when the script is missing
+    #[arg(long)]
+    pub if_present: bool,
+    /// Skip the pre and post scripts
+    #[arg(long)]
+    pub ignore_scripts: bool,
+    /// Arguments passed on to the script, after --
+    #[arg(last = true, index = 3)]
+    pub args: Vec<String>,
 }
 
 #[derive(clap::Args, Debug, Clone)]
 pub struct Exec {
+    /// Package to install into a temporary prefix before running the command
+    #[arg(long = "package", short = 'p')]
+    pub packages: Vec<String>,
     #[arg(required = true, index = 1)]
     pub command: String,
     #[arg(
@@ -1,4 +1,8 @@
-pub const AUTH: &str = "_auth";
+pub(crate) const AUTH: &str = "_auth";
+pub(crate) const AUTH_TOKEN: &str = "_authToken";
+pub const ALWAYS_AUTH: &str = "always-auth";
+pub(crate) const USERNAME: &str = "username";
+pub(crate) const PASSWORD: &str = "_password";
 pub const ACCESS: &str = "access";
 pub const ALL: &str = "all";
 pub const ALLOW_SAME_VERSION: &str = "allow-same-version";
@@ -72,8 +76,10 @@ pub const LOGS_MAX: &str = "logs-max";
 pub const LONG: &str = "long";
 pub const MAX_SOCKETS: &str = "maxsockets";
 pub const MESSAGE: &str = "message";
+pub const NODE_LINKER: &str = "node-linker";
 pub const NODE_OPTIONS: &str = "node-options";
 pub const NO_PROXY: &str = "no-proxy";
+pub const NOPROXY: &str = "noproxy";
 pub const OFFLINE: &str = "offline";
 pub const OMIT: &str = "omit";
 pub const OMIT_LOCKFILE_REGISTRY_RESOLVED: &str = "omit-lockfile-registry-resolved";
@@ -81,6 +87,7 @@ pub const OS: &str = "os";
 pub const OTP: &str = "otp";
 pub const PACK_DESTINATION: &str = "pack-destination";
 pub const PACKAGE: &str = "package";
+pub const PACKAGE_IMPORT_METHOD: &str = "package-import-method";
 pub const PACKAGE_LOCK: &str = "package-lock";
 pub const PACKAGE_LOCK_ONLY: &str = "package-lock-only";
 pub const PARSEABLE: &str = "parseable";
@@ -97,6 +104,7 @@ pub const READ_ONLY: &str = "read-only";
 pub const REBUILD_BUNDLE: &str = "rebuild-bundle";
 pub const REGISTRY: &str = "registry";
 pub const REPLACE_REGISTRY_HOST: &str = "replace-registry-host";
+pub const SCOPED_REGISTRY_SUFFIX: &str = ":registry";
 pub const SAVE: &str = "save";
 pub const SAVE_BUNDLE: &str = "save-bundle";
 pub const SAVE_DEV: &str = "save-dev";
@@ -138,4 +146,5 @@ pub const YES: &str = "yes";
 pub const ALSO: &str = "also";
 pub const CACHE_MAX: &str = "cache-max";
 pub const CACHE_MIN: &str = "cache-min";
-pub const CERT: &str = "CERT";
+pub const CERT: &str = "cert";
+pub const KEY: &str = "key";
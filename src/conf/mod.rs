@@ -1,3 +1,4 @@
-mod constants;
+pub(crate) mod constants;
 mod npm_conf;
-pub use npm_conf::NpmConfig;
+mod rc_file;
+pub use npm_conf::{InstallStrategy, Location, NpmConfig, PackageImportMethod};
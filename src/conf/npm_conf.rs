@@ -1,33 +1,36 @@
 #[allow(clippy::all)]
 use crate::conf::constants::*;
+use crate::conf::rc_file::RcFile;
 use crate::errors::ExecutionError;
-use crate::pipeline::{determine_global_config_file_location, parse_config};
+use crate::network::Credentials;
+use crate::pipeline::config_file_location;
 use chrono::NaiveDate;
 use std::cmp::PartialEq;
 use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
 use std::string::ToString;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Access {
     Public,
     Restricted,
     Null,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum AuthType {
     Web,
     Legacy,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[allow(dead_code)]
 pub enum Depth {
     StringVal(String),
     IntVal(i32),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Include {
     Dev,
     Optional,
@@ -35,9 +38,13 @@ pub enum Include {
     Peer,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct NpmConfig {
-    pub _auth: Option<String>,
+    /// Top level `_auth`, `_authToken`, `username` and `_password`
+    pub default_credentials: Credentials,
+    /// Credentials configured per registry through `//host/path/:_authToken=<token>`
+    pub registry_credentials: BTreeMap<String, Credentials>,
+    pub always_auth: bool,
     pub access: Access,
     pub all: bool,
     pub allow_same_version: bool,
@@ -120,6 +127,7 @@ pub struct NpmConfig {
     pub otp: Option<String>,
     pub pack_destination: Option<String>,
     pub package: String,
+    pub package_import_method: PackageImportMethod,
     pub package_lock: bool,
     pub package_lock_only: bool,
     pub parseable: bool,
@@ -136,6 +144,8 @@ pub struct NpmConfig {
     pub rebuild_bundle: bool,
     pub registry: String,
     pub replace_registry_host: String,
+    /// Registries configured per scope through `@scope:registry=<url>`
+    pub scoped_registries: BTreeMap<String, String>,
     pub save: bool,
     pub save_bundle: bool,
     pub save_dev: bool,
@@ -179,16 +189,40 @@ pub struct NpmConfig {
     pub cache_min: i32,
     pub cert: Option<String>,
     pub _key: Option<String>,
+    /// Every explicitly configured key, with the layer that won
+    pub entries: BTreeMap<String, ConfigEntry>,
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone, Copy)]
 pub enum Location {
-    User,
     Global,
+    User,
     Project,
+    Env,
+    Cli,
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Location::Global => "global",
+            Location::User => "user",
+            Location::Project => "project",
+            Location::Env => "env",
+            Location::Cli => "cli",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A raw configuration value together with the layer it was read from
+#[derive(Debug, Clone)]
+pub struct ConfigEntry {
+    pub value: Option<String>,
+    pub location: Location,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum InstallStrategy {
     Hoisted,
     Nested,
@@ -196,7 +230,18 @@ pub enum InstallStrategy {
     Linked,
 }
 
-#[derive(Debug)]
+/// How packages are brought from the content store into node_modules
+#[derive(Debug, Clone, PartialEq)]
+pub enum PackageImportMethod {
+    /// Hard links, falling back to reflinks or copies across file systems
+    Auto,
+    Hardlink,
+    Clone,
+    CloneOrCopy,
+    Copy,
+}
+
+#[derive(Debug, Clone)]
 pub enum LogLevel {
     Silent,
     Error,
@@ -211,6 +256,27 @@ pub enum LogLevel {
     None,
 }
 
+/// Replaces `${VAR}` references with the value of the environment variable, like npm does
+/// for every key and value of a configuration layer
+fn expand_env(value: &str) -> String {
+    let mut expanded = String::new();
+    let mut rest = value;
+
+    while let Some(start) = rest.find("${") {
+        let Some(end) = rest[start..].find('}') else {
+            break;
+        };
+        let name = &rest[start + 2..start + end];
+
+        expanded.push_str(&rest[..start]);
+        expanded.push_str(&std::env::var(name).unwrap_or_default());
+        rest = &rest[start + end + 1..];
+    }
+
+    expanded.push_str(rest);
+    expanded
+}
+
 fn create_omit() -> Option<String> {
     if let Ok(e) = std::env::var("NODE_ENV") {
         if e == "production" {
@@ -331,7 +397,9 @@ impl NpmConfig {
     // https://docs.npmjs.com/cli/v10/using-npm/config
     pub fn new(conf: BTreeMap<String, Option<String>>) -> Self {
         let npm_config_defaults = NpmConfig {
-            _auth: None,
+            default_credentials: Credentials::default(),
+            registry_credentials: BTreeMap::new(),
+            always_auth: false,
             access: Access::Public,
             all: false,
             allow_same_version: false,
@@ -414,6 +482,7 @@ impl NpmConfig {
             otp: None,
             pack_destination: Some(".".into()),
             package: "".to_string(),
+            package_import_method: PackageImportMethod::Auto,
             package_lock: true,
             package_lock_only: false,
             parseable: false,
@@ -430,6 +499,7 @@ impl NpmConfig {
             rebuild_bundle: true,
             registry: "https://registry.npmjs.org/".to_string(),
             replace_registry_host: "npmjs".to_string(),
+            scoped_registries: BTreeMap::new(),
             save: true,
             save_bundle: false,
             save_dev: false,
@@ -473,13 +543,13 @@ impl NpmConfig {
             cache_min: 0,
             cert: None,
             _key: None,
+            entries: BTreeMap::new(),
         };
 
         let mut conf_struct = npm_config_defaults;
 
         Self::determine_config(&mut conf_struct, conf);
 
-        println!("{:?}", conf_struct);
         conf_struct
     }
 
@@ -488,8 +558,6 @@ impl NpmConfig {
             return default_value;
         }
 
-        println!("{:?}", value.clone().unwrap().as_str());
-
         match value.clone().unwrap().as_str() {
             "true" => true,
             "false" => false,
@@ -521,6 +589,19 @@ impl NpmConfig {
         value.clone().unwrap()
     }
 
+    /// Applies a configuration layer on top of the current values
+    pub fn apply(&mut self, conf: BTreeMap<String, Option<String>>, location: Location) {
+        for (key, value) in conf {
+            if key.is_empty() || value.is_none() {
+                continue;
+            }
+            let key = expand_env(&key);
+            let value = value.map(|v| expand_env(&v));
+            Self::handle_key_processing(self, key.clone(), &value);
+            self.entries.insert(key, ConfigEntry { value, location });
+        }
+    }
+
     fn determine_config(conf_struct: &mut NpmConfig, conf: BTreeMap<String, Option<String>>) {
         for (key, value) in conf {
             if value.is_none() {
@@ -532,8 +613,13 @@ impl NpmConfig {
 
     fn handle_key_processing(conf_struct: &mut NpmConfig, key: String, value: &Option<String>) {
         match key.as_str() {
-            AUTH => {
-                conf_struct._auth = Self::parse_string(&conf_struct._auth, value);
+            AUTH | AUTH_TOKEN | USERNAME | PASSWORD => {
+                if let Some(v) = value {
+                    conf_struct.default_credentials.set(&key, v);
+                }
+            }
+            ALWAYS_AUTH => {
+                conf_struct.always_auth = Self::parse_bool(conf_struct.always_auth, value);
             }
             ACCESS => {
                 if let Some(v) = value {
@@ -855,10 +941,21 @@ impl NpmConfig {
             MESSAGE => {
                 conf_struct.message = Self::parse_set_string(&conf_struct.message, value);
             }
+            NODE_LINKER => {
+                if let Some(v) = value {
+                    match v.as_str() {
+                        "hoisted" => conf_struct.install_strategy = InstallStrategy::Hoisted,
+                        "isolated" | "pnpm" => {
+                            conf_struct.install_strategy = InstallStrategy::Linked
+                        }
+                        _ => {}
+                    }
+                }
+            }
             NODE_OPTIONS => {
                 conf_struct.node_options = Self::parse_string(&conf_struct.node_options, value);
             }
-            NO_PROXY => {
+            NO_PROXY | NOPROXY => {
                 conf_struct.no_proxy = Self::parse_string(&conf_struct.no_proxy, value);
             }
             OFFLINE => {
@@ -884,6 +981,22 @@ impl NpmConfig {
             PACKAGE => {
                 conf_struct.package = Self::parse_set_string(&conf_struct.package, value);
             }
+            PACKAGE_IMPORT_METHOD => {
+                if let Some(v) = value {
+                    match v.as_str() {
+                        "auto" => conf_struct.package_import_method = PackageImportMethod::Auto,
+                        "hardlink" => {
+                            conf_struct.package_import_method = PackageImportMethod::Hardlink
+                        }
+                        "clone" => conf_struct.package_import_method = PackageImportMethod::Clone,
+                        "clone-or-copy" => {
+                            conf_struct.package_import_method = PackageImportMethod::CloneOrCopy
+                        }
+                        "copy" => conf_struct.package_import_method = PackageImportMethod::Copy,
+                        _ => {}
+                    }
+                }
+            }
             PACKAGE_LOCK => {
                 conf_struct.package_lock = Self::parse_bool(conf_struct.package_lock, value);
             }
@@ -1088,17 +1201,52 @@ impl NpmConfig {
             CERT => {
                 conf_struct.cert = Self::parse_string(&conf_struct.cert, value);
             }
+            KEY => {
+                conf_struct._key = Self::parse_string(&conf_struct._key, value);
+            }
+            scoped if scoped.starts_with('@') && scoped.ends_with(SCOPED_REGISTRY_SUFFIX) => {
+                if let Some(url) = value {
+                    let scope = scoped.trim_end_matches(SCOPED_REGISTRY_SUFFIX);
+                    conf_struct
+                        .scoped_registries
+                        .insert(scope.to_string(), url.clone());
+                }
+            }
+            nerfed if nerfed.starts_with("//") => {
+                if let (Some((prefix, field)), Some(v)) = (nerfed.rsplit_once(':'), value) {
+                    let credentials = conf_struct
+                        .registry_credentials
+                        .entry(prefix.to_string())
+                        .or_default();
+                    if !credentials.set(field, v) {
+                        log::debug!("Unknown key: {}", key);
+                    }
+                }
+            }
             _ => {
                 log::debug!("Unknown key: {}", key);
             }
         }
     }
 
+    /// Registry a package is fetched from, honouring `@scope:registry=` entries
+    pub fn registry_for(&self, package_name: &str) -> &str {
+        let registry = package_name
+            .split_once('/')
+            .filter(|(scope, _)| scope.starts_with('@'))
+            .and_then(|(scope, _)| self.scoped_registries.get(scope));
+
+        registry.unwrap_or(&self.registry).trim_end_matches('/')
+    }
+
     pub fn switch_global(&mut self, global_val: Option<String>) {
         if global_val.is_none() {
             return;
         }
         self.global = Self::parse_bool(false, &global_val);
+        if self.global {
+            self._location = Location::Global;
+        }
     }
 
     pub fn switch_location(&mut self, location_val: Option<String>) {
@@ -1113,22 +1261,25 @@ impl NpmConfig {
         }
     }
 
-    fn serialize_config(map: BTreeMap<String, Option<String>>) -> String {
-        let mut serialized = String::new();
-        for (key, value) in map {
-            if key.is_empty() {
-                continue;
-            }
-            match value {
-                Some(v) => {
-                    serialized.push_str(&format!("{}={}\n", key, v));
-                }
-                None => {
-                    serialized.push_str(&format!("{}=\n", key));
-                }
-            }
+    /// Edits the config file of the selected location in place
+    fn edit_config_file(&self, edit: impl FnOnce(&mut RcFile)) -> Result<(), ExecutionError> {
+        let Some(conf_file) = config_file_location(&self._location) else {
+            return Err(ExecutionError::ConfigError(format!(
+                "The {} location has no config file",
+                self._location
+            )));
+        };
+
+        let conf_content = std::fs::read_to_string(&conf_file).unwrap_or_default();
+        let mut rc_file = RcFile::new(&conf_content);
+        edit(&mut rc_file);
+
+        if let Some(parent) = conf_file.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| ExecutionError::ConfigError(e.to_string()))?;
         }
-        serialized
+        std::fs::write(conf_file, rc_file.to_string())
+            .map_err(|e| ExecutionError::ConfigError(e.to_string()))
     }
 
     pub fn set_value(&mut self, key: &str, value: Option<String>) -> Result<(), ExecutionError> {
@@ -1137,72 +1288,95 @@ impl NpmConfig {
             self.global = true;
         }
 
-        if self._location == Location::User {
-            let conf_file = determine_global_config_file_location();
-            let conf_content = std::fs::read_to_string(&conf_file).unwrap();
-            let mut read_conf = parse_config(conf_content);
+        self.edit_config_file(|rc_file| rc_file.set(key, value.as_deref().unwrap_or_default()))
+    }
+
+    pub fn delete_value(&mut self, key: &str) -> Result<(), ExecutionError> {
+        let mut deleted = false;
+        self.edit_config_file(|rc_file| deleted = rc_file.delete(key))?;
 
-            match value {
-                Some(v) => {
-                    read_conf.insert(key.to_string(), Some(v));
-                }
-                None => {
-                    read_conf.insert(key.to_string(), None);
-                }
-            }
-            let serialized_config = Self::serialize_config(read_conf);
-            std::fs::write(conf_file, serialized_config).unwrap();
+        if !deleted {
+            log::info!("{} is not set in the {} config", key, self._location);
         }
         Ok(())
     }
 
     pub fn get_value(&mut self, key: String) -> Result<(), ExecutionError> {
-        if self._location == Location::User {
-            let read_conf_string =
-                std::fs::read_to_string(determine_global_config_file_location()).unwrap();
-            let conf = parse_config(read_conf_string);
-            let retrieved_kv = conf.get(&key);
-            match retrieved_kv {
-                Some(v) => {
-                    println!("{}", v.clone().unwrap());
-                }
-                None => {
-                    println!("undefined");
-                }
+        match self.entries.get(&key) {
+            Some(entry) => {
+                let value = Self::protect_value(&key, entry.value.clone());
+                println!("{}", value.unwrap_or_default());
+            }
+            None => {
+                println!("undefined");
             }
         }
         Ok(())
     }
 
     pub fn list_value(&self) -> Result<(), ExecutionError> {
-        if self._location == Location::User {
-            let read_conf_string =
-                std::fs::read_to_string(determine_global_config_file_location()).unwrap();
-            let conf = parse_config(read_conf_string);
+        if self.json {
+            let conf = self
+                .entries
+                .iter()
+                .map(|(key, entry)| (key, Self::protect_value(key, entry.value.clone())))
+                .collect::<BTreeMap<_, _>>();
+            let serialized_json = serde_json::to_string_pretty(&conf).unwrap();
+            println!("{}", serialized_json);
+            return Ok(());
+        }
 
-            if self.json {
-                let serialized_json = serde_json::to_string_pretty(&conf).unwrap();
-                println!("{}", serialized_json);
-                return Ok(());
+        for location in [
+            Location::Global,
+            Location::User,
+            Location::Project,
+            Location::Env,
+            Location::Cli,
+        ] {
+            let entries = self
+                .entries
+                .iter()
+                .filter(|(_, entry)| entry.location == location)
+                .collect::<Vec<_>>();
+            if entries.is_empty() {
+                continue;
             }
 
-            for (key, value) in conf {
-                if key.is_empty() {
-                    continue;
-                }
-                match value {
-                    Some(v) => {
-                        println!("{}={}", key, v);
-                    }
-                    None => {
-                        println!("{}=", key);
-                    }
-                }
+            match config_file_location(&location) {
+                Some(file) => println!("; \"{}\" config from {}", location, file.display()),
+                None => println!("; \"{}\" config", location),
             }
+            for (key, entry) in entries {
+                let value = Self::protect_value(key, entry.value.clone());
+                println!("{}={}", key, value.unwrap_or_default());
+            }
+            println!();
         }
         Ok(())
     }
 
+    /// Hides tokens and passwords from `config get` and `config list`
+    fn protect_value(key: &str, value: Option<String>) -> Option<String> {
+        let field = key.rsplit(':').next().unwrap_or(key);
+        match field {
+            AUTH | AUTH_TOKEN | PASSWORD => value.map(|_| "(protected)".to_string()),
+            _ => value,
+        }
+    }
+
+    /// Configured values scripts see as `npm_config_*`. Credentials, the client key,
+    /// `//host/:` registry settings and proxies with a password are left out.
+    pub fn script_entries(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.entries.iter().filter_map(|(key, entry)| {
+            let value = entry.value.as_ref()?;
+            let secret = key.starts_with('_')
+                || key.starts_with("//")
+                || [USERNAME, KEY].contains(&key.as_str())
+                || ([PROXY, HTTPS_PROXY].contains(&key.as_str()) && value.contains('@'));
+            (!secret).then_some((key, value))
+        })
+    }
+
     pub fn switch_json(&mut self, json_val: Option<bool>) {
         if json_val.is_none() {
             return;
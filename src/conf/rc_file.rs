@@ -0,0 +1,100 @@
+use std::fmt::{Display, Formatter};
+
+// ─── RcFile ──────────────────────────────────────────────────────────────────
+
+/// Line based editor for `.npmrc` style files.
+/// Only the edited key is touched, comments and unrelated lines are kept as they are.
+pub struct RcFile {
+    lines: Vec<String>,
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+
+impl RcFile {
+    pub fn new(content: &str) -> Self {
+        Self {
+            lines: content.lines().map(|l| l.to_string()).collect(),
+        }
+    }
+
+    fn key_of(line: &str) -> Option<&str> {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with(';') {
+            return None;
+        }
+
+        let key = trimmed.split_once('=').map(|(k, _)| k).unwrap_or(trimmed);
+        Some(key.trim())
+    }
+
+    /// Replaces the value of `key`, or appends it when the file does not have it yet
+    pub fn set(&mut self, key: &str, value: &str) {
+        let entry = format!("{}={}", key, value);
+        let mut replaced = false;
+
+        self.lines.retain_mut(|line| {
+            if Self::key_of(line) != Some(key) {
+                return true;
+            }
+            if replaced {
+                return false;
+            }
+            *line = entry.clone();
+            replaced = true;
+            true
+        });
+
+        if !replaced {
+            self.lines.push(entry);
+        }
+    }
+
+    /// Removes every line setting `key`, returns whether something was removed
+    pub fn delete(&mut self, key: &str) -> bool {
+        let before = self.lines.len();
+        self.lines.retain(|line| Self::key_of(line) != Some(key));
+        before != self.lines.len()
+    }
+}
+
+impl Display for RcFile {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.lines
+            .iter()
+            .try_for_each(|line| writeln!(f, "{}", line))
+    }
+}
+
+// ─── Tests ───────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTENT: &str =
+        "# team settings\nregistry=http://mirror\n\n; scoped\n@ourco:registry=http://ourco\n";
+
+    #[test]
+    fn test_set_keeps_comments() {
+        let mut rc = RcFile::new(CONTENT);
+        rc.set("registry", "http://other");
+        rc.set("strict-ssl", "false");
+
+        assert_eq!(
+            rc.to_string(),
+            "# team settings\nregistry=http://other\n\n; scoped\n@ourco:registry=http://ourco\nstrict-ssl=false\n"
+        );
+    }
+
+    #[test]
+    fn test_delete() {
+        let mut rc = RcFile::new(CONTENT);
+
+        assert!(rc.delete("@ourco:registry"));
+        assert!(!rc.delete("missing"));
+        assert_eq!(
+            rc.to_string(),
+            "# team settings\nregistry=http://mirror\n\n; scoped\n"
+        );
+    }
+}
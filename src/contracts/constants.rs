@@ -1,3 +1,2 @@
 pub const CRAFT_VERBOSE_LOGGING: &str = "CRAFT_VERBOSE";
-#[allow(dead_code)]
 pub const LOCK_FILE_NAME: &str = "craft-lock.yaml";
@@ -11,7 +11,7 @@ mod registry;
 pub use cache::PersistentCache;
 
 pub use actor::Actor;
-pub use constants::CRAFT_VERBOSE_LOGGING;
+pub use constants::{CRAFT_VERBOSE_LOGGING, LOCK_FILE_NAME};
 pub use lockfile::Lockfile;
 pub use logger::Logger;
 pub use pipe::Pipe;
@@ -6,6 +6,7 @@ pub enum Phase {
     Downloading,
     Extracting,
     Linking,
+    Building,
 }
 
 #[derive(Debug)]
@@ -10,6 +10,24 @@ pub enum ExecutionError {
     ScriptNotFound(String),
     #[error("Failed to find a script in node_modules/.bin")]
     NoScriptsFound,
-    #[error("Failed to parse .npmrc file")]
+    #[error("Invalid configuration: {0}")]
     ConfigError(String),
+    #[error("Cannot install with frozen lockfile: {0}")]
+    FrozenLockfile(String),
+    #[error("{0} {1} script failed: {2}")]
+    ScriptFailed(String, String, String),
+    #[error("Missing from the offline cache: {}", .0.join(", "))]
+    Offline(Vec<String>),
+    #[error("{0} script exited with code {1}")]
+    ScriptExited(String, i32),
+}
+
+impl ExecutionError {
+    /// The exit code of craft for this error, scripts hand on their own
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ExecutionError::ScriptExited(_, code) => *code,
+            _ => 1,
+        }
+    }
 }
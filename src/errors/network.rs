@@ -10,6 +10,16 @@ pub enum NetworkError {
 
     #[error("Failed to fetch version {0}")]
     FailedToFetchVersion(String),
-    #[error("Checksum mismatch while downloading {0}")]
-    CheckSum(String),
+    #[error("Integrity check failed for {0}: expected {1}, got {2}")]
+    Integrity(String, String, String),
+    #[error("Failed to read local package {0}: {1}")]
+    LocalPackage(String, String),
+    #[error("{0} is not available offline")]
+    Offline(String),
+    #[error("Failed to configure the HTTP client: {0}")]
+    Client(String),
+    #[error("git {0} failed: {1}")]
+    Git(String, String),
+    #[error("Resolving a dependency failed: {0}")]
+    Task(#[from] tokio::task::JoinError),
 }
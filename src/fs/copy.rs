@@ -1,42 +1,57 @@
-use fs_extra::dir::CopyOptions;
 use std::fs;
 use std::path::Path;
 
-pub fn copy_dir(from: &Path, to: &Path) -> Result<(), Box<dyn std::error::Error>> {
-    let mut from = from.to_path_buf();
-    if to.exists() {
-        fs::remove_dir_all(to).unwrap();
-    }
-
-    if !from.exists() {
-        from = from.join("..");
-    }
+/// Recreates a directory with hard links to the files of `from`, copying them when the
+/// file system can not link, e.g. across devices. Symlinks are left out, registry tarballs
+/// have none and links made by the other layouts must not leak into a copy.
+pub fn hardlink_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(to)?;
 
-    fs::create_dir_all(to).unwrap();
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let target = to.join(entry.file_name());
 
-    let options = CopyOptions::new().overwrite(true);
+        if file_type.is_dir() {
+            hardlink_dir(&entry.path(), &target)?;
+        } else if file_type.is_file() && fs::hard_link(entry.path(), &target).is_err() {
+            fs::copy(entry.path(), &target)?;
+        }
+    }
 
-    copy_recursive(from.as_path(), to, &options)
+    Ok(())
 }
 
-fn copy_recursive(
-    from: &Path,
-    to: &Path,
-    options: &CopyOptions,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let from_meta = fs::metadata(from)?;
-
-    if options.overwrite && to.exists() {
-        fs::remove_dir_all(to)?;
-    }
+/// Gives every hard linked file below `dir` its own copy, so that writing into a package
+/// does not change the content store. Nested node_modules belong to other packages.
+pub fn detach_dir(dir: &Path) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
 
-    if from_meta.is_dir() {
-        symlink_dir(from, to)?;
+        if file_type.is_dir() && entry.file_name() != "node_modules" {
+            detach_dir(&entry.path())?;
+        } else if file_type.is_file() && is_shared(&entry.metadata()?) {
+            let tmp = entry.path().with_extension("craft-detach");
+            fs::copy(entry.path(), &tmp)?;
+            fs::rename(&tmp, entry.path())?;
+        }
     }
 
     Ok(())
 }
 
+#[cfg(unix)]
+fn is_shared(meta: &fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    meta.nlink() > 1
+}
+
+#[cfg(not(unix))]
+fn is_shared(_: &fs::Metadata) -> bool {
+    true
+}
+
 #[cfg(unix)]
 pub fn symlink_dir<P: AsRef<Path>, U: AsRef<Path>>(from: P, to: U) -> std::io::Result<()> {
     std::os::unix::fs::symlink(from, to)?;
@@ -1,5 +1,5 @@
 mod copy;
 mod file_config;
 
-pub use copy::copy_dir;
+pub use copy::{detach_dir, hardlink_dir, symlink_dir};
 pub use file_config::get_config_dir;
@@ -6,6 +6,10 @@ pub const AUTO_INSTALL_PEERS: &str = "autoInstallPeers";
 pub const EXCLUDE_LINKS_FROM_LOCKFILE: &str = "excludeLinksFromLockfile";
 pub const PEER_SUFFIX_MAX_LENGTH: &str = "peerSuffixMaxLength";
 
+pub const OVERRIDES: &str = "overrides";
+pub const ONLY_BUILT_DEPENDENCIES: &str = "onlyBuiltDependencies";
+pub const NEVER_BUILT_DEPENDENCIES: &str = "neverBuiltDependencies";
+
 // Importers dependencies
 pub const SPECIFIER: &str = "specifier";
 pub const VERSION: &str = "version";
@@ -13,7 +17,7 @@ pub const VERSION: &str = "version";
 pub const DEPENDENCIES: &str = "dependencies";
 pub const DEV_DEPENDENCIES: &str = "devDependencies";
 pub const PEER_DEPENDENCIES: &str = "peerDependencies";
-pub const OPT_DEPENDENCIES: &str = "optDependencies";
+pub const OPT_DEPENDENCIES: &str = "optionalDependencies";
 pub const PEER_DEPENDENCIES_META: &str = "peerDependenciesMeta";
 
 // packages
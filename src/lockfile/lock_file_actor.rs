@@ -1,11 +1,11 @@
-use crate::actors::PackageType;
-use crate::contracts::Lockfile;
+use crate::actors::{PackageType, PreprocessDependencyInstall};
+use crate::contracts::{Lockfile, LOCK_FILE_NAME};
 use crate::errors::LockfileError;
 use crate::lockfile::constants::CURRENT_IMPORTER;
 use crate::lockfile::lockfile_structure::{
-    ImporterSections, LockfileStructure, ResolvedDependency,
+    ImporterSections, LockfileStructure, ResolvedDependencies, ResolvedDependency,
 };
-use crate::package::{PackageMetaHandler, PackageRecorder};
+use crate::package::{Overrides, PackageJson, PackageMetaHandler, PackageRecorder};
 use crate::pipeline::ResolvedItem;
 use std::collections::HashMap;
 use std::fs;
@@ -14,25 +14,43 @@ use std::path::Path;
 pub struct LockFileActor {
     resolved_items: Vec<ResolvedItem>,
     recorder: PackageRecorder,
+    /// Drop importer entries that are no longer listed in package.json
+    prune: bool,
 }
 
 impl LockFileActor {
     pub(crate) fn new(
         resolved_items: Vec<ResolvedItem>,
         recorder: PackageRecorder,
+        prune: bool,
     ) -> LockFileActor {
         LockFileActor {
             resolved_items,
             recorder,
+            prune,
         }
     }
 
     fn persist_lockfile_structure(content: &str) -> Result<(), LockfileError> {
-        fs::write("craft-lock.yaml", content)
+        fs::write(LOCK_FILE_NAME, content)
             .map_err(|e| LockfileError::FileWriteError(e.to_string()))?;
         Ok(())
     }
 
+    /// Aliased dependencies are listed under the alias and lock `<real name>@<version>`
+    fn importer_entry(item: &ResolvedItem) -> (String, ResolvedDependency) {
+        let (name, version) = match item.alias() {
+            Some(alias) => (alias, item.package.to_string()),
+            None => (item.package.name.clone(), item.package.version.clone()),
+        };
+
+        let dependency = ResolvedDependency {
+            version,
+            specifier: item.specifier.clone(),
+        };
+        (name, dependency)
+    }
+
     fn create_importers(
         packages: Vec<ResolvedItem>,
         map: Option<&ImporterSections>,
@@ -41,92 +59,45 @@ impl LockFileActor {
 
         packages.iter().for_each(|item| {
             if item.parent.is_none() {
+                let (name, dependency) = Self::importer_entry(item);
                 match item.package_type {
                     PackageType::Dev(_) => match &mut map_to_use.dev_dependencies {
                         Some(ref mut dev_d) => {
-                            dev_d.insert(
-                                item.package.name.clone(),
-                                ResolvedDependency {
-                                    version: item.package.version.clone(),
-                                    specifier: item.specifier.clone(),
-                                },
-                            );
+                            dev_d.insert(name.clone(), dependency.clone());
                         }
                         None => {
                             let mut dev_deps = HashMap::new();
-                            dev_deps.insert(
-                                item.package.name.clone(),
-                                ResolvedDependency {
-                                    version: item.package.version.clone(),
-                                    specifier: item.specifier.clone(),
-                                },
-                            );
+                            dev_deps.insert(name.clone(), dependency.clone());
                             map_to_use.dev_dependencies = Some(dev_deps)
                         }
                     },
                     PackageType::Optional(_) => match map_to_use.optional_dependencies {
                         Some(ref mut opt_d) => {
-                            opt_d.insert(
-                                item.package.name.clone(),
-                                ResolvedDependency {
-                                    version: item.package.version.clone(),
-                                    specifier: item.specifier.clone(),
-                                },
-                            );
+                            opt_d.insert(name.clone(), dependency.clone());
                         }
                         None => {
                             let mut dev_deps = HashMap::new();
-                            dev_deps.insert(
-                                item.package.name.clone(),
-                                ResolvedDependency {
-                                    version: item.package.version.clone(),
-                                    specifier: item.specifier.clone(),
-                                },
-                            );
+                            dev_deps.insert(name.clone(), dependency.clone());
                             map_to_use.optional_dependencies = Some(dev_deps)
                         }
                     },
                     PackageType::Prod(_) => match map_to_use.dependencies {
                         Some(ref mut opt_d) => {
-                            opt_d.insert(
-                                item.package.name.clone(),
-                                ResolvedDependency {
-                                    version: item.package.version.clone(),
-                                    specifier: item.specifier.clone(),
-                                },
-                            );
+                            opt_d.insert(name.clone(), dependency.clone());
                         }
                         None => {
                             let mut prod_deps = HashMap::new();
-                            prod_deps.insert(
-                                item.package.name.clone(),
-                                ResolvedDependency {
-                                    version: item.package.version.clone(),
-                                    specifier: item.specifier.clone(),
-                                },
-                            );
+                            prod_deps.insert(name.clone(), dependency.clone());
                             map_to_use.dependencies = Some(prod_deps)
                         }
                     },
                     PackageType::Peer(_) => match map_to_use.peer_dependencies {
                         Some(ref mut opt_d) => {
-                            opt_d.insert(
-                                item.package.name.clone(),
-                                ResolvedDependency {
-                                    version: item.package.version.clone(),
-                                    specifier: item.specifier.clone(),
-                                },
-                            );
+                            opt_d.insert(name.clone(), dependency.clone());
                         }
                         None => {
                             let mut prod_deps = HashMap::new();
-                            prod_deps.insert(
-                                item.package.name.clone(),
-                                ResolvedDependency {
-                                    version: item.package.version.clone(),
-                                    specifier: item.specifier.clone(),
-                                },
-                            );
+                            prod_deps.insert(name.clone(), dependency.clone());
                             map_to_use.peer_dependencies = Some(prod_deps)
                         }
                     },
@@ -137,6 +108,31 @@ impl LockFileActor {
         map_to_use
     }
 
+    fn retain_listed(
+        locked: &mut Option<ResolvedDependencies>,
+        listed: Option<&HashMap<String, String>>,
+    ) {
+        if let Some(deps) = locked {
+            deps.retain(|name, _| listed.is_some_and(|l| l.contains_key(name)));
+        }
+    }
+
+    /// Removes the importer entries that package.json does not list anymore
+    fn prune_importer(importer: &mut ImporterSections, package_json: &PackageJson) {
+        Self::retain_listed(
+            &mut importer.dependencies,
+            package_json.dependencies.as_ref(),
+        );
+        Self::retain_listed(
+            &mut importer.dev_dependencies,
+            package_json.dev_dependencies.as_ref(),
+        );
+        Self::retain_listed(
+            &mut importer.optional_dependencies,
+            package_json.optional_dependencies.as_ref(),
+        );
+    }
+
     fn handle_importers(
         &self,
         lockfile_structure: &mut LockfileStructure,
@@ -146,9 +142,18 @@ impl LockFileActor {
                 let current_importer = e.get(CURRENT_IMPORTER);
                 match current_importer {
                     Some(i) => {
+                        let mut importer = i.clone();
+                        if self.prune {
+                            if let Ok(package_json) =
+                                PreprocessDependencyInstall::read_package_json()
+                            {
+                                Self::prune_importer(&mut importer, &package_json);
+                            }
+                        }
+
                         e.insert(
                             CURRENT_IMPORTER.to_string(),
-                            Self::create_importers(self.resolved_items.clone(), Some(&i.clone())),
+                            Self::create_importers(self.resolved_items.clone(), Some(&importer)),
                         );
                         Ok(())
                     }
@@ -173,6 +178,23 @@ impl LockFileActor {
         }
     }
 
+    /// Records the overrides of package.json, the next install compares against them
+    fn handle_overrides(lockfile_structure: &mut LockfileStructure) {
+        if let Ok(package_json) = PreprocessDependencyInstall::read_package_json() {
+            lockfile_structure.overrides = Overrides::new(&package_json).to_lockfile();
+        }
+    }
+
+    /// Records which dependencies may build, frozen installs read it from the lockfile
+    fn handle_build_settings(lockfile_structure: &mut LockfileStructure) {
+        let Ok(package_json) = PreprocessDependencyInstall::read_package_json() else {
+            return;
+        };
+        let settings = package_json.pnpm.unwrap_or_default();
+        lockfile_structure.only_built_dependencies = settings.only_built_dependencies;
+        lockfile_structure.never_built_dependencies = settings.never_built_dependencies;
+    }
+
     fn handle_packages(&self, lockfile_structure: &mut LockfileStructure) {
         let mut hashmap: HashMap<String, PackageMetaHandler> = HashMap::new();
 
@@ -186,8 +208,61 @@ impl LockFileActor {
             hashmap.insert(p.0.to_string(), pm_handler);
         });
 
+        Self::keep_untouched_packages(lockfile_structure, &mut hashmap);
+
         lockfile_structure.packages = Some(hashmap)
     }
+
+    /// Importer entries that were not part of this install keep their previously locked
+    /// packages. Everything that is not reachable from the importers is dropped.
+    fn keep_untouched_packages(
+        lockfile_structure: &LockfileStructure,
+        packages: &mut HashMap<String, PackageMetaHandler>,
+    ) {
+        let (Some(previous), Some(importer)) = (
+            lockfile_structure.packages.as_ref(),
+            lockfile_structure
+                .importers
+                .as_ref()
+                .and_then(|i| i.get(CURRENT_IMPORTER)),
+        ) else {
+            return;
+        };
+
+        let roots = [
+            &importer.dependencies,
+            &importer.dev_dependencies,
+            &importer.optional_dependencies,
+            &importer.peer_dependencies,
+        ]
+        .into_iter()
+        .flatten()
+        .flat_map(|deps| deps.iter())
+        .map(|(name, locked)| locked.package_key(name).to_string())
+        .filter(|key| !packages.contains_key(key))
+        .collect();
+
+        for key in lockfile_structure.reachable_packages(roots) {
+            if packages.contains_key(&key) {
+                continue;
+            }
+            let Some(handler) = previous.get(&key) else {
+                continue;
+            };
+
+            let mut handler = handler.clone();
+            if let Some(snapshot) = lockfile_structure
+                .snapshots
+                .as_ref()
+                .and_then(|s| s.get(&key))
+            {
+                let mut resolved = snapshot.dependencies.clone().unwrap_or_default();
+                resolved.extend(snapshot.optional_dependencies.clone().unwrap_or_default());
+                handler.resolved_dependencies = (!resolved.is_empty()).then_some(resolved);
+            }
+            packages.insert(key, handler);
+        }
+    }
 }
 
 impl Lockfile<LockfileStructure> for LockFileActor {
@@ -200,19 +275,149 @@ impl Lockfile<LockfileStructure> for LockFileActor {
     }
 
     fn run(&self) -> Result<(), LockfileError> {
-        if fs::exists("craft-lock.yaml").expect("Can't check existence of file does_not_exist.txt")
-        {
-            let mut lockfile_structure = Self::read_lock_file(Path::new("craft-lock.yaml"))?;
+        if fs::exists(LOCK_FILE_NAME).expect("Can't check existence of file does_not_exist.txt") {
+            let mut lockfile_structure = Self::read_lock_file(Path::new(LOCK_FILE_NAME))?;
             self.handle_importers(&mut lockfile_structure)?;
+            Self::handle_overrides(&mut lockfile_structure);
+            Self::handle_build_settings(&mut lockfile_structure);
             self.handle_packages(&mut lockfile_structure);
             Self::persist_lockfile_structure(&lockfile_structure.write_to_string())?;
             Ok(())
         } else {
             let mut lockfile_structure = LockfileStructure::default();
             self.handle_importers(&mut lockfile_structure)?;
+            Self::handle_overrides(&mut lockfile_structure);
+            Self::handle_build_settings(&mut lockfile_structure);
             self.handle_packages(&mut lockfile_structure);
             Self::persist_lockfile_structure(&lockfile_structure.write_to_string())?;
             Ok(())
         }
     }
 }
+
+// ─── Tests ───────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package::NpmPackage;
+
+    const LOCKFILE: &str = r#"
+lockfileVersion: '1.0'
+
+importers:
+
+  .:
+    dependencies:
+      loose-envify:
+        specifier: ^1.4.0
+        version: 1.4.0
+      left-pad:
+        specifier: ^1.0.0
+        version: 1.3.0
+
+packages:
+
+  js-tokens@4.0.0:
+    resolution: {integrity: sha512-tokens}
+
+  left-pad@1.3.0:
+    resolution: {integrity: sha512-pad}
+
+  loose-envify@1.4.0:
+    resolution: {integrity: sha512-envify}
+
+  unused@1.0.0:
+    resolution: {integrity: sha512-unused}
+
+snapshots:
+
+  js-tokens@4.0.0: {}
+
+  left-pad@1.3.0: {}
+
+  loose-envify@1.4.0:
+    dependencies:
+      js-tokens: 4.0.0
+
+  unused@1.0.0: {}
+"#;
+
+    #[test]
+    fn test_prune_importer() {
+        let lockfile: LockfileStructure = serde_yaml_ng::from_str(LOCKFILE).unwrap();
+        let mut importer = lockfile.importers.unwrap()[CURRENT_IMPORTER].clone();
+        let package_json: PackageJson =
+            r#"{ "dependencies": { "loose-envify": "^1.4.0" } }"#.to_string().into();
+
+        LockFileActor::prune_importer(&mut importer, &package_json);
+
+        let deps = importer.dependencies.unwrap();
+        assert_eq!(deps.len(), 1);
+        assert!(deps.contains_key("loose-envify"));
+    }
+
+    #[test]
+    fn test_keep_untouched_packages() {
+        let lockfile: LockfileStructure = serde_yaml_ng::from_str(LOCKFILE).unwrap();
+        let mut packages = HashMap::new();
+        packages.insert(
+            "left-pad@1.3.0".to_string(),
+            lockfile.packages.as_ref().unwrap()["left-pad@1.3.0"].clone(),
+        );
+
+        LockFileActor::keep_untouched_packages(&lockfile, &mut packages);
+
+        let mut keys: Vec<_> = packages.keys().cloned().collect();
+        keys.sort();
+        assert_eq!(
+            keys,
+            vec!["js-tokens@4.0.0", "left-pad@1.3.0", "loose-envify@1.4.0"]
+        );
+        assert_eq!(
+            packages["loose-envify@1.4.0"]
+                .resolved_dependencies
+                .as_ref()
+                .unwrap()["js-tokens"],
+            "4.0.0"
+        );
+
+        // Kept packages only carry resolved dependencies, their snapshots must stay valid
+        let written = LockfileStructure {
+            packages: Some(packages),
+            ..lockfile
+        }
+        .write_to_string();
+        let reloaded: LockfileStructure = serde_yaml_ng::from_str(&written).unwrap();
+        let snapshots = reloaded.snapshots.unwrap();
+        assert!(snapshots["js-tokens@4.0.0"].dependencies.is_none());
+        assert_eq!(
+            snapshots["loose-envify@1.4.0"]
+                .dependencies
+                .as_ref()
+                .unwrap()["js-tokens"],
+            "4.0.0"
+        );
+    }
+
+    #[test]
+    fn test_alias_importer_entry() {
+        let package = NpmPackage {
+            name: "lodash".to_string(),
+            version: "4.17.21".to_string(),
+            ..Default::default()
+        };
+        let item = ResolvedItem::with_no_parent(
+            package,
+            "npm:lodash@^4".to_string(),
+            PackageType::Prod("lodash4@npm:lodash@^4".to_string()),
+        );
+
+        let (name, dependency) = LockFileActor::importer_entry(&item);
+
+        assert_eq!(name, "lodash4");
+        assert_eq!(dependency.specifier, "npm:lodash@^4");
+        assert_eq!(dependency.version, "lodash@4.17.21");
+        assert_eq!(dependency.package_key(&name).to_string(), "lodash@4.17.21");
+    }
+}
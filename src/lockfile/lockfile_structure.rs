@@ -1,12 +1,14 @@
+use crate::cache::RegistryKey;
 use crate::lockfile::constants::{
     AUTO_INSTALL_PEERS, CPU, DEPENDENCIES, DEV_DEPENDENCIES, EXCLUDE_LINKS_FROM_LOCKFILE, HAS_BIN,
-    LOCKFILE_VERSION, OPTIONAL, OPT_DEPENDENCIES, OS, PACKAGES, PEER_DEPENDENCIES,
-    PEER_DEPENDENCIES_META, PEER_SUFFIX_MAX_LENGTH, RESOLUTION, SETTINGS, SNAPSHOTS, SPECIFIER,
-    VERSION,
+    LOCKFILE_VERSION, NEVER_BUILT_DEPENDENCIES, ONLY_BUILT_DEPENDENCIES, OPTIONAL,
+    OPT_DEPENDENCIES, OS, OVERRIDES, PACKAGES, PEER_DEPENDENCIES, PEER_DEPENDENCIES_META,
+    PEER_SUFFIX_MAX_LENGTH, RESOLUTION, SETTINGS, SNAPSHOTS, SPECIFIER, VERSION,
 };
 use crate::package::PackageMetaHandler;
 use serde::{Deserialize, Serialize};
-use std::collections::{BTreeMap, HashMap};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct ResolvedDependency {
@@ -14,12 +16,35 @@ pub struct ResolvedDependency {
     pub version: String,
 }
 
+impl ResolvedDependency {
+    /// Whether the locked entry was produced for the given package.json specifier.
+    /// A `*` specifier is locked as `^<version>`, so that case is accepted as well.
+    pub fn matches_specifier(&self, specifier: &str) -> bool {
+        self.specifier == specifier
+            || (specifier == "*" && self.specifier == format!("^{}", self.version))
+    }
+
+    /// Key of the locked package, aliased dependencies lock `<real name>@<version>`
+    pub fn package_key(&self, name: &str) -> RegistryKey {
+        match self.version.get(1..).and_then(|v| v.find('@')) {
+            Some(index) => RegistryKey {
+                name: self.version[..=index].to_string(),
+                version: self.version[index + 2..].to_string(),
+            },
+            None => RegistryKey {
+                name: name.to_string(),
+                version: self.version.clone(),
+            },
+        }
+    }
+}
+
 type ProjectId = String;
 pub type ResolvedDependencies = HashMap<String, ResolvedDependency>;
 type CatalogName = String;
 type DependencyName = String;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 #[serde(untagged)]
 pub enum LockfileResolution {
     Directory(DirectoryResolution),
@@ -31,35 +56,61 @@ pub enum LockfileResolution {
 /**
  * tarball hosted remotely
  */
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct TarballResolution {
     pub r#type: Option<String>,
-    pub tarball: Option<String>,
+    pub tarball: String,
     pub integrity: Option<String>,
     pub path: Option<String>,
 }
 
-#[derive(Serialize, Deserialize)]
+/**
+ * local directory, `type` is `directory` for copies and `link` for symlinks
+ */
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct DirectoryResolution {
-    directory: String,
+    pub r#type: Option<String>,
+    pub directory: String,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct GitRepositoryResolution {
-    repo: String,
-    commit: String,
-    path: Option<String>,
+    pub repo: String,
+    pub commit: String,
+    pub path: Option<String>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct IntegrityResolution {
     pub integrity: String,
 }
 
+impl LockfileResolution {
+    pub fn integrity(&self) -> Option<&str> {
+        match self {
+            LockfileResolution::Integrity(i) => Some(&i.integrity),
+            LockfileResolution::Tarball(t) => t.integrity.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Identifies git, tarball and local packages, which may share their name@version with
+    /// a registry release: the commit, the tarball integrity or the path. Registry packages have none
+    pub fn source_id(&self) -> Option<String> {
+        let source = match self {
+            LockfileResolution::GitRepository(g) => &g.commit,
+            LockfileResolution::Tarball(t) => t.integrity.as_ref().unwrap_or(&t.tarball),
+            LockfileResolution::Directory(d) => &d.directory,
+            LockfileResolution::Integrity(_) => return None,
+        };
+        Some(hex::encode(Sha256::digest(source))[..16].to_string())
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ResolvedCatalogEntry {
@@ -67,6 +118,7 @@ pub struct ResolvedCatalogEntry {
     pub version: String,
 }
 
+#[allow(dead_code)]
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct PackageSnapshot {
@@ -79,6 +131,16 @@ pub struct PackageSnapshot {
     pub resolution: LockfileResolution,
 }
 
+/**
+ * Entry of the snapshots section, holds the exact versions a package was linked against
+ */
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotEntry {
+    pub dependencies: Option<HashMap<String, String>>,
+    pub optional_dependencies: Option<HashMap<String, String>>,
+}
+
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct PatchFile {
@@ -86,6 +148,7 @@ pub struct PatchFile {
     pub hash: String,
 }
 
+#[allow(dead_code)]
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct PatchInfo {
@@ -136,6 +199,8 @@ pub struct LockfileStructure {
         serialize_with = "ordered_map"
     )]
     pub packages: Option<HashMap<String, PackageMetaHandler>>,
+    #[serde(skip_serializing)]
+    pub snapshots: Option<HashMap<String, SnapshotEntry>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub never_built_dependencies: Option<Vec<String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -155,6 +220,29 @@ pub struct LockfileStructure {
 impl LockfileStructure {
     const ESCAPE_CHARS: [char; 4] = ['@', '<', '>', '*'];
 
+    /// Collects every package key reachable from `roots` through the snapshots section
+    pub fn reachable_packages(&self, roots: Vec<String>) -> HashSet<String> {
+        let mut reachable = HashSet::new();
+        let mut pending = roots;
+
+        while let Some(key) = pending.pop() {
+            if !reachable.insert(key.clone()) {
+                continue;
+            }
+
+            let Some(snapshot) = self.snapshots.as_ref().and_then(|s| s.get(&key)) else {
+                continue;
+            };
+            let dependencies = snapshot.dependencies.iter();
+            let optional = snapshot.optional_dependencies.iter();
+            for (name, version) in dependencies.chain(optional).flatten() {
+                pending.push(format!("{}@{}", name, version));
+            }
+        }
+
+        reachable
+    }
+
     fn starts_with_illegal_character(str: &str) -> bool {
         if let Some(c) = str.chars().next() {
             if Self::ESCAPE_CHARS.contains(&c) {
@@ -171,6 +259,34 @@ impl LockfileStructure {
         str.to_string()
     }
 
+    fn quote(str: &str) -> String {
+        format!("'{}'", str.replace('\'', "''"))
+    }
+
+    fn format_resolution(resolution: &LockfileResolution) -> String {
+        match resolution {
+            LockfileResolution::Integrity(i) => format!("{{integrity: {}}}", i.integrity),
+            LockfileResolution::GitRepository(g) => format!(
+                "{{type: git, repo: {}, commit: {}}}",
+                Self::quote(&g.repo),
+                g.commit
+            ),
+            LockfileResolution::Tarball(t) => match &t.integrity {
+                Some(integrity) => format!(
+                    "{{tarball: {}, integrity: {}}}",
+                    Self::quote(&t.tarball),
+                    integrity
+                ),
+                None => format!("{{tarball: {}}}", Self::quote(&t.tarball)),
+            },
+            LockfileResolution::Directory(d) => format!(
+                "{{type: {}, directory: {}}}",
+                d.r#type.as_deref().unwrap_or("directory"),
+                Self::quote(&d.directory)
+            ),
+        }
+    }
+
     fn format_line(key: &str, value: Option<&str>, indent: i32) -> String {
         let mut str = "".to_string();
         for _ in 0..indent * 2 {
@@ -217,6 +333,23 @@ impl LockfileStructure {
         settings_str
     }
 
+    fn format_overrides(overrides: &HashMap<String, String>) -> String {
+        let mut overrides_str = Self::format_line(OVERRIDES, None, 0);
+        let ordered: BTreeMap<_, _> = overrides.iter().collect();
+        ordered.iter().for_each(|(key, version)| {
+            overrides_str.push_str(&Self::format_line(key, Some(version), 1));
+        });
+        overrides_str
+    }
+
+    fn format_list(key: &str, items: &[String]) -> String {
+        let mut list_str = Self::format_line(key, None, 0);
+        items.iter().for_each(|item| {
+            list_str.push_str(&format!("  - {}\n", Self::format_string(item)));
+        });
+        list_str
+    }
+
     fn format_lockfile_version(&self) -> String {
         Self::format_line(
             LOCKFILE_VERSION,
@@ -294,7 +427,7 @@ impl LockfileStructure {
     ) {
         packages_serialized.push('\n');
         if snapshot
-            && p.1.dependencies.is_none()
+            && p.1.resolved_dependencies.is_none()
             && p.1.peer_dependencies.is_none()
             && p.1.has_bin.is_none()
         {
@@ -305,10 +438,9 @@ impl LockfileStructure {
 
         if !snapshot {
             if let Some(res) = &p.1.resolution {
-                let integrity = format!("{{integrity: {}}}", res.integrity);
                 packages_serialized.push_str(&Self::format_line(
                     RESOLUTION,
-                    Some(&integrity),
+                    Some(&Self::format_resolution(res)),
                     index + 1,
                 ));
             }
@@ -413,6 +545,21 @@ impl LockfileStructure {
             serialized_content.push_str(&self.format_settings())
         }
 
+        if let Some(overrides) = &self.overrides {
+            serialized_content.push('\n');
+            serialized_content.push_str(&Self::format_overrides(overrides))
+        }
+
+        if let Some(only_built) = &self.only_built_dependencies {
+            serialized_content.push('\n');
+            serialized_content.push_str(&Self::format_list(ONLY_BUILT_DEPENDENCIES, only_built))
+        }
+
+        if let Some(never_built) = &self.never_built_dependencies {
+            serialized_content.push('\n');
+            serialized_content.push_str(&Self::format_list(NEVER_BUILT_DEPENDENCIES, never_built))
+        }
+
         if self.importers.is_some() {
             serialized_content.push('\n');
             serialized_content.push_str(&self.format_importers())
@@ -470,10 +617,12 @@ impl Default for LockfileStructure {
             craftfile_checksum: None,
             never_built_dependencies: None,
             packages: None,
+            snapshots: None,
         }
     }
 }
 
+#[allow(dead_code)]
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct ProjectSnapshot {
@@ -484,8 +633,10 @@ struct ProjectSnapshot {
     dependencies_meta: Option<DependenciesMeta>,
 }
 
+#[allow(dead_code)]
 pub type DependenciesMeta = HashMap<DependencyName, DependencyMeta>;
 
+#[allow(dead_code)]
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct DependencyMeta {
@@ -1,3 +1,3 @@
-mod constants;
+pub(crate) mod constants;
 pub mod lock_file_actor;
 pub(crate) mod lockfile_structure;
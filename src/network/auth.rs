@@ -0,0 +1,227 @@
+use std::collections::BTreeMap;
+use std::fmt::{Debug, Formatter};
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use reqwest::Url;
+
+use crate::conf::constants::{AUTH, AUTH_TOKEN, PASSWORD, USERNAME};
+use crate::conf::NpmConfig;
+
+// ─── Credentials ─────────────────────────────────────────────────────────────
+
+/// Credentials of a single registry, as configured through `.npmrc`
+#[derive(Clone, Default)]
+pub struct Credentials {
+    auth_token: Option<String>,
+    auth: Option<String>,
+    username: Option<String>,
+    password: Option<String>,
+}
+
+// Tokens must never end up in verbose logs
+impl Debug for Credentials {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let redact = |v: &Option<String>| v.as_ref().map(|_| "(protected)");
+        f.debug_struct("Credentials")
+            .field("auth_token", &redact(&self.auth_token))
+            .field("auth", &redact(&self.auth))
+            .field("username", &self.username)
+            .field("password", &redact(&self.password))
+            .finish()
+    }
+}
+
+impl Credentials {
+    /// Stores a credential field, returns false if the field is not a credential
+    pub fn set(&mut self, field: &str, value: &str) -> bool {
+        let value = Some(value.to_string());
+        match field {
+            AUTH_TOKEN => self.auth_token = value,
+            AUTH => self.auth = value,
+            USERNAME => self.username = value,
+            PASSWORD => self.password = value,
+            _ => return false,
+        }
+        true
+    }
+
+    /// Value of the Authorization header, `_password` is stored base64 encoded like npm does
+    fn authorization(&self) -> Option<String> {
+        if let Some(token) = &self.auth_token {
+            return Some(format!("Bearer {}", token));
+        }
+
+        if let Some(auth) = &self.auth {
+            return Some(format!("Basic {}", auth));
+        }
+
+        let (username, password) = (self.username.as_ref()?, self.password.as_ref()?);
+        let password = STANDARD.decode(password).ok()?;
+        let password = String::from_utf8(password).ok()?;
+        let basic = STANDARD.encode(format!("{}:{}", username, password));
+        Some(format!("Basic {}", basic))
+    }
+}
+
+// ─── Helpers ─────────────────────────────────────────────────────────────────
+
+/// Strips the protocol and file name from a URL, e.g. https://host:8080/a/b.tgz => //host:8080/a/
+pub fn nerf_dart(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    let host = url.host_str()?;
+    let port = url.port().map(|p| format!(":{}", p)).unwrap_or_default();
+    let path = url.path();
+    let directory = &path[..=path.rfind('/').unwrap_or(0)];
+
+    Some(format!("//{}{}{}", host, port, directory))
+}
+
+// ─── RegistryAuth ────────────────────────────────────────────────────────────
+
+/// Picks the credentials that belong to a request URL
+#[derive(Clone, Debug, Default)]
+pub struct RegistryAuth {
+    registries: BTreeMap<String, Credentials>,
+    default_credentials: Credentials,
+    default_registry: Option<String>,
+    always_auth: bool,
+}
+
+impl RegistryAuth {
+    pub fn new(config: &NpmConfig) -> Self {
+        let registries = config
+            .registry_credentials
+            .iter()
+            .map(|(prefix, credentials)| {
+                let prefix = match prefix.ends_with('/') {
+                    true => prefix.clone(),
+                    false => format!("{}/", prefix),
+                };
+                (prefix, credentials.clone())
+            })
+            .collect();
+
+        Self {
+            registries,
+            default_credentials: config.default_credentials.clone(),
+            default_registry: nerf_dart(&config.registry),
+            always_auth: config.always_auth,
+        }
+    }
+
+    /// Authorization header for the URL, the most specific configured prefix wins.
+    /// Top level credentials only go to the default registry unless `always-auth` is set.
+    pub fn authorization(&self, url: &str) -> Option<String> {
+        let nerfed = nerf_dart(url)?;
+
+        let scoped = self
+            .registries
+            .iter()
+            .filter(|(prefix, _)| nerfed.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len());
+        if let Some((_, credentials)) = scoped {
+            return credentials.authorization();
+        }
+
+        let is_default_registry = self
+            .default_registry
+            .as_ref()
+            .is_some_and(|r| nerfed.starts_with(r.as_str()));
+        if is_default_registry || self.always_auth {
+            return self.default_credentials.authorization();
+        }
+
+        None
+    }
+}
+
+// ─── Tests ───────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn auth(entries: &[(&str, &str)]) -> RegistryAuth {
+        let conf = entries
+            .iter()
+            .map(|(k, v)| (k.to_string(), Some(v.to_string())))
+            .collect::<BTreeMap<_, _>>();
+        RegistryAuth::new(&NpmConfig::new(conf))
+    }
+
+    #[test]
+    fn test_nerf_dart() {
+        assert_eq!(
+            nerf_dart("https://npm.ourco.dev:8443/@ourco/ui/-/ui-1.0.0.tgz").unwrap(),
+            "//npm.ourco.dev:8443/@ourco/ui/-/"
+        );
+        assert_eq!(
+            nerf_dart("https://registry.npmjs.org/").unwrap(),
+            "//registry.npmjs.org/"
+        );
+    }
+
+    #[test]
+    fn test_authorization_by_prefix() {
+        let auth = auth(&[
+            ("//npm.ourco.dev/:_authToken", "team"),
+            ("//npm.ourco.dev/private/:_authToken", "private"),
+            ("_auth", "dXNlcjpwYXNz"),
+        ]);
+
+        assert_eq!(
+            auth.authorization("https://npm.ourco.dev/@ourco%2fui"),
+            Some("Bearer team".to_string())
+        );
+        assert_eq!(
+            auth.authorization("https://npm.ourco.dev/private/pkg/-/pkg-1.0.0.tgz"),
+            Some("Bearer private".to_string())
+        );
+        assert_eq!(
+            auth.authorization("https://registry.npmjs.org/left-pad"),
+            Some("Basic dXNlcjpwYXNz".to_string())
+        );
+        assert_eq!(auth.authorization("https://cdn.example.com/a.tgz"), None);
+    }
+
+    #[test]
+    fn test_always_auth_and_basic_credentials() {
+        let auth = auth(&[
+            ("username", "user"),
+            ("_password", "cGFzcw=="),
+            ("always-auth", "true"),
+        ]);
+
+        assert_eq!(
+            auth.authorization("https://cdn.example.com/a.tgz"),
+            Some("Basic dXNlcjpwYXNz".to_string())
+        );
+    }
+
+    #[test]
+    fn test_expanded_credentials() {
+        std::env::set_var("CRAFT_TEST_AUTH_TOKEN", "secret");
+        let mut conf = NpmConfig::new(BTreeMap::new());
+        conf.apply(
+            BTreeMap::from([(
+                "//npm.ourco.dev/:_authToken".to_string(),
+                Some("${CRAFT_TEST_AUTH_TOKEN}".to_string()),
+            )]),
+            crate::conf::Location::User,
+        );
+
+        assert_eq!(
+            RegistryAuth::new(&conf).authorization("https://npm.ourco.dev/pkg"),
+            Some("Bearer secret".to_string())
+        );
+    }
+
+    #[test]
+    fn test_credentials_are_redacted() {
+        let mut credentials = Credentials::default();
+        credentials.set(AUTH_TOKEN, "secret");
+
+        assert!(!format!("{:?}", credentials).contains("secret"));
+    }
+}
@@ -0,0 +1,224 @@
+use std::time::Duration;
+
+use reqwest::{Certificate, Client, Identity, NoProxy, Proxy};
+
+use crate::conf::NpmConfig;
+use crate::errors::NetworkError;
+
+// ─── NetworkSettings ─────────────────────────────────────────────────────────
+
+/// Proxies, TLS trust and client certificates from the config,
+/// applied to the HTTP client and handed to git through its environment
+#[derive(Debug, Clone, Default)]
+pub struct NetworkSettings {
+    https_proxy: Option<String>,
+    http_proxy: Option<String>,
+    no_proxy: Option<String>,
+    /// Inline PEM certificates from `ca`
+    ca: Option<String>,
+    ca_file: Option<String>,
+    cert: Option<String>,
+    key: Option<String>,
+    strict_ssl: bool,
+    timeout: Option<Duration>,
+}
+
+impl NetworkSettings {
+    pub fn new(config: &NpmConfig) -> Self {
+        let non_empty = |value: &Option<String>| value.clone().filter(|v| !v.trim().is_empty());
+
+        Self {
+            https_proxy: non_empty(&config.https_proxy).or_else(|| non_empty(&config.proxy)),
+            http_proxy: non_empty(&config.proxy),
+            no_proxy: non_empty(&config.no_proxy),
+            ca: non_empty(&config.ca).map(|ca| Self::pem(&ca)),
+            ca_file: non_empty(&config.ca_file),
+            cert: non_empty(&config.cert).map(|cert| Self::pem(&cert)),
+            key: non_empty(&config._key).map(|key| Self::pem(&key)),
+            strict_ssl: config.strict_ssl,
+            timeout: (config.fetch_timeout > 0)
+                .then(|| Duration::from_millis(config.fetch_timeout as u64)),
+        }
+    }
+
+    /// .npmrc keeps PEM blocks on one line with escaped newlines
+    fn pem(value: &str) -> String {
+        value.replace("\\n", "\n")
+    }
+
+    /// Proxy URLs may carry credentials, so they never end up in errors
+    fn proxy(proxy: reqwest::Result<Proxy>) -> Result<Proxy, NetworkError> {
+        proxy.map_err(|_| NetworkError::Client("invalid proxy URL".to_string()))
+    }
+
+    fn bundle(pem: &[u8], source: &str) -> Result<Vec<Certificate>, NetworkError> {
+        match Certificate::from_pem_bundle(pem) {
+            Ok(certificates) if !certificates.is_empty() => Ok(certificates),
+            _ => Err(NetworkError::Client(format!(
+                "invalid certificate in {}",
+                source
+            ))),
+        }
+    }
+
+    fn certificates(&self) -> Result<Vec<Certificate>, NetworkError> {
+        let mut certificates = vec![];
+
+        if let Some(ca) = &self.ca {
+            certificates.extend(Self::bundle(ca.as_bytes(), "ca")?);
+        }
+        if let Some(path) = &self.ca_file {
+            let bundle = std::fs::read(path)
+                .map_err(|e| NetworkError::Client(format!("cafile {}: {}", path, e)))?;
+            certificates.extend(Self::bundle(&bundle, path)?);
+        }
+
+        Ok(certificates)
+    }
+
+    /// The client every request goes through
+    pub fn client(&self) -> Result<Client, NetworkError> {
+        // Proxies only come from the config, which already folds in HTTPS_PROXY and NO_PROXY
+        let mut builder = Client::builder().no_proxy();
+
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+
+        let no_proxy = self.no_proxy.as_deref().and_then(NoProxy::from_string);
+        if let Some(url) = &self.https_proxy {
+            builder = builder.proxy(Self::proxy(Proxy::https(url))?.no_proxy(no_proxy.clone()));
+        }
+        if let Some(url) = &self.http_proxy {
+            builder = builder.proxy(Self::proxy(Proxy::http(url))?.no_proxy(no_proxy));
+        }
+
+        for certificate in self.certificates()? {
+            builder = builder.add_root_certificate(certificate);
+        }
+        if let (Some(cert), Some(key)) = (&self.cert, &self.key) {
+            let identity = Identity::from_pkcs8_pem(cert.as_bytes(), key.as_bytes())
+                .map_err(|_| NetworkError::Client("invalid cert or key".to_string()))?;
+            builder = builder.identity(identity);
+        }
+        if !self.strict_ssl {
+            builder = builder.danger_accept_invalid_certs(true);
+        }
+
+        builder
+            .build()
+            .map_err(|e| NetworkError::Client(e.to_string()))
+    }
+
+    /// The same settings for git, which reads them from its environment
+    pub fn git_env(&self) -> Vec<(&'static str, String)> {
+        let mut env = vec![];
+
+        if let Some(url) = &self.https_proxy {
+            env.push(("https_proxy", url.clone()));
+        }
+        if let Some(url) = &self.http_proxy {
+            env.push(("http_proxy", url.clone()));
+        }
+        if let Some(hosts) = &self.no_proxy {
+            env.push(("no_proxy", hosts.clone()));
+        }
+        if let Some(path) = &self.ca_file {
+            env.push(("GIT_SSL_CAINFO", path.clone()));
+        }
+        if !self.strict_ssl {
+            env.push(("GIT_SSL_NO_VERIFY", "true".to_string()));
+        }
+
+        env
+    }
+}
+
+// ─── Tests ───────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn settings(entries: &[(&str, &str)]) -> NetworkSettings {
+        let conf = entries
+            .iter()
+            .map(|(k, v)| (k.to_string(), Some(v.to_string())))
+            .collect::<BTreeMap<_, _>>();
+        NetworkSettings::new(&NpmConfig::new(conf))
+    }
+
+    /// A proxy answering a single request, returns its URL and the request line it received
+    async fn proxy() -> (String, tokio::task::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let handle = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = [0; 1024];
+            let read = socket.read(&mut request).await.unwrap();
+            socket
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                .await
+                .unwrap();
+            let request = String::from_utf8_lossy(&request[..read]).to_string();
+            request.lines().next().unwrap_or_default().to_string()
+        });
+
+        (format!("http://{}", address), handle)
+    }
+
+    #[tokio::test]
+    async fn test_proxy() {
+        let (url, request) = proxy().await;
+        let client = settings(&[("proxy", &url), ("no-proxy", "localhost")])
+            .client()
+            .unwrap();
+
+        let response = client
+            .get("http://registry.invalid/lib")
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status().is_success());
+        assert_eq!(
+            request.await.unwrap(),
+            "GET http://registry.invalid/lib HTTP/1.1"
+        );
+
+        // Hosts in no-proxy are reached directly
+        let (url, _) = proxy().await;
+        let client = settings(&[("proxy", &url), ("no-proxy", "registry.invalid")])
+            .client()
+            .unwrap();
+        assert!(client
+            .get("http://registry.invalid/lib")
+            .send()
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn test_tls_settings() {
+        let error = settings(&[("ca", "not a certificate")])
+            .client()
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Failed to configure the HTTP client: invalid certificate in ca"
+        );
+
+        let error = settings(&[("https-proxy", "http://user:secret@[bad")])
+            .client()
+            .unwrap_err();
+        assert!(!error.to_string().contains("secret"));
+
+        let insecure = settings(&[("strict-ssl", "false"), ("cafile", "/etc/corp.pem")]);
+        let env = insecure.git_env();
+        assert!(env.contains(&("GIT_SSL_NO_VERIFY", "true".to_string())));
+        assert!(env.contains(&("GIT_SSL_CAINFO", "/etc/corp.pem".to_string())));
+    }
+}
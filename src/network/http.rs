@@ -1,39 +1,251 @@
 use std::path::PathBuf;
 
+use super::auth::RegistryAuth;
+use super::client::NetworkSettings;
+use super::policy::FetchPolicy;
+use super::retry::RetryPolicy;
+use super::Integrity;
+use crate::conf::NpmConfig;
 use crate::errors::NetworkError;
-use sha1::{Digest, Sha1};
+use reqwest::header::AUTHORIZATION;
+use reqwest::{Client, RequestBuilder, Response};
 use tokio::fs::File;
 use tokio::io::AsyncWriteExt;
 
-pub struct Http;
+/// HTTP client shared by every registry request, attaches the configured credentials
+/// and retries transient failures
+#[derive(Debug, Clone)]
+pub struct Http {
+    client: Client,
+    auth: RegistryAuth,
+    retry: RetryPolicy,
+    settings: NetworkSettings,
+    policy: FetchPolicy,
+}
 
 impl Http {
+    pub fn new(config: &NpmConfig) -> Result<Self, NetworkError> {
+        let settings = NetworkSettings::new(config);
+
+        Ok(Self {
+            client: settings.client()?,
+            auth: RegistryAuth::new(config),
+            retry: RetryPolicy::new(config),
+            settings,
+            policy: FetchPolicy::new(config),
+        })
+    }
+
+    pub fn policy(&self) -> FetchPolicy {
+        self.policy
+    }
+
+    /// Proxy and TLS settings for git processes
+    pub fn git_env(&self) -> Vec<(&'static str, String)> {
+        self.settings.git_env()
+    }
+
+    pub fn get(&self, url: &str) -> RequestBuilder {
+        let request = self.client.get(url);
+        match self.auth.authorization(url) {
+            Some(authorization) => request.header(AUTHORIZATION, authorization),
+            None => request,
+        }
+    }
+
+    /// Sends the request, repeating it on 5xx, 429 and connection failures.
+    /// The last response is returned as is once retries are exhausted.
+    pub async fn send(&self, request: RequestBuilder) -> Result<Response, NetworkError> {
+        if self.policy == FetchPolicy::Offline {
+            let url = request.try_clone().and_then(|r| r.build().ok());
+            let url = url.map(|r| r.url().to_string()).unwrap_or_default();
+            return Err(NetworkError::Offline(url));
+        }
+        let mut attempt = 0;
+
+        loop {
+            let current = request
+                .try_clone()
+                .expect("Requests without a streamed body can be cloned");
+            let result = current.send().await;
+
+            let retry_after = match &result {
+                Ok(response) if RetryPolicy::is_retryable_status(response.status()) => {
+                    RetryPolicy::retry_after(response)
+                }
+                Err(e) if RetryPolicy::is_transient(e) => None,
+                _ => return Ok(result?),
+            };
+            if attempt >= self.retry.retries {
+                return Ok(result?);
+            }
+
+            let delay = self.retry.delay(attempt, retry_after);
+            match &result {
+                Ok(response) => log::warn!("Got {}, retrying in {:?}", response.status(), delay),
+                Err(e) => log::warn!("Request failed: {}, retrying in {:?}", e, delay),
+            }
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    /// Downloads to `path`, starting over when the connection drops mid-way.
+    /// Partially written files are removed on failure.
     pub async fn download_file(
+        &self,
         url: &str,
         path: &PathBuf,
-        sha_sum: &str,
+        integrity: Option<&Integrity>,
     ) -> Result<(), NetworkError> {
-        log::info!("Downloading file from: {}", url);
-        let mut response = reqwest::get(url).await?;
-        let mut hasher = Sha1::new();
-
-        let mut file = match File::create(path).await {
-            Ok(file) => file,
-            Err(_) => {
-                println!("Could not create file at {:?}", path);
-                panic!("Stop");
+        let mut attempt = 0;
+
+        loop {
+            let result = self.try_download(url, path, integrity).await;
+            let Err(e) = result else {
+                return Ok(());
+            };
+            let _ = tokio::fs::remove_file(path).await;
+
+            let transient =
+                matches!(&e, NetworkError::FetchFailure(e) if RetryPolicy::is_transient(e));
+            if !transient || attempt >= self.retry.retries {
+                return Err(e);
             }
-        };
+            let delay = self.retry.delay(attempt, None);
+            log::warn!("Download of {} failed: {}, retrying in {:?}", url, e, delay);
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    async fn try_download(
+        &self,
+        url: &str,
+        path: &PathBuf,
+        integrity: Option<&Integrity>,
+    ) -> Result<(), NetworkError> {
+        log::info!("Downloading file from: {}", url);
+        let mut response = self.send(self.get(url)).await?.error_for_status()?;
+        let mut hasher = integrity.map(Integrity::hasher);
+
+        let mut file = File::create(path).await?;
 
         while let Some(chunk) = response.chunk().await? {
             file.write_all(&chunk).await?;
-            hasher.update(&chunk);
+            if let Some(hasher) = hasher.as_mut() {
+                hasher.update(&chunk);
+            }
         }
-        let calculated_sha = hasher.finalize().0;
-        let calculated_sha_hex = hex::encode(calculated_sha);
-        if calculated_sha_hex != sha_sum {
-            return Err(NetworkError::CheckSum(url.to_string()));
+        // Writes finish in the background, the file is complete once flushed
+        file.flush().await?;
+
+        if let (Some(hasher), Some(expected)) = (hasher, integrity) {
+            if let Err(actual) = hasher.verify(expected) {
+                // The caller removes the tampered tarball
+                return Err(NetworkError::Integrity(
+                    url.to_string(),
+                    expected.to_string(),
+                    actual,
+                ));
+            }
         }
         Ok(())
     }
 }
+
+// ─── Tests ───────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    fn http() -> Http {
+        let config = NpmConfig::new(BTreeMap::from([
+            ("fetch-retries".to_string(), Some("2".to_string())),
+            ("fetch-retry-mintimeout".to_string(), Some("1".to_string())),
+        ]));
+        Http::new(&config).unwrap()
+    }
+
+    /// Answers each connection with the next raw response
+    async fn serve(responses: Vec<String>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            for response in responses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = [0; 1024];
+                let _ = socket.read(&mut request).await.unwrap();
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        format!("http://{}/lib-1.0.0.tgz", address)
+    }
+
+    fn response(status: &str, headers: &str, body: &str) -> String {
+        format!(
+            "HTTP/1.1 {}\r\nConnection: close\r\n{}\r\n{}",
+            status, headers, body
+        )
+    }
+
+    #[tokio::test]
+    async fn test_retry_server_errors() {
+        let url = serve(vec![
+            response("503 Service Unavailable", "Content-Length: 0\r\n", ""),
+            response(
+                "429 Too Many Requests",
+                "Retry-After: 0\r\nContent-Length: 0\r\n",
+                "",
+            ),
+            response("200 OK", "Content-Length: 2\r\n", "ok"),
+        ])
+        .await;
+
+        let http = http();
+        let response = http.send(http.get(&url)).await.unwrap();
+        assert_eq!(response.text().await.unwrap(), "ok");
+    }
+
+    #[tokio::test]
+    async fn test_download_after_reset() {
+        let url = serve(vec![
+            // The connection closes before the announced body was sent
+            response("200 OK", "Content-Length: 100\r\n", "partial"),
+            response("200 OK", "Content-Length: 8\r\n", "complete"),
+        ])
+        .await;
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("lib@1.0.0");
+
+        http().download_file(&url, &path, None).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "complete");
+
+        let url = serve(vec![response("404 Not Found", "Content-Length: 0\r\n", "")]).await;
+        assert!(http().download_file(&url, &path, None).await.is_err());
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_offline() {
+        let config = NpmConfig::new(BTreeMap::from([(
+            "offline".to_string(),
+            Some("true".to_string()),
+        )]));
+        let http = Http::new(&config).unwrap();
+        assert_eq!(http.policy(), FetchPolicy::Offline);
+
+        // Nothing listens there, the request must not even be attempted
+        let error = http.send(http.get("http://127.0.0.1:9/lib")).await;
+        assert_eq!(
+            error.unwrap_err().to_string(),
+            "http://127.0.0.1:9/lib is not available offline"
+        );
+    }
+}
@@ -0,0 +1,195 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use sha2::{Digest, Sha256, Sha384, Sha512};
+
+/// Subresource integrity of the content, e.g. sha512-<base64 digest>
+pub fn sha512_integrity(content: &[u8]) -> String {
+    format!("sha512-{}", STANDARD.encode(Sha512::digest(content)))
+}
+
+// ─── Integrity ───────────────────────────────────────────────────────────────
+
+/// Hash algorithms of an SRI string, weakest first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Algorithm {
+    Sha1,
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl Algorithm {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "sha1" => Some(Self::Sha1),
+            "sha256" => Some(Self::Sha256),
+            "sha384" => Some(Self::Sha384),
+            "sha512" => Some(Self::Sha512),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Sha1 => "sha1",
+            Self::Sha256 => "sha256",
+            Self::Sha384 => "sha384",
+            Self::Sha512 => "sha512",
+        }
+    }
+}
+
+/// The hash a download has to match
+#[derive(Debug, Clone, PartialEq)]
+pub struct Integrity {
+    pub algorithm: Algorithm,
+    /// Base64 digest
+    pub digest: String,
+}
+
+impl Integrity {
+    /// Picks the strongest hash of an SRI string, which may list several separated by spaces
+    pub fn parse(sri: &str) -> Option<Self> {
+        sri.split_whitespace()
+            .filter_map(|hash| {
+                let (algorithm, digest) = hash.split_once('-')?;
+                // Options like `?foo` may follow the digest
+                let digest = digest.split('?').next()?;
+                Some(Self {
+                    algorithm: Algorithm::parse(algorithm)?,
+                    digest: digest.to_string(),
+                })
+            })
+            .max_by_key(|integrity| integrity.algorithm)
+    }
+
+    /// The legacy hex `shasum` of the registry
+    pub fn from_shasum(shasum: &str) -> Option<Self> {
+        let digest = hex::decode(shasum).ok()?;
+        Some(Self {
+            algorithm: Algorithm::Sha1,
+            digest: STANDARD.encode(digest),
+        })
+    }
+
+    /// The integrity of a package, falling back to its shasum
+    pub fn for_package(integrity: Option<&str>, shasum: &str) -> Option<Self> {
+        integrity
+            .and_then(Self::parse)
+            .or_else(|| Self::from_shasum(shasum))
+    }
+
+    pub fn hasher(&self) -> IntegrityHasher {
+        IntegrityHasher::new(self.algorithm)
+    }
+
+    pub fn check(&self, content: &[u8]) -> Result<(), String> {
+        let mut hasher = self.hasher();
+        hasher.update(content);
+        hasher.verify(self)
+    }
+}
+
+impl std::fmt::Display for Integrity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.algorithm.name(), self.digest)
+    }
+}
+
+// ─── IntegrityHasher ─────────────────────────────────────────────────────────
+
+/// Hashes a download chunk by chunk with the algorithm of the expected integrity
+pub enum IntegrityHasher {
+    Sha1(sha1::Sha1),
+    Sha256(Sha256),
+    Sha384(Sha384),
+    Sha512(Sha512),
+}
+
+impl IntegrityHasher {
+    fn new(algorithm: Algorithm) -> Self {
+        match algorithm {
+            Algorithm::Sha1 => Self::Sha1(sha1::Digest::new()),
+            Algorithm::Sha256 => Self::Sha256(Sha256::new()),
+            Algorithm::Sha384 => Self::Sha384(Sha384::new()),
+            Algorithm::Sha512 => Self::Sha512(Sha512::new()),
+        }
+    }
+
+    pub fn update(&mut self, chunk: &[u8]) {
+        match self {
+            Self::Sha1(hasher) => sha1::Digest::update(hasher, chunk),
+            Self::Sha256(hasher) => hasher.update(chunk),
+            Self::Sha384(hasher) => hasher.update(chunk),
+            Self::Sha512(hasher) => hasher.update(chunk),
+        }
+    }
+
+    fn finalize(self) -> Integrity {
+        let (algorithm, digest) = match self {
+            Self::Sha1(hasher) => (Algorithm::Sha1, sha1::Digest::finalize(hasher).to_vec()),
+            Self::Sha256(hasher) => (Algorithm::Sha256, hasher.finalize().to_vec()),
+            Self::Sha384(hasher) => (Algorithm::Sha384, hasher.finalize().to_vec()),
+            Self::Sha512(hasher) => (Algorithm::Sha512, hasher.finalize().to_vec()),
+        };
+        Integrity {
+            algorithm,
+            digest: STANDARD.encode(digest),
+        }
+    }
+
+    /// Compares the hashed content with the expected integrity, returns the actual one on mismatch
+    pub fn verify(self, expected: &Integrity) -> Result<(), String> {
+        let actual = self.finalize();
+        match actual == *expected {
+            true => Ok(()),
+            false => Err(actual.to_string()),
+        }
+    }
+}
+
+// ─── Tests ───────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sha512_integrity() {
+        assert_eq!(
+            sha512_integrity(b"craft"),
+            "sha512-u+rFheHyfzuatBWoTGVUmjbz83t8exyix4jGL7iWxr4EGQGdGnhaRP10CbsE93jquj3Z+EqSLhg2G/IWaixXJQ=="
+        );
+    }
+
+    #[test]
+    fn test_strongest_algorithm() {
+        let sha512 = sha512_integrity(b"craft");
+        let sri = format!("sha1-abc= {} sha256-def=", sha512);
+
+        let integrity = Integrity::parse(&sri).unwrap();
+        assert_eq!(integrity.algorithm, Algorithm::Sha512);
+        assert_eq!(integrity.to_string(), sha512);
+        assert!(integrity.check(b"craft").is_ok());
+        assert_eq!(
+            integrity.check(b"tampered").unwrap_err().len(),
+            sha512.len()
+        );
+
+        assert_eq!(Integrity::parse("md5-abc"), None);
+    }
+
+    #[test]
+    fn test_shasum_fallback() {
+        let shasum = "3ea3ae2c24dfcf1ad3cb40b5a1ad6d1b4e6a4c8f";
+        let integrity = Integrity::for_package(None, shasum).unwrap();
+        assert_eq!(integrity.algorithm, Algorithm::Sha1);
+        assert_eq!(
+            hex::encode(STANDARD.decode(&integrity.digest).unwrap()),
+            shasum
+        );
+
+        let preferred = Integrity::for_package(Some(&sha512_integrity(b"")), shasum).unwrap();
+        assert_eq!(preferred.algorithm, Algorithm::Sha512);
+    }
+}
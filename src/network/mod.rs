@@ -1,3 +1,11 @@
+mod auth;
+mod client;
 mod http;
+mod integrity;
+mod policy;
+mod retry;
 
+pub use auth::Credentials;
 pub use http::Http;
+pub use integrity::{sha512_integrity, Integrity};
+pub use policy::FetchPolicy;
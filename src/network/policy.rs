@@ -0,0 +1,25 @@
+use crate::conf::NpmConfig;
+
+// ─── FetchPolicy ─────────────────────────────────────────────────────────────
+
+/// Whether metadata and tarballs may come from the network
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FetchPolicy {
+    Online,
+    /// Cached metadata is used whenever it satisfies the range
+    PreferOffline,
+    /// Only the local caches, anything missing fails the install
+    Offline,
+}
+
+impl FetchPolicy {
+    pub fn new(config: &NpmConfig) -> Self {
+        if config.offline {
+            Self::Offline
+        } else if config.prefer_offline {
+            Self::PreferOffline
+        } else {
+            Self::Online
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use std::time::{Duration, SystemTime};
+
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Response, StatusCode};
+
+use crate::conf::NpmConfig;
+
+// ─── RetryPolicy ─────────────────────────────────────────────────────────────
+
+/// When and how long to wait before repeating a failed request, from the `fetch-retry-*` settings
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub retries: u32,
+    factor: u32,
+    min_timeout: Duration,
+    max_timeout: Duration,
+}
+
+impl RetryPolicy {
+    pub fn new(config: &NpmConfig) -> Self {
+        let millis = |value: i32| Duration::from_millis(value.max(0) as u64);
+        Self {
+            retries: config.fetch_retries.max(0) as u32,
+            factor: config.fetch_retry_factor.max(1) as u32,
+            min_timeout: millis(config.fetch_retry_mintimeout),
+            max_timeout: millis(config.fetch_retry_maxtimeout),
+        }
+    }
+
+    /// Exponential backoff, `Retry-After` wins when the server sent one
+    pub fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        let backoff = self
+            .min_timeout
+            .saturating_mul(self.factor.saturating_pow(attempt));
+        retry_after.unwrap_or(backoff).min(self.max_timeout)
+    }
+
+    /// Overloaded or failing servers are worth asking again, anything else is final
+    pub fn is_retryable_status(status: StatusCode) -> bool {
+        status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+    }
+
+    /// Connection resets, timeouts and bodies cut short, which surface as decode errors
+    /// when the body is streamed
+    pub fn is_transient(error: &reqwest::Error) -> bool {
+        error.is_connect()
+            || error.is_timeout()
+            || error.is_request()
+            || error.is_body()
+            || error.is_decode()
+    }
+
+    /// `Retry-After` as seconds or as an HTTP date
+    pub fn retry_after(response: &Response) -> Option<Duration> {
+        let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?;
+        if let Ok(seconds) = value.trim().parse::<u64>() {
+            return Some(Duration::from_secs(seconds));
+        }
+        let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+        let at = SystemTime::UNIX_EPOCH + Duration::from_secs(date.timestamp().max(0) as u64);
+        Some(at.duration_since(SystemTime::now()).unwrap_or_default())
+    }
+}
+
+// ─── Tests ───────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delay() {
+        let config = NpmConfig::new(Default::default());
+        let policy = RetryPolicy::new(&config);
+
+        assert_eq!(policy.retries, 2);
+        assert_eq!(policy.delay(0, None), Duration::from_secs(10));
+        // 100s is capped by fetch-retry-maxtimeout
+        assert_eq!(policy.delay(1, None), Duration::from_secs(60));
+        assert_eq!(
+            policy.delay(0, Some(Duration::from_secs(3))),
+            Duration::from_secs(3)
+        );
+        assert!(RetryPolicy::is_retryable_status(StatusCode::BAD_GATEWAY));
+        assert!(RetryPolicy::is_retryable_status(
+            StatusCode::TOO_MANY_REQUESTS
+        ));
+        assert!(!RetryPolicy::is_retryable_status(StatusCode::NOT_FOUND));
+    }
+}
@@ -9,4 +9,7 @@ use super::NpmPackage;
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct FullPackage {
     pub versions: HashMap<String, NpmPackage>,
+    /// e.g. latest -> 4.17.21, next -> 5.0.0-rc.1
+    #[serde(rename = "dist-tags", default)]
+    pub dist_tags: HashMap<String, String>,
 }
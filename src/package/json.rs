@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 
 use serde::Deserialize;
+use serde_json::Value;
 
 // ─── PackageJson ─────────────────────────────────────────────────────────────
 
@@ -11,6 +12,19 @@ pub struct PackageJson {
     pub dev_dependencies: Option<HashMap<String, String>>,
     pub optional_dependencies: Option<HashMap<String, String>>,
     pub scripts: Option<HashMap<String, String>>,
+    pub overrides: Option<Value>,
+    pub resolutions: Option<HashMap<String, String>>,
+    pub pnpm: Option<PnpmSettings>,
+}
+
+/// Settings pnpm reads from the `pnpm` field of the root package.json
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PnpmSettings {
+    /// Only these dependencies may run lifecycle scripts
+    pub only_built_dependencies: Option<Vec<String>>,
+    /// These dependencies never run lifecycle scripts
+    pub never_built_dependencies: Option<Vec<String>>,
 }
 
 // ─────────────────────────────────────────────────────────────────────────────
@@ -2,6 +2,7 @@ mod full_package;
 mod git_package;
 mod json;
 mod npm_package;
+mod overrides;
 mod package_recorder;
 mod pkg;
 mod registry;
@@ -9,9 +10,12 @@ mod registry;
 pub use full_package::FullPackage;
 pub use json::PackageJson;
 pub use npm_package::BinType;
+pub use npm_package::Distribution;
 pub use npm_package::NpmPackage;
+pub use overrides::Overrides;
 pub use package_recorder::PackageMetaHandler;
 pub use package_recorder::PackageMetaRecorder;
 pub use package_recorder::PackageRecorder;
 pub use package_recorder::ResolvedBinary;
 pub use pkg::Package;
+pub use registry::Registry;
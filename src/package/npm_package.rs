@@ -1,5 +1,7 @@
 use crate::cache::RegistryKey;
-use crate::package::package_recorder::{PackageMetaRecorder, PackageResolution};
+use crate::lockfile::lockfile_structure::{IntegrityResolution, LockfileResolution};
+use crate::package::package_recorder::PackageMetaRecorder;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fmt::Display;
@@ -56,7 +58,7 @@ pub struct NpmPackage {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub optional_dependencies: Option<HashMap<String, String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub overrides: Option<HashMap<String, String>>,
+    pub overrides: Option<serde_json::Value>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub os: Option<Vec<String>>,
@@ -71,6 +73,7 @@ pub struct NpmPackage {
     pub depth_traces: Option<Vec<Vec<RegistryKey>>>,
 }
 
+#[allow(dead_code)]
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Funding {
     r#type: Option<String>,
@@ -98,9 +101,13 @@ impl From<NpmPackage> for PackageMetaRecorder {
             ..Default::default()
         };
 
-        if let Some(integrity) = val.dist.integrity {
-            meta_recoder.resolution = Some(PackageResolution { integrity })
-        }
+        meta_recoder.resolution = match val.dist.resolution {
+            Some(resolution) => Some(resolution),
+            None => val
+                .dist
+                .integrity
+                .map(|integrity| LockfileResolution::Integrity(IntegrityResolution { integrity })),
+        };
         if val.bin.is_some() {
             meta_recoder.has_bin = Some(true)
         }
@@ -170,6 +177,10 @@ pub struct Distribution {
 
     #[serde(rename = "unpackedSize")]
     pub unpacked_size: Option<u64>,
+
+    /// Where the package comes from when it is not a registry tarball
+    #[serde(skip)]
+    pub resolution: Option<LockfileResolution>,
 }
 
 impl Display for NpmPackage {
@@ -182,4 +193,33 @@ impl NpmPackage {
     pub fn contains_org(&self) -> bool {
         self.name.contains('/')
     }
+
+    /// Key of the package in the download cache, the unpack folder and the content store,
+    /// the source of git, tarball and local packages is part of the version
+    pub fn store_key(&self) -> RegistryKey {
+        RegistryKey::stored(&self.name, &self.version, self.dist.resolution.as_ref())
+    }
+
+    /// Reads a package.json that does not come from a registry, e.g. from a git repository.
+    /// Only the fields used for installing are kept, everything else is free form.
+    pub fn from_manifest(manifest: &str) -> Result<Self, serde_json::Error> {
+        let manifest: serde_json::Value = serde_json::from_str(manifest)?;
+        fn field<T: DeserializeOwned>(manifest: &serde_json::Value, key: &str) -> Option<T> {
+            serde_json::from_value(manifest.get(key)?.clone()).ok()
+        }
+
+        Ok(Self {
+            name: field(&manifest, "name").unwrap_or_default(),
+            version: field(&manifest, "version").unwrap_or_else(|| "0.0.0".to_string()),
+            dependencies: field(&manifest, "dependencies"),
+            peer_dependencies: field(&manifest, "peerDependencies"),
+            peer_dependencies_meta: field(&manifest, "peerDependenciesMeta"),
+            optional_dependencies: field(&manifest, "optionalDependencies"),
+            bin: field(&manifest, "bin"),
+            scripts: field(&manifest, "scripts"),
+            os: field(&manifest, "os"),
+            cpu: field(&manifest, "cpu"),
+            ..Default::default()
+        })
+    }
 }
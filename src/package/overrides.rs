@@ -0,0 +1,337 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use nodejs_semver::{Range, Version};
+use serde_json::{Map, Value};
+
+use super::{Package, PackageJson};
+use crate::actors::PackageType;
+use crate::cache::RegistryKey;
+
+const SELF_KEY: &str = ".";
+const REFERENCE_PREFIX: &str = "$";
+const SELECTOR_SEPARATOR: &str = ">";
+
+// ─── Selector ────────────────────────────────────────────────────────────────
+
+/// A package in an override path, e.g. `foo` or `foo@^2`
+#[derive(Debug, Clone, PartialEq)]
+struct Selector {
+    name: String,
+    range: Option<String>,
+}
+
+impl Selector {
+    fn parse(key: &str) -> Self {
+        let (name, range) = PackageType::Prod(key.to_string()).get_parts();
+        Self {
+            name,
+            range: (range != "*").then_some(range),
+        }
+    }
+
+    fn range(&self) -> Option<Range> {
+        self.range.as_ref().and_then(|r| r.parse().ok())
+    }
+
+    /// Matches a resolved ancestor
+    fn matches_key(&self, key: &RegistryKey) -> bool {
+        if self.name != key.name {
+            return false;
+        }
+        match (self.range(), key.version.parse::<Version>()) {
+            (Some(range), Ok(version)) => version.satisfies(&range),
+            _ => self.range.is_none(),
+        }
+    }
+
+    /// Matches a dependency that is about to be resolved
+    fn matches_package(&self, package: &Package) -> bool {
+        if self.name != package.name {
+            return false;
+        }
+        match (self.range(), package.raw_version.parse::<Range>()) {
+            (Some(range), Ok(wanted)) => range.allows_any(&wanted),
+            _ => self.range.is_none(),
+        }
+    }
+
+    fn to_key(&self) -> String {
+        match &self.range {
+            Some(range) => format!("{}@{}", self.name, range),
+            None => self.name.clone(),
+        }
+    }
+}
+
+// ─── Overrides ───────────────────────────────────────────────────────────────
+
+/// Versions forced by the root package.json, from npm `overrides` and yarn `resolutions`.
+/// A rule applies to a dependency reached through its parents in the given order,
+/// at any depth, and the most specific rule wins.
+#[derive(Debug, Clone, Default)]
+pub struct Overrides {
+    rules: Vec<(Vec<Selector>, String)>,
+}
+
+impl Overrides {
+    pub fn new(package_json: &PackageJson) -> Self {
+        let mut overrides = Self::default();
+
+        if let Some(Value::Object(map)) = &package_json.overrides {
+            overrides.collect(map, vec![], package_json);
+        }
+        for (key, version) in package_json.resolutions.iter().flatten() {
+            let path = Self::parse_resolution(key);
+            if !path.is_empty() {
+                overrides.add(path, Self::dereference(version, package_json));
+            }
+        }
+
+        overrides
+    }
+
+    /// npm allows nested objects, where `.` overrides the parent itself
+    fn collect(
+        &mut self,
+        map: &Map<String, Value>,
+        path: Vec<Selector>,
+        package_json: &PackageJson,
+    ) {
+        for (key, value) in map {
+            let mut path = path.clone();
+            if key != SELF_KEY {
+                path.extend(Self::split_selectors(key).into_iter().map(Selector::parse));
+            }
+
+            match value {
+                Value::String(version) if !path.is_empty() => {
+                    self.add(path, Self::dereference(version, package_json))
+                }
+                Value::Object(nested) => self.collect(nested, path, package_json),
+                _ => {}
+            }
+        }
+    }
+
+    /// Splits `parent>child` without breaking ranges like `foo@>=1`
+    fn split_selectors(key: &str) -> Vec<&str> {
+        let mut selectors = vec![];
+        let mut start = 0;
+
+        for (index, _) in key.match_indices(SELECTOR_SEPARATOR) {
+            let previous = key[..index].chars().last();
+            let next = key[index + 1..].chars().next();
+            if matches!(previous, Some('@' | ' ')) || next == Some('=') {
+                continue;
+            }
+            selectors.push(key[start..index].trim());
+            start = index + 1;
+        }
+        selectors.push(key[start..].trim());
+
+        selectors
+    }
+
+    /// Yarn paths look like `**/foo`, `bar/foo` or `@scope/bar/**/foo`
+    fn parse_resolution(key: &str) -> Vec<Selector> {
+        let mut path = vec![];
+        let mut parts = key.split('/');
+
+        while let Some(part) = parts.next() {
+            match part {
+                "**" | "" => continue,
+                scope if scope.starts_with('@') => {
+                    let name = format!("{}/{}", scope, parts.next().unwrap_or_default());
+                    path.push(Selector::parse(&name));
+                }
+                name => path.push(Selector::parse(name)),
+            }
+        }
+
+        path
+    }
+
+    /// `$foo` points at the version of foo in the root package.json
+    fn dereference(version: &str, package_json: &PackageJson) -> String {
+        let Some(name) = version.strip_prefix(REFERENCE_PREFIX) else {
+            return version.to_string();
+        };
+
+        [
+            &package_json.dependencies,
+            &package_json.dev_dependencies,
+            &package_json.optional_dependencies,
+        ]
+        .into_iter()
+        .flatten()
+        .find_map(|deps| deps.get(name).cloned())
+        .unwrap_or_else(|| version.to_string())
+    }
+
+    fn add(&mut self, path: Vec<Selector>, version: String) {
+        self.rules.retain(|(existing, _)| *existing != path);
+        self.rules.push((path, version));
+    }
+
+    /// Whether the ancestors contain the parent selectors in order
+    fn matches_parents(selectors: &[Selector], parents: &[RegistryKey]) -> bool {
+        let mut parents = parents.iter();
+        selectors
+            .iter()
+            .all(|selector| parents.any(|parent| selector.matches_key(parent)))
+    }
+
+    /// The version forced for a dependency reached through `parents`, root first
+    pub fn version_for(&self, package: &Package, parents: &[RegistryKey]) -> Option<&str> {
+        self.rules
+            .iter()
+            .filter(|(path, _)| {
+                let (target, selectors) = path.split_last().unwrap();
+                target.matches_package(package) && Self::matches_parents(selectors, parents)
+            })
+            .max_by_key(|(path, _)| path.len())
+            .map(|(_, version)| version.as_str())
+    }
+
+    /// The overrides as recorded in craft-lock.yaml, e.g. `bar>foo: 1.0.0`
+    pub fn to_lockfile(&self) -> Option<HashMap<String, String>> {
+        let recorded: HashMap<String, String> = self
+            .rules
+            .iter()
+            .map(|(path, version)| {
+                let key: Vec<String> = path.iter().map(Selector::to_key).collect();
+                (key.join(SELECTOR_SEPARATOR), version.clone())
+            })
+            .collect();
+
+        (!recorded.is_empty()).then_some(recorded)
+    }
+
+    /// Names of the packages whose override differs from the one recorded in the lockfile
+    pub fn changed_since(&self, locked: Option<&HashMap<String, String>>) -> HashSet<String> {
+        let current: BTreeMap<_, _> = self.to_lockfile().unwrap_or_default().into_iter().collect();
+        let locked: BTreeMap<_, _> = locked.cloned().unwrap_or_default().into_iter().collect();
+
+        current
+            .iter()
+            .filter(|(key, version)| locked.get(*key) != Some(version))
+            .chain(locked.iter().filter(|(key, _)| !current.contains_key(*key)))
+            .filter_map(|(key, _)| {
+                let target = *Self::split_selectors(key).last()?;
+                Some(Selector::parse(target).name)
+            })
+            .collect()
+    }
+}
+
+// ─── Tests ───────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overrides(package_json: &str) -> Overrides {
+        Overrides::new(&package_json.to_string().into())
+    }
+
+    fn key(name: &str, version: &str) -> RegistryKey {
+        RegistryKey {
+            name: name.to_string(),
+            version: version.to_string(),
+        }
+    }
+
+    fn forced(overrides: &Overrides, spec: &str, parents: &[RegistryKey]) -> Option<String> {
+        let package = Package::new(PackageType::Prod(spec.to_string()));
+        overrides.version_for(&package, parents).map(str::to_string)
+    }
+
+    #[test]
+    fn test_npm_overrides() {
+        let overrides = overrides(
+            r#"{
+                "dependencies": { "minimist": "^1.2.8" },
+                "overrides": {
+                    "semver": "7.5.4",
+                    "qs@^6": "6.11.0",
+                    "webpack": { ".": "5.0.0", "minimist": "$minimist" },
+                    "jest>glob": "7.2.3",
+                    "ws@>=7 <7.5.10": "7.5.10"
+                }
+            }"#,
+        );
+        let app = [key("app", "1.0.0")];
+
+        assert_eq!(
+            forced(&overrides, "semver@^5", &app).as_deref(),
+            Some("7.5.4")
+        );
+        assert_eq!(
+            forced(&overrides, "qs@^6.5.0", &app).as_deref(),
+            Some("6.11.0")
+        );
+        assert_eq!(forced(&overrides, "qs@^5", &app), None);
+        assert_eq!(
+            forced(&overrides, "webpack@^4", &app).as_deref(),
+            Some("5.0.0")
+        );
+
+        assert_eq!(
+            forced(&overrides, "ws@^7.4.0", &app).as_deref(),
+            Some("7.5.10")
+        );
+
+        let under_webpack = [key("app", "1.0.0"), key("webpack", "5.0.0")];
+        assert_eq!(
+            forced(&overrides, "minimist@^1.0.0", &under_webpack).as_deref(),
+            Some("^1.2.8")
+        );
+        assert_eq!(forced(&overrides, "minimist@^1.0.0", &app), None);
+
+        let under_jest = [key("jest", "29.0.0"), key("expect", "29.0.0")];
+        assert_eq!(
+            forced(&overrides, "glob@^7", &under_jest).as_deref(),
+            Some("7.2.3")
+        );
+        assert_eq!(forced(&overrides, "glob@^7", &app), None);
+
+        let recorded = overrides.to_lockfile().unwrap();
+        assert_eq!(recorded["webpack>minimist"], "^1.2.8");
+        assert_eq!(recorded["qs@^6"], "6.11.0");
+    }
+
+    #[test]
+    fn test_yarn_resolutions() {
+        let overrides = overrides(
+            r#"{ "resolutions": { "**/lodash": "4.17.21", "@babel/core/@babel/types": "7.20.0" } }"#,
+        );
+
+        assert_eq!(
+            forced(&overrides, "lodash@^4.0.0", &[key("a", "1.0.0")]).as_deref(),
+            Some("4.17.21")
+        );
+        let babel = [key("@babel/core", "7.20.0")];
+        assert_eq!(
+            forced(&overrides, "@babel/types@^7", &babel).as_deref(),
+            Some("7.20.0")
+        );
+        assert_eq!(forced(&overrides, "@babel/types@^7", &[]), None);
+    }
+
+    #[test]
+    fn test_changed_since() {
+        let overrides = overrides(r#"{ "overrides": { "semver": "7.5.4", "a>qs": "6.11.0" } }"#);
+        let locked = HashMap::from([
+            ("semver".to_string(), "7.5.2".to_string()),
+            ("a>qs".to_string(), "6.11.0".to_string()),
+            ("@scope/old".to_string(), "1.0.0".to_string()),
+        ]);
+
+        let mut changed: Vec<_> = overrides.changed_since(Some(&locked)).into_iter().collect();
+        changed.sort();
+        assert_eq!(changed, vec!["@scope/old", "semver"]);
+        assert!(overrides
+            .changed_since(overrides.to_lockfile().as_ref())
+            .is_empty());
+    }
+}
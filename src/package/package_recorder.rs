@@ -1,5 +1,6 @@
 use crate::cache::{RegistryKey, DEP_CACHE_FOLDER};
 use crate::fs::get_config_dir;
+use crate::lockfile::lockfile_structure::LockfileResolution;
 use crate::package::npm_package::PeerDependencyMeta;
 use crate::package::BinType;
 use serde::{Deserialize, Serialize};
@@ -11,7 +12,7 @@ use std::path::PathBuf;
 pub struct PackageMetaRecorder {
     pub name: String,
     pub version: String,
-    pub resolution: Option<PackageResolution>,
+    pub resolution: Option<LockfileResolution>,
     pub peer_dependencies: Option<HashMap<String, String>>,
     pub has_bin: Option<bool>,
     pub peer_dependencies_meta: Option<HashMap<String, PeerDependencyMeta>>,
@@ -22,6 +23,8 @@ pub struct PackageMetaRecorder {
     pub bin: Option<BinType>,
     pub depth_traces: Option<Vec<Vec<RegistryKey>>>,
     pub resolved_binaries: Option<Vec<ResolvedBinary>>,
+    /// Installed as an optional dependency of the project
+    pub optional: bool,
 }
 
 #[derive(Clone, Default, Debug, Serialize, Deserialize)]
@@ -33,8 +36,9 @@ pub struct ResolvedBinary {
 
 impl PackageMetaRecorder {
     pub fn resolve_path_to_package(&self) -> PathBuf {
+        let key = RegistryKey::stored(&self.name, &self.version, self.resolution.as_ref());
         get_config_dir(DEP_CACHE_FOLDER.clone())
-            .join(format!("{}-{}", self.name, self.version))
+            .join(format!("{}-{}", key.name, key.version))
             .join("package")
     }
 }
@@ -65,7 +69,7 @@ impl From<PackageMetaRecorder> for PackageMetaHandler {
 #[serde(rename_all = "camelCase")]
 pub struct PackageMetaHandler {
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub resolution: Option<PackageResolution>,
+    pub resolution: Option<LockfileResolution>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub peer_dependencies: Option<HashMap<String, String>>,
@@ -85,11 +89,6 @@ pub struct PackageMetaHandler {
     pub bin: Option<BinType>,
 }
 
-#[derive(Clone, Default, Debug, Deserialize, Serialize)]
-pub struct PackageResolution {
-    pub integrity: String,
-}
-
 #[derive(Clone, Default, Debug)]
 pub struct PackageRecorder {
     pub main_packages: HashMap<RegistryKey, PackageMetaRecorder>,
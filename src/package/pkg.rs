@@ -4,6 +4,8 @@ use crate::cache::RegistryKey;
 use nodejs_semver::Range;
 use std::fmt::Display;
 
+pub const NPM_ALIAS_PREFIX: &str = "npm:";
+
 // ─── Package ───────────────────────────────────────────────────────────────────
 
 #[derive(Debug, Clone)]
@@ -12,6 +14,8 @@ pub struct Package {
     pub registry: Registry,
     pub raw_version: String,
     pub package_type: PackageType,
+    /// Folder name in node_modules for `alias@npm:name@range` dependencies
+    pub alias: Option<String>,
 }
 
 impl From<Package> for RegistryKey {
@@ -35,21 +39,64 @@ impl Display for Package {
 
 impl Package {
     pub(crate) fn satisfies(&self, version: &str) -> bool {
-        let range: Range = self.raw_version.parse().unwrap();
-        let version: nodejs_semver::Version = version.parse().unwrap();
+        let (Ok(range), Ok(version)) = (
+            self.raw_version.parse::<Range>(),
+            version.parse::<nodejs_semver::Version>(),
+        ) else {
+            return false;
+        };
         version.satisfies(&range)
     }
 
+    /// The dist-tag the specifier points at, e.g. `next` for react@next.
+    /// A bare `*` follows the configured default tag, like npm does.
+    pub fn dist_tag<'a>(&'a self, default_tag: &'a str) -> Option<&'a str> {
+        match self.raw_version.as_str() {
+            "*" | "" => Some(default_tag),
+            version if version.parse::<Range>().is_err() => Some(version),
+            _ => None,
+        }
+    }
+
     pub fn new(package: PackageType) -> Self {
-        let binding = package.get_parts();
+        let mut binding = package.get_parts();
+        let mut alias = None;
+        if let Some(target) = binding.1.strip_prefix(NPM_ALIAS_PREFIX) {
+            let target = PackageType::Prod(target.to_string()).get_parts();
+            let name = std::mem::replace(&mut binding, target).0;
+            // `craft install npm:lodash@^4` has nothing to alias
+            alias = (!name.starts_with(NPM_ALIAS_PREFIX)).then_some(name);
+        }
+        let registry = if Registry::is_local(&binding.1) {
+            Registry::Local
+        } else if Registry::is_tarball_url(&binding.1) {
+            Registry::Tarball
+        } else if Registry::is_git(&binding.1) {
+            Registry::Git
+        } else {
+            Registry::Npm
+        };
 
         Self {
             name: binding.0,
-            registry: Registry::Npm,
+            registry,
             raw_version: binding.1,
             package_type: package,
+            alias,
         }
     }
+
+    /// The same dependency pointing at another version, used to apply overrides
+    pub fn with_version(&self, version: &str) -> Self {
+        let mut package = Self::new(PackageType::Prod(format!("{}@{}", self.name, version)));
+        package.alias = self.alias.clone().or(package.alias);
+        package
+    }
+
+    /// The specifier as written in package.json, e.g. npm:lodash@^4 for an alias
+    pub fn specifier(&self) -> String {
+        self.package_type.get_parts().1
+    }
 }
 
 /*impl From<NpmPackage> for Package {
@@ -85,5 +132,37 @@ mod tests {
         assert_eq!(package.name, "lodash");
         assert_eq!(package.registry, Registry::Npm);
         assert_eq!(package.raw_version, "*");
+
+        let package = Package::new(PackageType::Prod("lib@github:owner/lib#v1".to_string()));
+        assert_eq!(package.name, "lib");
+        assert_eq!(package.registry, Registry::Git);
+        assert_eq!(package.raw_version, "github:owner/lib#v1");
+
+        let package = Package::new(PackageType::Prod("shared@file:../shared".to_string()));
+        assert_eq!(package.name, "shared");
+        assert_eq!(package.registry, Registry::Local);
+        assert_eq!(package.raw_version, "file:../shared");
+
+        let package = Package::new(PackageType::Prod(
+            "lib@https://example.com/lib-1.2.3.tgz".to_string(),
+        ));
+        assert_eq!(package.name, "lib");
+        assert_eq!(package.registry, Registry::Tarball);
+
+        let package = Package::new(PackageType::Prod("lodash4@npm:lodash@^4".to_string()));
+        assert_eq!(package.name, "lodash");
+        assert_eq!(package.raw_version, "^4");
+        assert_eq!(package.alias.as_deref(), Some("lodash4"));
+        assert_eq!(package.specifier(), "npm:lodash@^4");
+    }
+
+    #[test]
+    fn test_dist_tag() {
+        let package = |spec: &str| Package::new(PackageType::Prod(format!("react@{}", spec)));
+
+        assert_eq!(package("next").dist_tag("latest"), Some("next"));
+        assert_eq!(package("*").dist_tag("beta"), Some("beta"));
+        assert_eq!(package("^18.2.0").dist_tag("latest"), None);
+        assert!(!package("next").satisfies("18.2.0"));
     }
 }
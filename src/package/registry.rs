@@ -6,11 +6,35 @@ use std::fmt::Display;
 pub enum Registry {
     Npm,
     Git,
+    Local,
+    Tarball,
 }
 
 // ─────────────────────────────────────────────────────────────────────────────
 
 impl Registry {
+    /// file:, link: and relative or absolute paths to a directory or a .tgz
+    pub fn is_local(version: &str) -> bool {
+        let local_prefixes = ["file:", "link:", "./", "../", "/", "~/"];
+
+        local_prefixes
+            .iter()
+            .any(|&prefix| version.starts_with(prefix))
+    }
+
+    /// http(s) URL of a .tgz, checked before `is_git` which also accepts http prefixes
+    pub fn is_tarball_url(version: &str) -> bool {
+        let Some(url) = ["http://", "https://"]
+            .iter()
+            .find_map(|scheme| version.strip_prefix(scheme))
+        else {
+            return false;
+        };
+        let path = url.split(['?', '#']).next().unwrap_or_default();
+
+        path.ends_with(".tgz") || path.ends_with(".tar.gz")
+    }
+
     pub fn is_git(version: &str) -> bool {
         let git_prefixes = [
             "git+",
@@ -39,6 +63,8 @@ impl Display for Registry {
         let str = match self {
             Registry::Npm => "npm".to_string(),
             Registry::Git => "git".to_string(),
+            Registry::Local => "local".to_string(),
+            Registry::Tarball => "tarball".to_string(),
         };
         write!(f, "{}", str)
     }
@@ -74,9 +100,36 @@ mod tests {
         assert!(!Registry::is_git("> 2"));
     }
 
+    #[test]
+    fn test_is_local() {
+        assert!(Registry::is_local("file:../shared"));
+        assert!(Registry::is_local("link:../shared"));
+        assert!(Registry::is_local("./vendor/x.tgz"));
+        assert!(Registry::is_local("/opt/packages/x"));
+
+        assert!(!Registry::is_local("^1.0.0"));
+        assert!(!Registry::is_local("github:owner/repo"));
+    }
+
+    #[test]
+    fn test_is_tarball_url() {
+        assert!(Registry::is_tarball_url(
+            "https://example.com/lib-1.2.3.tgz"
+        ));
+        assert!(Registry::is_tarball_url(
+            "http://localhost:8080/lib.tar.gz?token=1"
+        ));
+
+        assert!(!Registry::is_tarball_url("https://github.com/owner/repo"));
+        assert!(!Registry::is_tarball_url("git+https://example.com/lib.tgz"));
+        assert!(!Registry::is_tarball_url("./lib.tgz"));
+    }
+
     #[test]
     fn test_to_string() {
         assert_eq!(Registry::Npm.to_string(), "npm");
         assert_eq!(Registry::Git.to_string(), "git");
+        assert_eq!(Registry::Local.to_string(), "local");
+        assert_eq!(Registry::Tarball.to_string(), "tarball");
     }
 }
@@ -1,9 +1,12 @@
 use std::path::PathBuf;
 
+use crate::cache::RegistryKey;
+
 // ─────────────────────────────────────────────────────────────────────────────
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct LinkArtifactItem {
+    pub key: RegistryKey,
     pub to: PathBuf,
     pub from: PathBuf,
 }
@@ -11,7 +14,7 @@ pub struct LinkArtifactItem {
 // ─────────────────────────────────────────────────────────────────────────────
 
 impl LinkArtifactItem {
-    pub fn new(from: PathBuf, to: PathBuf) -> Self {
-        Self { from, to }
+    pub fn new(key: RegistryKey, from: PathBuf, to: PathBuf) -> Self {
+        Self { key, from, to }
     }
 }
@@ -2,7 +2,10 @@ use std::collections::HashMap;
 
 use crate::actors::PackageType;
 use crate::cache::RegistryKey;
-use crate::{contracts::PipeArtifact, package::NpmPackage};
+use crate::{
+    contracts::PipeArtifact,
+    package::{NpmPackage, Package},
+};
 // --------------------------------------------------------------------------------
 
 #[derive(Debug, Clone)]
@@ -39,6 +42,19 @@ impl ResolvedItem {
         }
     }
 
+    /// Aliases get their own entry, so `lodash` and `lodash4@npm:lodash` can both be linked
+    pub fn artifact_key(package: &NpmPackage, alias: Option<&str>) -> String {
+        match alias {
+            Some(alias) => format!("{}@npm:{}", alias, package),
+            None => package.to_string(),
+        }
+    }
+
+    /// Folder name of an `alias@npm:name@range` dependency
+    pub fn alias(&self) -> Option<String> {
+        Package::new(self.package_type.clone()).alias
+    }
+
     #[cfg(test)]
     pub fn with_no_parent(
         package: NpmPackage,
@@ -62,7 +78,12 @@ impl ResolveArtifacts {
         self.packages.get(key)
     }
 
+    /// A direct dependency keeps its entry when the same version is also pulled in transitively
     pub fn insert(&mut self, key: String, value: ResolvedItem) {
+        let direct = self.packages.get(&key).is_some_and(|p| p.parent.is_none());
+        if direct && value.parent.is_some() {
+            return;
+        }
         self.packages.insert(key, value);
     }
 }
@@ -141,4 +162,38 @@ mod tests {
 
         assert_eq!(resolve_artifacts.get_artifacts().len(), 1);
     }
+
+    #[test]
+    fn test_keep_direct_dependency() {
+        let mut resolve_artifacts = ResolveArtifacts::new();
+        let package = NpmPackage {
+            name: "c".to_string(),
+            version: "2.0.0".to_string(),
+            ..Default::default()
+        };
+        let parent = RegistryKey {
+            name: "y".to_string(),
+            version: "1.0.0".to_string(),
+        };
+
+        resolve_artifacts.insert(
+            package.to_string(),
+            ResolvedItem::with_no_parent(
+                package.clone(),
+                "^2.0.0".to_string(),
+                PackageType::Prod("c@^2.0.0".to_string()),
+            ),
+        );
+        resolve_artifacts.insert(
+            package.to_string(),
+            ResolvedItem::new(
+                package.clone(),
+                Some(vec![parent]),
+                "^2.0.0".to_string(),
+                PackageType::Prod("c@^2.0.0".to_string()),
+            ),
+        );
+
+        assert!(resolve_artifacts.get("c@2.0.0").unwrap().parent.is_none());
+    }
 }
@@ -0,0 +1,514 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc::Sender,
+};
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use tokio::process::Command;
+
+use super::artifacts::LinkArtifactItem;
+use crate::{
+    actors::ScriptEnv,
+    cache::RegistryKey,
+    conf::NpmConfig,
+    contracts::{Logger, Phase, Pipe, ProgressAction},
+    errors::ExecutionError,
+    fs::detach_dir,
+    lockfile::lockfile_structure::LockfileStructure,
+    logger::CraftLogger,
+    package::PackageRecorder,
+};
+
+/// Scripts run by an install, in this order
+const LIFECYCLE_SCRIPTS: [&str; 3] = ["preinstall", "install", "postinstall"];
+
+/// What npm runs for native addons that do not bring their own install script
+const NODE_GYP_REBUILD: &str = "node-gyp rebuild";
+
+/// Written into a package folder once its scripts succeeded, holds the built `name@version`
+const BUILT_MARKER: &str = ".craft-built";
+
+// ─── BuildPolicy ─────────────────────────────────────────────────────────────
+
+/// Which dependencies may run their lifecycle scripts, from `ignore-scripts` and the
+/// `onlyBuiltDependencies` / `neverBuiltDependencies` recorded in the lockfile
+#[derive(Debug, Clone, Default)]
+pub struct BuildPolicy {
+    ignore_scripts: bool,
+    only_built: Option<HashSet<String>>,
+    never_built: HashSet<String>,
+}
+
+impl BuildPolicy {
+    pub fn new(config: &NpmConfig, lockfile: Option<&LockfileStructure>) -> Self {
+        let list = |list: Option<&Vec<String>>| list.map(|l| l.iter().cloned().collect());
+
+        Self {
+            ignore_scripts: config.ignore_scripts,
+            only_built: list(lockfile.and_then(|l| l.only_built_dependencies.as_ref())),
+            never_built: list(lockfile.and_then(|l| l.never_built_dependencies.as_ref()))
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn allows(&self, name: &str) -> bool {
+        if self.ignore_scripts || self.never_built.contains(name) {
+            return false;
+        }
+        self.only_built
+            .as_ref()
+            .is_none_or(|only_built| only_built.contains(name))
+    }
+}
+
+// ─── BuilderPipe ─────────────────────────────────────────────────────────────
+
+#[derive(Debug, Default, Deserialize)]
+struct Manifest {
+    scripts: Option<HashMap<String, String>>,
+}
+
+/// Runs the install scripts of dependencies once they are linked,
+/// dependencies are built before the packages depending on them
+pub struct BuilderPipe {
+    tx: Sender<ProgressAction>,
+    linked: Vec<LinkArtifactItem>,
+    recorder: PackageRecorder,
+    policy: BuildPolicy,
+    script_env: ScriptEnv,
+}
+
+impl BuilderPipe {
+    pub fn new(
+        tx: Sender<ProgressAction>,
+        linked: Vec<LinkArtifactItem>,
+        recorder: PackageRecorder,
+        policy: BuildPolicy,
+        script_env: ScriptEnv,
+    ) -> Self {
+        Self {
+            tx,
+            linked,
+            recorder,
+            policy,
+            script_env,
+        }
+    }
+
+    /// Every package after its dependencies, cycles are broken where they are found
+    fn build_order(&self) -> Vec<RegistryKey> {
+        let dependencies: HashMap<&RegistryKey, Vec<RegistryKey>> = self
+            .recorder
+            .main_packages
+            .iter()
+            .chain(self.recorder.sub_dependencies.iter())
+            .map(|(key, meta)| {
+                let resolved = meta.resolved_dependencies.iter().flatten();
+                let dependencies = resolved
+                    .map(|(name, version)| RegistryKey {
+                        name: name.clone(),
+                        version: version.clone(),
+                    })
+                    .collect();
+                (key, dependencies)
+            })
+            .collect();
+
+        fn visit(
+            key: &RegistryKey,
+            dependencies: &HashMap<&RegistryKey, Vec<RegistryKey>>,
+            visited: &mut HashSet<RegistryKey>,
+            order: &mut Vec<RegistryKey>,
+        ) {
+            if !visited.insert(key.clone()) {
+                return;
+            }
+            for dependency in dependencies.get(key).into_iter().flatten() {
+                visit(dependency, dependencies, visited, order);
+            }
+            order.push(key.clone());
+        }
+
+        let mut keys: Vec<&RegistryKey> = self.linked.iter().map(|l| &l.key).collect();
+        keys.sort_by_key(|key| key.to_string());
+
+        let mut visited = HashSet::new();
+        let mut order = vec![];
+        for key in keys {
+            visit(key, &dependencies, &mut visited, &mut order);
+        }
+        order
+    }
+
+    /// Whether the package at `dir` was already built by an earlier install
+    pub fn is_built(key: &RegistryKey, dir: &Path) -> bool {
+        fs::read_to_string(dir.join(BUILT_MARKER)).is_ok_and(|built| built == key.to_string())
+    }
+
+    /// Installed only for optional dependencies of the project, like npm a failing build
+    /// then removes the package instead of failing the install
+    fn is_optional(&self, key: &RegistryKey) -> bool {
+        let main = &self.recorder.main_packages;
+        if let Some(meta) = main.get(key) {
+            return meta.optional;
+        }
+
+        let traces = self
+            .recorder
+            .sub_dependencies
+            .get(key)
+            .and_then(|meta| meta.depth_traces.as_ref());
+        traces.is_some_and(|traces| {
+            !traces.is_empty()
+                && traces.iter().all(|trace| {
+                    trace
+                        .first()
+                        .and_then(|root| main.get(root))
+                        .is_some_and(|meta| meta.optional)
+                })
+        })
+    }
+
+    /// Removes every linked copy of a package whose build failed, with the top level
+    /// links and `.bin` shims pointing at it
+    fn remove_failed(&self, key: &RegistryKey) -> std::io::Result<()> {
+        for linked in self.linked.iter().filter(|linked| &linked.key == key) {
+            match fs::symlink_metadata(&linked.to) {
+                Ok(meta) if meta.is_dir() => fs::remove_dir_all(&linked.to)?,
+                Ok(_) => fs::remove_file(&linked.to)?,
+                Err(_) => continue,
+            }
+
+            // The project node_modules, isolated packages live deeper in its store
+            let Some(node_modules) = linked
+                .to
+                .ancestors()
+                .filter(|dir| dir.file_name().is_some_and(|name| name == "node_modules"))
+                .last()
+            else {
+                continue;
+            };
+
+            let top = node_modules.join(&key.name);
+            let dangling = fs::symlink_metadata(&top).is_ok() && fs::metadata(&top).is_err();
+            if dangling {
+                fs::remove_file(&top)?;
+            }
+
+            let bin_dir = node_modules.join(".bin");
+            let Ok(entries) = fs::read_dir(&bin_dir) else {
+                continue;
+            };
+            // Shims of other packages may use the same name, only those running this one go
+            let target = format!("\"$basedir/../{}/", key.name);
+            for entry in entries {
+                let name = entry?.file_name().to_string_lossy().to_string();
+                let shim = bin_dir.join(&name);
+                if name.ends_with(".CMD") || name.ends_with(".ps1") {
+                    continue;
+                }
+                if fs::read_to_string(&shim).is_ok_and(|shim| shim.contains(&target)) {
+                    for shim in [
+                        name.clone(),
+                        format!("{}.CMD", name),
+                        format!("{}.ps1", name),
+                    ] {
+                        let _ = fs::remove_file(bin_dir.join(shim));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The lifecycle scripts a linked package defines, in the order they run
+    fn scripts(dir: &Path) -> Vec<(&'static str, String)> {
+        let manifest: Manifest = fs::read_to_string(dir.join("package.json"))
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        let mut scripts = manifest.scripts.unwrap_or_default();
+
+        let has_install = scripts.contains_key("install") || scripts.contains_key("preinstall");
+        if !has_install && dir.join("binding.gyp").exists() {
+            scripts.insert("install".to_string(), NODE_GYP_REBUILD.to_string());
+        }
+
+        LIFECYCLE_SCRIPTS
+            .iter()
+            .filter_map(|event| Some((*event, scripts.remove(*event)?)))
+            .collect()
+    }
+
+    async fn run_script(
+        &self,
+        key: &RegistryKey,
+        dir: &Path,
+        event: &str,
+        script: &str,
+    ) -> Result<(), ExecutionError> {
+        CraftLogger::verbose(format!("Running {} of {}: {}", event, key, script));
+
+        let output = Command::from(self.script_env.command(dir, event, script))
+            .output()
+            .await
+            .map_err(|e| {
+                ExecutionError::ScriptFailed(key.to_string(), event.to_string(), e.to_string())
+            })?;
+
+        if output.status.success() {
+            return Ok(());
+        }
+
+        // Scripts run quietly, their output only matters when they fail
+        for stream in [&output.stdout, &output.stderr] {
+            for line in String::from_utf8_lossy(stream).lines() {
+                CraftLogger::error(format!("{}: {}", key, line));
+            }
+        }
+        Err(ExecutionError::ScriptFailed(
+            key.to_string(),
+            event.to_string(),
+            output.status.to_string(),
+        ))
+    }
+
+    async fn build(&self, key: &RegistryKey, dir: &Path) -> Result<(), ExecutionError> {
+        let scripts = Self::scripts(dir);
+        if scripts.is_empty() || Self::is_built(key, dir) {
+            return Ok(());
+        }
+
+        // Local directories are linked from their source and built in place
+        let is_link = fs::symlink_metadata(dir).is_ok_and(|meta| meta.is_symlink());
+        if !is_link {
+            detach_dir(dir).map_err(|e| {
+                ExecutionError::ScriptFailed(key.to_string(), "install".to_string(), e.to_string())
+            })?;
+        }
+
+        for (event, script) in scripts {
+            self.run_script(key, dir, event, &script).await?;
+        }
+
+        // The source of a local directory is left alone, it is built on every install
+        if !is_link {
+            fs::write(dir.join(BUILT_MARKER), key.to_string()).map_err(|e| {
+                ExecutionError::ScriptFailed(key.to_string(), "install".to_string(), e.to_string())
+            })?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Pipe<()> for BuilderPipe {
+    async fn run(&mut self) -> Result<(), ExecutionError> {
+        let mut locations: HashMap<&RegistryKey, Vec<&PathBuf>> = HashMap::new();
+        for linked in &self.linked {
+            if self.policy.allows(&linked.key.name) {
+                locations.entry(&linked.key).or_default().push(&linked.to);
+            }
+        }
+
+        let has_scripts = locations.iter().any(|(key, dirs)| {
+            dirs.iter()
+                .any(|dir| !Self::scripts(dir).is_empty() && !Self::is_built(key, dir))
+        });
+        if !has_scripts {
+            return Ok(());
+        }
+        let _ = self.tx.send(ProgressAction::new(Phase::Building));
+
+        for key in self.build_order() {
+            for dir in locations.get(&key).into_iter().flatten() {
+                match self.build(&key, dir).await {
+                    Err(e) if self.is_optional(&key) => {
+                        CraftLogger::warn(format!("Optional dependency {} failed: {}", key, e));
+                        if let Err(e) = self.remove_failed(&key) {
+                            CraftLogger::warn(format!("Failed to remove {}: {}", key, e));
+                        }
+                        break;
+                    }
+                    result => result?,
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// ─── Tests ───────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::symlink_dir;
+    use crate::package::PackageMetaRecorder;
+    use crate::pipeline::binary_templates::get_bash_script;
+
+    fn key(name: &str) -> RegistryKey {
+        RegistryKey {
+            name: name.to_string(),
+            version: "1.0.0".to_string(),
+        }
+    }
+
+    fn package(root: &Path, name: &str, scripts: &str) -> LinkArtifactItem {
+        let dir = root.join("node_modules").join(name);
+        fs::create_dir_all(&dir).unwrap();
+        let manifest = format!(r#"{{"name":"{}","scripts":{{{}}}}}"#, name, scripts);
+        fs::write(dir.join("package.json"), manifest).unwrap();
+        LinkArtifactItem::new(key(name), PathBuf::new(), dir)
+    }
+
+    #[test]
+    fn test_build_policy() {
+        let mut lockfile = LockfileStructure {
+            never_built_dependencies: Some(vec!["husky".to_string()]),
+            ..Default::default()
+        };
+        let config = NpmConfig::new(Default::default());
+
+        let policy = BuildPolicy::new(&config, Some(&lockfile));
+        assert!(policy.allows("esbuild"));
+        assert!(!policy.allows("husky"));
+
+        lockfile.only_built_dependencies = Some(vec!["esbuild".to_string()]);
+        let policy = BuildPolicy::new(&config, Some(&lockfile));
+        assert!(policy.allows("esbuild"));
+        assert!(!policy.allows("sharp"));
+
+        let config =
+            NpmConfig::new([("ignore-scripts".to_string(), Some("true".to_string()))].into());
+        assert!(!BuildPolicy::new(&config, None).allows("esbuild"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_build_in_order() {
+        let root = tempfile::tempdir().unwrap();
+        let log = root.path().join("log");
+        let append =
+            |text: &str| format!("echo {} $npm_lifecycle_event >> {}", text, log.display());
+
+        let linked = vec![
+            package(
+                root.path(),
+                "app-lib",
+                &format!(r#""postinstall":"{}""#, append("app-lib")),
+            ),
+            package(
+                root.path(),
+                "native",
+                &format!(
+                    r#""preinstall":"{}","install":"{}""#,
+                    append("native"),
+                    append("native")
+                ),
+            ),
+            package(root.path(), "plain", ""),
+        ];
+
+        let mut recorder = PackageRecorder::default();
+        let meta = PackageMetaRecorder {
+            resolved_dependencies: Some([("native".to_string(), "1.0.0".to_string())].into()),
+            ..Default::default()
+        };
+        recorder.main_packages.insert(key("app-lib"), meta);
+
+        let script_env = ScriptEnv::new(&NpmConfig::new(Default::default()));
+        let build = |linked: Vec<LinkArtifactItem>, recorder: PackageRecorder| {
+            let (tx, _rx) = std::sync::mpsc::channel();
+            let mut pipe = BuilderPipe::new(
+                tx,
+                linked,
+                recorder,
+                BuildPolicy::default(),
+                script_env.clone(),
+            );
+            async move { pipe.run().await }
+        };
+        build(linked.clone(), recorder.clone()).await.unwrap();
+        assert_eq!(
+            fs::read_to_string(&log).unwrap(),
+            "native preinstall\nnative install\napp-lib postinstall\n"
+        );
+
+        // Built packages are skipped by later installs
+        fs::remove_file(&log).unwrap();
+        build(linked, recorder).await.unwrap();
+        assert!(!log.exists());
+
+        let failing = vec![package(root.path(), "broken", r#""install":"exit 3""#)];
+        let error = build(failing.clone(), PackageRecorder::default())
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("broken@1.0.0 install"));
+
+        // Optional dependencies only warn
+        let mut recorder = PackageRecorder::default();
+        let meta = PackageMetaRecorder {
+            optional: true,
+            ..Default::default()
+        };
+        recorder.main_packages.insert(key("broken"), meta);
+        build(failing, recorder).await.unwrap();
+        assert!(!BuilderPipe::is_built(
+            &key("broken"),
+            &root.path().join("node_modules/broken")
+        ));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_remove_failed_optional() {
+        let root = tempfile::tempdir().unwrap();
+        let node_modules = root.path().join("node_modules");
+        let bin_dir = node_modules.join(".bin");
+        fs::create_dir_all(&bin_dir).unwrap();
+
+        // Isolated layout, linked at the top and exposing a binary
+        let store = node_modules.join(".craft/broken@1.0.0");
+        let broken = package(&store, "broken", r#""install":"exit 3""#);
+        symlink_dir(&broken.to, node_modules.join("broken")).unwrap();
+        let healthy = package(root.path(), "healthy", "");
+        for (bin, package) in [("broken-cli", "broken"), ("healthy-cli", "healthy")] {
+            let shim = get_bash_script(vec![], package, "cli.js");
+            fs::write(bin_dir.join(bin), shim).unwrap();
+            fs::write(bin_dir.join(format!("{}.CMD", bin)), "").unwrap();
+            fs::write(bin_dir.join(format!("{}.ps1", bin)), "").unwrap();
+        }
+
+        let mut recorder = PackageRecorder::default();
+        let meta = PackageMetaRecorder {
+            optional: true,
+            ..Default::default()
+        };
+        recorder.main_packages.insert(key("broken"), meta);
+        let (tx, _rx) = std::sync::mpsc::channel();
+        let script_env = ScriptEnv::new(&NpmConfig::new(Default::default()));
+        let mut pipe = BuilderPipe::new(
+            tx,
+            vec![broken.clone(), healthy.clone()],
+            recorder,
+            BuildPolicy::default(),
+            script_env,
+        );
+        pipe.run().await.unwrap();
+
+        assert!(!broken.to.exists());
+        assert!(fs::symlink_metadata(node_modules.join("broken")).is_err());
+        for shim in ["broken-cli", "broken-cli.CMD", "broken-cli.ps1"] {
+            assert!(!bin_dir.join(shim).exists());
+        }
+        assert!(healthy.to.join("package.json").exists());
+        for shim in ["healthy-cli", "healthy-cli.CMD", "healthy-cli.ps1"] {
+            assert!(bin_dir.join(shim).exists());
+        }
+    }
+}
@@ -1,10 +1,11 @@
 use async_trait::async_trait;
 
 use crate::{
-    cache::{PackagesCache, RegistryCache},
+    cache::{PackagesCache, RegistryCache, DLX_CACHE_FOLDER},
     command::CacheAction,
     contracts::{PersistentCache, Pipe},
     errors::ExecutionError,
+    fs::get_config_dir,
 };
 
 pub struct CacheCleanPipe {
@@ -26,6 +27,8 @@ impl Pipe<()> for CacheCleanPipe {
             CacheAction::Clean => {
                 let _ = PackagesCache::default().clean().await;
                 let _ = RegistryCache::default().clean().await;
+                let dlx = get_config_dir(DLX_CACHE_FOLDER.clone());
+                let _ = tokio::fs::remove_dir_all(dlx).await;
             }
         }
 
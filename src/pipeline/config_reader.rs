@@ -1,4 +1,5 @@
-use crate::conf::NpmConfig;
+use crate::conf::constants::{GLOBAL_CONFIG, PREFIX, USER_CONFIG};
+use crate::conf::{Location, NpmConfig};
 use crate::contracts::Pipe;
 use crate::errors::ExecutionError;
 use async_trait::async_trait;
@@ -6,13 +7,17 @@ use homedir::my_home;
 use std::collections::BTreeMap;
 use std::env;
 use std::env::current_dir;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 const CONFIG_PNPM: &str = "pnpm/rc";
+const NPMRC: &str = ".npmrc";
+const ENV_PREFIX: &str = "npm_config_";
+const GLOBAL_NPMRC: &str = "etc/npmrc";
 
+/// The rc file craft falls back to for the global layer when npm's prefix is unknown
 pub fn determine_global_config_file_location() -> PathBuf {
-    if env::var("$XDG_CONFIG_HOME").is_ok() {
-        let mut config_dir = PathBuf::from(env::var("$XDG_CONFIG_HOME").unwrap());
+    if let Some(config_home) = env::var_os("XDG_CONFIG_HOME") {
+        let mut config_dir = PathBuf::from(config_home);
         config_dir.push(CONFIG_PNPM);
         return config_dir;
     }
@@ -40,66 +45,239 @@ pub fn determine_global_config_file_location() -> PathBuf {
     }
 }
 
+/// npm's global prefix: `npm_config_prefix`, `PREFIX`, else where node is installed
+fn global_prefix(env_config: &BTreeMap<String, Option<String>>) -> Option<PathBuf> {
+    if let Some(Some(prefix)) = env_config.get(PREFIX) {
+        return Some(PathBuf::from(prefix));
+    }
+    if let Some(prefix) = env::var_os("PREFIX") {
+        return Some(PathBuf::from(prefix));
+    }
+
+    let node = if cfg!(target_os = "windows") {
+        "node.exe"
+    } else {
+        "node"
+    };
+    let bin_dir = env::split_paths(&env::var_os("PATH")?).find(|dir| dir.join(node).is_file())?;
+    match cfg!(target_os = "windows") {
+        true => Some(bin_dir),
+        false => bin_dir.parent().map(Path::to_path_buf),
+    }
+}
+
+/// File backing a configuration layer, env and CLI layers have none.
+/// Like npm, `npm_config_globalconfig` and `npm_config_userconfig` replace the default files
+pub fn config_file_location(location: &Location) -> Option<PathBuf> {
+    let env_config = parse_env_config(env::vars());
+    let configured = |key: &str| env_config.get(key).cloned().flatten().map(PathBuf::from);
+
+    match location {
+        Location::Global => configured(GLOBAL_CONFIG).or_else(|| {
+            let global_npmrc = global_prefix(&env_config).map(|prefix| prefix.join(GLOBAL_NPMRC));
+            Some(global_npmrc.unwrap_or_else(determine_global_config_file_location))
+        }),
+        Location::User => configured(USER_CONFIG)
+            .or_else(|| my_home().ok().flatten().map(|home| home.join(NPMRC))),
+        Location::Project => current_dir().ok().map(|cwd| cwd.join(NPMRC)),
+        Location::Env | Location::Cli => None,
+    }
+}
+
 pub fn parse_config(conf: String) -> BTreeMap<String, Option<String>> {
     let mut config_map = BTreeMap::new();
     let lines = conf.split("\n");
     for line in lines {
-        if line.trim().is_empty() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with(';') {
             continue;
         }
-        let parts = line.split("=");
-        let mut parts_iter = parts.into_iter();
-
-        // skip invalid line
-        if parts_iter.clone().count() < 1 {
-            continue;
-        }
-
-        let key = parts_iter.next().unwrap().trim();
-        let value = parts_iter.next().map(|s| s.trim());
+        // Values may contain `=` themselves, e.g. base64 padding of `_auth`
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) => (key.trim(), Some(value.trim())),
+            None => (line.trim(), None),
+        };
         config_map.insert(key.to_string(), value.map(|s| s.to_string()));
     }
     config_map
 }
 
-pub struct ConfigReader;
+/// Reads `npm_config_<key>` variables, e.g. npm_config_strict_ssl => strict-ssl
+pub fn parse_env_config(
+    vars: impl Iterator<Item = (String, String)>,
+) -> BTreeMap<String, Option<String>> {
+    vars.filter_map(|(name, value)| {
+        let prefix = name.get(..ENV_PREFIX.len())?;
+        if !prefix.eq_ignore_ascii_case(ENV_PREFIX) {
+            return None;
+        }
+
+        let key = name[ENV_PREFIX.len()..].to_lowercase();
+        let leading = key.len() - key.trim_start_matches('_').len();
+        let key = format!("{}{}", &key[..leading], key[leading..].replace('_', "-"));
+        (!key.is_empty()).then_some((key, Some(value)))
+    })
+    .collect()
+}
+
+/// Merges defaults, global, user and project files, env variables and CLI overrides
+pub struct ConfigReader {
+    overrides: BTreeMap<String, Option<String>>,
+}
 
 // ─── Implementations ─────────────────────────────────────────────────────────
 
 impl ConfigReader {
-    pub fn new() -> Self {
-        Self
+    pub fn new(overrides: BTreeMap<String, Option<String>>) -> Self {
+        Self { overrides }
     }
 }
 
 #[async_trait]
 impl Pipe<NpmConfig> for ConfigReader {
     async fn run(&mut self) -> Result<NpmConfig, ExecutionError> {
-        let conf = read_config_file(determine_global_config_file_location());
-        if let Ok(cwd) = current_dir() {
-            let _local_conf = read_config_file(cwd);
-        }
-        match conf {
-            Ok(conf) => Ok(conf),
-            Err(e) => Err(ExecutionError::ConfigError(e.to_string())),
-        }
+        let mut conf = NpmConfig::new(BTreeMap::new());
+
+        let files = [Location::Global, Location::User, Location::Project]
+            .into_iter()
+            .filter_map(|location| Some((config_file_location(&location)?, location)));
+        apply_config_files(&mut conf, files)?;
+
+        conf.apply(parse_env_config(env::vars()), Location::Env);
+        conf.apply(self.overrides.clone(), Location::Cli);
+
+        Ok(conf)
     }
 }
 
-pub fn read_config_file(config_file: PathBuf) -> Result<NpmConfig, std::io::Error> {
+/// Applies the rc files in order, later files win
+fn apply_config_files(
+    conf: &mut NpmConfig,
+    files: impl IntoIterator<Item = (PathBuf, Location)>,
+) -> Result<(), ExecutionError> {
+    for (file, location) in files {
+        let layer =
+            read_config_map(file).map_err(|e| ExecutionError::ConfigError(e.to_string()))?;
+        conf.apply(layer, location);
+    }
+    Ok(())
+}
+
+pub fn read_config_map(
+    config_file: PathBuf,
+) -> Result<BTreeMap<String, Option<String>>, std::io::Error> {
     let result_conf_read = std::fs::read_to_string(&config_file);
     match result_conf_read {
-        Ok(conf) => {
-            let read_conf = parse_config(conf);
-            let npm_conf = NpmConfig::new(read_conf);
-            Ok(npm_conf)
-        }
+        Ok(conf) => Ok(parse_config(conf)),
         Err(e) => {
             if e.kind() == std::io::ErrorKind::NotFound {
-                std::fs::File::create(&config_file)?;
-                return Ok(NpmConfig::new(BTreeMap::new()));
+                return Ok(BTreeMap::new());
             }
             Err(e)
         }
     }
 }
+
+// ─── Tests ───────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let conf = parse_config(
+            "# comment\n; another comment\n_auth=dXNlcjpwYXNz==\nstrict-ssl = false\n".into(),
+        );
+
+        assert_eq!(conf.len(), 2);
+        assert_eq!(conf["_auth"], Some("dXNlcjpwYXNz==".to_string()));
+        assert_eq!(conf["strict-ssl"], Some("false".to_string()));
+    }
+
+    #[test]
+    fn test_parse_env_config() {
+        let vars = vec![
+            ("npm_config_strict_ssl".to_string(), "false".to_string()),
+            (
+                "NPM_CONFIG_REGISTRY".to_string(),
+                "http://mirror".to_string(),
+            ),
+            ("npm_config__auth".to_string(), "secret".to_string()),
+            ("PATH".to_string(), "/usr/bin".to_string()),
+        ];
+        let conf = parse_env_config(vars.into_iter());
+
+        assert_eq!(conf.len(), 3);
+        assert_eq!(conf["strict-ssl"], Some("false".to_string()));
+        assert_eq!(conf["registry"], Some("http://mirror".to_string()));
+        assert!(conf.contains_key("_auth"));
+    }
+
+    #[test]
+    fn test_layers_keep_origin() {
+        let layer = |value: &str| BTreeMap::from([("registry".to_string(), Some(value.into()))]);
+        let mut conf = NpmConfig::new(BTreeMap::new());
+
+        conf.apply(layer("http://user"), Location::User);
+        conf.apply(layer("http://project"), Location::Project);
+
+        assert_eq!(conf.registry, "http://project");
+        assert_eq!(conf.entries["registry"].location, Location::Project);
+    }
+
+    #[test]
+    fn test_env_references_are_expanded() {
+        env::set_var("CRAFT_TEST_REGISTRY_HOST", "mirror.test");
+        env::set_var("CRAFT_TEST_AUTH_TOKEN", "secret");
+        let mut conf = NpmConfig::new(BTreeMap::new());
+        conf.apply(
+            parse_config(
+                "registry=https://${CRAFT_TEST_REGISTRY_HOST}/npm/\n\
+                 //${CRAFT_TEST_REGISTRY_HOST}/npm/:_authToken=${CRAFT_TEST_AUTH_TOKEN}\n"
+                    .into(),
+            ),
+            Location::User,
+        );
+
+        assert_eq!(conf.registry, "https://mirror.test/npm/");
+        assert!(conf.registry_credentials.contains_key("//mirror.test/npm/"));
+    }
+
+    #[test]
+    fn test_project_over_user_over_global() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = |name: &str, content: &str| {
+            let path = dir.path().join(name);
+            std::fs::write(&path, content).unwrap();
+            path
+        };
+        let global = file(
+            "global",
+            "init-license=global\ninit-version=global\nsave-prefix=global\n",
+        );
+        let user = file("user", "init-version=user\nsave-prefix=user\n");
+        let project = file("project", "save-prefix=project\n");
+
+        env::set_var("npm_config_globalconfig", &global);
+        env::set_var("NPM_CONFIG_USERCONFIG", &user);
+        assert_eq!(config_file_location(&Location::Global), Some(global));
+        assert_eq!(config_file_location(&Location::User), Some(user));
+
+        let mut conf = NpmConfig::new(BTreeMap::new());
+        let files = [Location::Global, Location::User]
+            .into_iter()
+            .map(|location| (config_file_location(&location).unwrap(), location))
+            .chain([(project, Location::Project)]);
+        apply_config_files(&mut conf, files).unwrap();
+        env::remove_var("npm_config_globalconfig");
+        env::remove_var("NPM_CONFIG_USERCONFIG");
+
+        assert_eq!(conf.init_license, "global");
+        assert_eq!(conf.init_version, "user");
+        assert_eq!(conf.save_prefix, "project");
+        assert_eq!(conf.entries["init-license"].location, Location::Global);
+        assert_eq!(conf.entries["init-version"].location, Location::User);
+        assert_eq!(conf.entries["save-prefix"].location, Location::Project);
+    }
+}
@@ -1,6 +1,6 @@
 use futures::future;
 use std::{
-    path::{Path, PathBuf},
+    path::Path,
     sync::{mpsc::Sender, Arc},
 };
 
@@ -13,18 +13,21 @@ use crate::{
     cache::PackagesCache,
     contracts::{PersistentCache, Phase, Pipe, PipeArtifact, ProgressAction},
     errors::ExecutionError,
+    lockfile::lockfile_structure::LockfileResolution,
     logger::CraftLogger,
-    network::Http,
+    network::{FetchPolicy, Http, Integrity},
     package::NpmPackage,
+    registry::{GitRegistry, LocalRegistry},
 };
 
 // ─── DownloaderPipe ─────────────────────────────────────────────────────────────
 
 #[derive(Debug)]
-pub struct DownloaderPipe<C: PersistentCache<PathBuf>> {
+pub struct DownloaderPipe<C: PersistentCache> {
     packages: Vec<NpmPackage>,
     cache: Arc<Mutex<C>>,
     artifacts: Arc<Mutex<DownloadArtifacts>>,
+    http: Http,
     tx: Sender<ProgressAction>,
 }
 
@@ -33,20 +36,37 @@ pub struct DownloaderPipe<C: PersistentCache<PathBuf>> {
 impl DownloaderPipe<PackagesCache> {
     pub fn new(
         artifacts: &dyn PipeArtifact<Vec<ResolvedItem>>,
+        http: Http,
         tx: Sender<ProgressAction>,
     ) -> Self {
+        // An aliased package can be resolved more than once, it is downloaded only once
+        let mut packages: Vec<NpmPackage> = vec![];
+        for item in artifacts.get_artifacts() {
+            if !packages.contains(&item.package) {
+                packages.push(item.package);
+            }
+        }
+
         Self {
-            packages: artifacts
-                .get_artifacts()
-                .iter()
-                .map(|item| item.package.clone())
-                .collect(),
+            packages,
             cache: Arc::new(Mutex::new(PackagesCache::default())),
             artifacts: Arc::new(Mutex::new(DownloadArtifacts::new())),
+            http,
             tx,
         }
     }
 
+    /// Whether a cached tarball still matches the integrity recorded for the package
+    async fn verify_cached(path: &Path, integrity: Option<&Integrity>) -> bool {
+        let Some(integrity) = integrity else {
+            return true;
+        };
+        match tokio::fs::read(path).await {
+            Ok(content) => integrity.check(&content).is_ok(),
+            Err(_) => false,
+        }
+    }
+
     async fn prepare_pkg_for_download(download_path: &Path) -> Result<(), std::io::Error> {
         tokio::fs::create_dir_all(download_path.parent().unwrap()).await
     }
@@ -55,12 +75,31 @@ impl DownloaderPipe<PackagesCache> {
         package: &NpmPackage,
         mut cache: PackagesCache,
         artifacts: Arc<Mutex<DownloadArtifacts>>,
+        http: Http,
     ) -> Result<(), ExecutionError> {
         let pkg = package.clone();
 
-        if cache.has(&pkg.clone().into()).await {
+        // Local packages are used in place
+        if let Some(location) = LocalRegistry::location(&pkg) {
+            artifacts.lock().await.insert(
+                pkg.to_string(),
+                DownloadArtifacts::to_artifact(pkg.clone(), location),
+            );
+            return Ok(());
+        }
+
+        // Git archives are cheap to rebuild and the commit may change without a version bump
+        let is_git = matches!(
+            pkg.dist.resolution,
+            Some(LockfileResolution::GitRepository(_))
+        );
+        let integrity = Integrity::for_package(pkg.dist.integrity.as_deref(), &pkg.dist.shasum);
+        let key = pkg.store_key();
+        let path = { &cache.get_cache_directory().join(key.to_string()) };
+
+        if !is_git && cache.has(&key).await && Self::verify_cached(path, integrity.as_ref()).await {
             log::info!("{}", format!("Package already downloaded: {}", pkg));
-            let cache_dir = { cache.get_cache_directory().join(pkg.to_string()) };
+            let cache_dir = path.clone();
 
             {
                 artifacts.lock().await.insert(
@@ -72,17 +111,28 @@ impl DownloaderPipe<PackagesCache> {
             return Ok(());
         }
 
-        let path = { &cache.get_cache_directory().join(pkg.to_string()) };
+        if http.policy() == FetchPolicy::Offline {
+            return Err(ExecutionError::Offline(vec![pkg.to_string()]));
+        }
 
         if pkg.contains_org() {
             Self::prepare_pkg_for_download(path).await.unwrap();
         }
-        let result = Http::download_file(&pkg.dist.tarball, path, &pkg.dist.shasum).await;
-        if result.is_err() {
+        let result = match &pkg.dist.resolution {
+            Some(LockfileResolution::GitRepository(git)) => {
+                GitRegistry::new(&http).archive(git, path).await
+            }
+            _ => {
+                http.download_file(&pkg.dist.tarball, path, integrity.as_ref())
+                    .await
+            }
+        };
+        if let Err(e) = result {
             CraftLogger::warn(format!("Failed to download package: {}", pkg));
+            CraftLogger::verbose(format!("{}: {}", pkg, e));
             return Err(ExecutionError::JobExecutionFailed(
-                "Failed to download package".parse().unwrap(),
-                "Failed to download package".parse().unwrap(),
+                format!("download {}", pkg),
+                e.to_string(),
             ));
         }
 
@@ -118,9 +168,10 @@ impl Pipe<DownloadArtifacts> for DownloaderPipe<PackagesCache> {
         for pkg in pkgs {
             let cache = cache.clone();
             let artifacts = self.artifacts.clone();
+            let http = self.http.clone();
             let job = tokio::spawn(async move {
                 CraftLogger::verbose(format!("Downloading package: {}", pkg));
-                match Self::download_pkg(&pkg, cache, artifacts).await {
+                match Self::download_pkg(&pkg, cache, artifacts, http).await {
                     Ok(_) => Ok(()),
                     Err(err) => Err(err),
                 }
@@ -129,14 +180,74 @@ impl Pipe<DownloadArtifacts> for DownloaderPipe<PackagesCache> {
         }
 
         let results: Vec<_> = future::join_all(jobs).await;
-        // Iterate over the results
+        // A package that could not be downloaded or verified fails the install,
+        // offline every missing tarball is reported at once
+        let mut missing = vec![];
         for result in results.into_iter() {
-            let jh_handle = result.unwrap();
-            if let Err(e) = jh_handle {
-                log::error!("Error is {}", e.to_string())
+            match result.unwrap() {
+                Err(ExecutionError::Offline(packages)) => missing.extend(packages),
+                Err(e) => {
+                    log::error!("Error is {}", e);
+                    return Err(e);
+                }
+                Ok(()) => {}
             }
         }
+        if !missing.is_empty() {
+            missing.sort();
+            return Err(ExecutionError::Offline(missing));
+        }
 
         Ok(self.artifacts.lock().await.clone())
     }
 }
+
+// ─── Tests ───────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conf::NpmConfig;
+    use crate::lockfile::lockfile_structure::TarballResolution;
+    use crate::network::sha512_integrity;
+
+    #[tokio::test]
+    async fn test_reuse_tarball_fetched_at_resolve() {
+        let directory = tempfile::tempdir().unwrap();
+        let content = b"resolved tarball".to_vec();
+        let integrity = sha512_integrity(&content);
+        // Nothing listens there, the tarball has to come from the cache
+        let url = "http://127.0.0.1:9/lib-1.2.3.tgz".to_string();
+        let mut package = NpmPackage {
+            name: "lib".to_string(),
+            version: "1.2.3".to_string(),
+            ..Default::default()
+        };
+        package.dist.tarball = url.clone();
+        package.dist.integrity = Some(integrity.clone());
+        package.dist.resolution = Some(LockfileResolution::Tarball(TarballResolution {
+            r#type: None,
+            tarball: url,
+            integrity: Some(integrity),
+            path: None,
+        }));
+        let cached = directory.path().join(package.store_key().to_string());
+        std::fs::write(&cached, &content).unwrap();
+
+        let mut cache = PackagesCache {
+            directory: directory.path().to_path_buf(),
+            ..Default::default()
+        };
+        cache.init().await.unwrap();
+        let artifacts = Arc::new(Mutex::new(DownloadArtifacts::new()));
+        let http = Http::new(&NpmConfig::new(Default::default())).unwrap();
+        DownloaderPipe::download_pkg(&package, cache, artifacts.clone(), http)
+            .await
+            .unwrap();
+
+        let stored = artifacts.lock().await.get_artifacts();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].zip_path, cached);
+        assert_eq!(std::fs::read(&cached).unwrap(), content);
+    }
+}
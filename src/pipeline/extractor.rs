@@ -6,9 +6,11 @@ use tokio::fs;
 use tokio::sync::Mutex;
 
 use super::artifacts::{ExtractArtifacts, StoredArtifact};
-use crate::cache::DEP_CACHE_FOLDER;
+use crate::cache::{ContentStore, DEP_CACHE_FOLDER};
 use crate::fs::get_config_dir;
+use crate::lockfile::lockfile_structure::LockfileResolution;
 use crate::pipeline::ResolvedItem;
+use crate::registry::LocalRegistry;
 use crate::{
     contracts::{Phase, Pipe, PipeArtifact, ProgressAction},
     errors::{ExecutionError, ZipError},
@@ -20,6 +22,7 @@ pub struct ExtractorPipe {
     packages: Vec<StoredArtifact>,
     artifacts: Arc<Mutex<ExtractArtifacts>>,
     tmp_folder: PathBuf,
+    store: ContentStore,
     tx: Sender<ProgressAction>,
 }
 
@@ -32,19 +35,36 @@ impl ExtractorPipe {
 
         Self {
             tmp_folder: tmp_cache_folder,
+            store: ContentStore::new(),
             packages: artifacts.get_artifacts(),
             artifacts: Arc::new(Mutex::new(ExtractArtifacts::new())),
             tx,
         }
     }
 
+    #[cfg(test)]
+    fn at(tmp_folder: PathBuf, store: ContentStore) -> Self {
+        Self {
+            tmp_folder,
+            store,
+            packages: vec![],
+            artifacts: Arc::new(Mutex::new(ExtractArtifacts::new())),
+            tx: std::sync::mpsc::channel().0,
+        }
+    }
+
     // Skip because we now simlink the extracted files
     pub async fn cleanup(vec: Vec<ResolvedItem>) -> Result<(), ExecutionError> {
         use std::fs::metadata;
 
         let mapped_str = vec
             .iter()
-            .map(|x| x.package.name.clone())
+            .map(|x| x.alias().unwrap_or_else(|| x.package.name.clone()))
+            // Scoped packages live in a folder named after the scope
+            .map(|name| match name.split_once('/') {
+                Some((scope, _)) => scope.to_string(),
+                None => name,
+            })
             .collect::<Vec<String>>();
         let mut entries = tokio::fs::read_dir("node_modules")
             .await
@@ -57,7 +77,11 @@ impl ExtractorPipe {
             let dir_name = entry.file_name().to_str().unwrap().to_string();
             let meta = metadata(entry.path())
                 .map_err(|e| ExecutionError::JobExecutionFailed(e.to_string(), e.to_string()))?;
-            if !mapped_str.contains(&dir_name) && meta.is_dir() && dir_name != ".bin" {
+            // .bin and the virtual store .craft are managed by the linker
+            if dir_name.starts_with('.') {
+                continue;
+            }
+            if !mapped_str.contains(&dir_name) && meta.is_dir() {
                 fs::remove_dir_all(entry.path()).await.map_err(|e| {
                     ExecutionError::JobExecutionFailed(e.to_string(), e.to_string())
                 })?;
@@ -74,30 +98,45 @@ impl ExtractorPipe {
     pub async fn unzip_archive(&self, artifact: &StoredArtifact) -> Result<(), ZipError> {
         let artifact_s = artifact.clone();
 
-        let tmp_folder = self.tmp_folder.clone();
+        // Git, tarball and local packages are kept apart from registry releases of the same version
+        let key = artifact.package.store_key();
+        let extracted_at = self
+            .tmp_folder
+            .join(format!("{}-{}", key.name, key.version));
+
+        let dest = extracted_at.clone();
+        let store = self.store.clone();
         tokio::task::spawn_blocking(move || {
-            let dest = tmp_folder.join(format!(
-                "{}-{}",
-                &artifact_s.package.name, &artifact_s.package.version
-            ));
+            let fresh = artifact_s.package.dist.resolution.is_some();
 
-            // Skip if already unzipped
+            // Skip if already unzipped, git and local packages are always unpacked fresh
             if dest.exists() {
-                return Ok(());
+                if !fresh {
+                    // Unpacked before the content store existed
+                    if store.index(&key).is_none() {
+                        store.add_package(&key, &dest.join("package"))?;
+                    }
+                    return Ok(());
+                }
+                let _ = std::fs::remove_dir_all(&dest);
             }
-            match Gzip::extract(&artifact_s.zip_path, &dest) {
-                Ok(_) => Ok(()),
-                Err(e) => Err(e),
+            match &artifact_s.package.dist.resolution {
+                // Local directories are imported from their source, never from the store
+                Some(LockfileResolution::Directory(directory)) => {
+                    let target = dest.join("package");
+                    LocalRegistry::materialize(directory, &artifact_s.zip_path, &target)
+                        .map_err(ZipError::from)
+                }
+                _ => {
+                    Gzip::extract(&artifact_s.zip_path, &dest)?;
+                    store.add_package(&key, &dest.join("package"))?;
+                    Ok(())
+                }
             }
         })
         .await
         .unwrap()?;
 
-        let extracted_at = self.tmp_folder.join(format!(
-            "{}-{}",
-            &artifact.package.name, &artifact.package.version
-        ));
-
         self.artifacts
             .lock()
             .await
@@ -120,3 +159,83 @@ impl Pipe<ExtractArtifacts> for ExtractorPipe {
         Ok(self.artifacts.lock().await.clone())
     }
 }
+
+// ─── Tests ───────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conf::PackageImportMethod;
+    use crate::lockfile::lockfile_structure::TarballResolution;
+    use crate::package::NpmPackage;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::path::Path;
+
+    /// lib@1.0.0 whose index.js holds `content`
+    fn tarball(path: &Path, content: &str) -> PathBuf {
+        let encoder = GzEncoder::new(std::fs::File::create(path).unwrap(), Compression::fast());
+        let mut builder = tar::Builder::new(encoder);
+        for (name, data) in [
+            (
+                "package/package.json",
+                r#"{"name":"lib","version":"1.0.0"}"#,
+            ),
+            ("package/index.js", content),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, name, data.as_bytes())
+                .unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+        path.to_path_buf()
+    }
+
+    #[tokio::test]
+    async fn test_sources_do_not_collide() {
+        let root = tempfile::tempdir().unwrap();
+        let package = NpmPackage {
+            name: "lib".to_string(),
+            version: "1.0.0".to_string(),
+            ..Default::default()
+        };
+        let mut from_url = package.clone();
+        from_url.dist.resolution = Some(LockfileResolution::Tarball(TarballResolution {
+            r#type: None,
+            tarball: "https://cdn.example.com/lib-1.0.0.tgz".to_string(),
+            integrity: Some("sha512-url".to_string()),
+            path: None,
+        }));
+        let registry = StoredArtifact {
+            package,
+            zip_path: tarball(&root.path().join("registry.tgz"), "registry"),
+        };
+        let url = StoredArtifact {
+            package: from_url,
+            zip_path: tarball(&root.path().join("url.tgz"), "url"),
+        };
+
+        let store = ContentStore::at(root.path().join("store"));
+        let extractor = ExtractorPipe::at(root.path().join("unpacked"), store.clone());
+        for artifact in [&registry, &url, &registry] {
+            extractor.unzip_archive(artifact).await.unwrap();
+        }
+
+        let installed = |artifact: &StoredArtifact| {
+            let key = artifact.package.store_key();
+            let to = root.path().join("node_modules").join(&key.version);
+            let index = store.index(&key).unwrap();
+            store
+                .import(&index, &to, &PackageImportMethod::Copy)
+                .unwrap();
+            std::fs::read_to_string(to.join("index.js")).unwrap()
+        };
+        assert_eq!(installed(&registry), "registry");
+        assert_eq!(installed(&url), "url");
+        assert_ne!(registry.package.store_key(), url.package.store_key());
+    }
+}
@@ -0,0 +1,195 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::cache::RegistryKey;
+
+/// Folders from the project root to a package, `[a, b]` is node_modules/a/node_modules/b.
+/// The location of a package also names its own node_modules folder, the root is `[]`.
+pub type Location = Vec<String>;
+
+// ─── Hoister ─────────────────────────────────────────────────────────────────
+
+/// Places every package at the highest node_modules folder where it does not conflict
+/// with another version of the same package, like npm's hoisted layout.
+/// Packages are placed breadth first, so shallower packages win the top level.
+pub struct Hoister<'a> {
+    dependencies: &'a HashMap<RegistryKey, Vec<RegistryKey>>,
+    /// node_modules folder -> folder name -> package
+    tree: HashMap<Location, HashMap<String, RegistryKey>>,
+    /// Where each package found a dependency: consumer, name, node_modules folder
+    edges: Vec<(Location, String, Location)>,
+}
+
+impl<'a> Hoister<'a> {
+    pub fn new(dependencies: &'a HashMap<RegistryKey, Vec<RegistryKey>>) -> Self {
+        Self {
+            dependencies,
+            tree: HashMap::new(),
+            edges: vec![],
+        }
+    }
+
+    /// Whether a package placed in `folder` would hide another version from a package
+    /// below it that already resolved `name` further up
+    fn shadows(&self, folder: &[String], name: &str) -> bool {
+        self.edges.iter().any(|(consumer, edge_name, found_in)| {
+            edge_name == name && consumer.starts_with(folder) && found_in.len() < folder.len()
+        })
+    }
+
+    /// The node_modules folder the dependency should go to, and whether it is already there
+    fn find_folder(&self, location: &Location, dependency: &RegistryKey) -> (Location, bool) {
+        let mut target = location.clone();
+
+        for depth in (0..=location.len()).rev() {
+            let folder = &location[..depth];
+            match self.tree.get(folder).and_then(|f| f.get(&dependency.name)) {
+                Some(existing) if existing == dependency => return (folder.to_vec(), true),
+                Some(_) => break,
+                None if self.shadows(folder, &dependency.name) => break,
+                None => target = folder.to_vec(),
+            }
+        }
+
+        (target, false)
+    }
+
+    /// Places the direct dependencies under their folder name, then everything they need
+    pub fn hoist(mut self, roots: Vec<(String, RegistryKey)>) -> Vec<(Location, RegistryKey)> {
+        let mut placed = vec![];
+        let mut queue = VecDeque::new();
+
+        for (folder, key) in roots {
+            self.tree
+                .entry(vec![])
+                .or_default()
+                .insert(folder.clone(), key.clone());
+            placed.push((vec![folder.clone()], key.clone()));
+            queue.push_back((vec![folder], key));
+        }
+
+        while let Some((location, key)) = queue.pop_front() {
+            let mut dependencies = self.dependencies.get(&key).cloned().unwrap_or_default();
+            dependencies.sort_by(|a, b| a.name.cmp(&b.name));
+
+            for dependency in dependencies {
+                let (folder, existing) = self.find_folder(&location, &dependency);
+                self.edges
+                    .push((location.clone(), dependency.name.clone(), folder.clone()));
+                if existing {
+                    continue;
+                }
+
+                self.tree
+                    .entry(folder.clone())
+                    .or_default()
+                    .insert(dependency.name.clone(), dependency.clone());
+                let mut dependency_location = folder;
+                dependency_location.push(dependency.name.clone());
+                placed.push((dependency_location.clone(), dependency.clone()));
+                queue.push_back((dependency_location, dependency));
+            }
+        }
+
+        placed
+    }
+}
+
+// ─── Tests ───────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(spec: &str) -> RegistryKey {
+        let (name, version) = spec.split_once('@').unwrap();
+        RegistryKey {
+            name: name.to_string(),
+            version: version.to_string(),
+        }
+    }
+
+    fn hoist(roots: &[&str], graph: &[(&str, &[&str])]) -> Vec<String> {
+        let dependencies = graph
+            .iter()
+            .map(|(parent, deps)| (key(parent), deps.iter().map(|d| key(d)).collect()))
+            .collect();
+        let roots = roots
+            .iter()
+            .map(|r| (key(r).name, key(r)))
+            .collect::<Vec<_>>();
+
+        let mut placed: Vec<String> = Hoister::new(&dependencies)
+            .hoist(roots)
+            .into_iter()
+            .map(|(location, key)| format!("{} {}", location.join("/"), key.version))
+            .collect();
+        placed.sort();
+        placed
+    }
+
+    #[test]
+    fn test_hoist_to_top() {
+        let placed = hoist(
+            &["a@1.0.0", "b@1.0.0"],
+            &[
+                ("a@1.0.0", &["c@1.0.0"]),
+                ("b@1.0.0", &["c@1.0.0"]),
+                ("c@1.0.0", &["a@1.0.0"]),
+            ],
+        );
+
+        assert_eq!(placed, vec!["a 1.0.0", "b 1.0.0", "c 1.0.0"]);
+    }
+
+    #[test]
+    fn test_nest_conflicts() {
+        let placed = hoist(
+            &["a@1.0.0", "c@2.0.0", "x@1.0.0"],
+            &[
+                ("a@1.0.0", &["c@1.0.0", "d@1.0.0"]),
+                ("d@1.0.0", &["c@1.0.0"]),
+                ("x@1.0.0", &["y@1.0.0"]),
+                ("y@1.0.0", &["c@2.0.0"]),
+            ],
+        );
+
+        assert_eq!(
+            placed,
+            vec![
+                "a 1.0.0",
+                "a/c 1.0.0",
+                "c 2.0.0",
+                "d 1.0.0",
+                "d/c 1.0.0",
+                "x 1.0.0",
+                "y 1.0.0"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_no_shadowing() {
+        // e needs c@2 from the top, so c@1 for f can not go into a/node_modules
+        let placed = hoist(
+            &["a@1.0.0", "c@2.0.0", "e@2.0.0", "f@2.0.0"],
+            &[
+                ("a@1.0.0", &["e@1.0.0", "f@1.0.0"]),
+                ("e@1.0.0", &["c@2.0.0"]),
+                ("f@1.0.0", &["c@1.0.0"]),
+            ],
+        );
+
+        assert_eq!(
+            placed,
+            vec![
+                "a 1.0.0",
+                "a/e 1.0.0",
+                "a/f 1.0.0",
+                "a/f/c 1.0.0",
+                "c 2.0.0",
+                "e 2.0.0",
+                "f 2.0.0"
+            ]
+        );
+    }
+}
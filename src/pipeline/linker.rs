@@ -1,14 +1,24 @@
-use std::{env, fs, path::PathBuf, sync::mpsc::Sender};
+use std::{
+    collections::{HashMap, HashSet},
+    env, fs, io,
+    path::{Path, PathBuf},
+    sync::mpsc::Sender,
+};
 
 use async_trait::async_trait;
 use lazy_static::lazy_static;
 
 use super::artifacts::{ExtractArtifactsMap, LinkArtifactItem, ResolvedItem};
+use super::hoist::{Hoister, Location};
 use crate::{
+    cache::{ContentStore, RegistryKey},
+    conf::{InstallStrategy, PackageImportMethod},
     contracts::{Logger, Phase, Pipe, ProgressAction},
     errors::ExecutionError,
-    fs::copy_dir,
+    fs::{hardlink_dir, symlink_dir},
+    lockfile::lockfile_structure::LockfileResolution,
     logger::CraftLogger,
+    pipeline::BuilderPipe,
 };
 use path_clean::clean;
 
@@ -22,6 +32,11 @@ pub struct LinkerPipe {
     resolved: Vec<ResolvedItem>,
     extracted: ExtractArtifactsMap,
     recorder: PackageRecorder,
+    strategy: InstallStrategy,
+    store: ContentStore,
+    import_method: PackageImportMethod,
+    /// Where the hoisted layout placed each package
+    hoisted: Vec<(Location, RegistryKey)>,
 }
 
 // ─────────────────────────────────────────────────────────────────────────────
@@ -30,6 +45,9 @@ lazy_static! {
     pub static ref NODE_MODULES: PathBuf = env::current_dir().unwrap().join("node_modules");
 }
 
+/// Folder inside node_modules holding every package of the isolated layout
+pub const VIRTUAL_STORE: &str = ".craft";
+
 // ─────────────────────────────────────────────────────────────────────────────
 
 impl LinkerPipe {
@@ -38,21 +56,98 @@ impl LinkerPipe {
         resolved: Vec<ResolvedItem>,
         extracted: ExtractArtifactsMap,
         recorder: PackageRecorder,
+        strategy: InstallStrategy,
+        import_method: PackageImportMethod,
     ) -> Self {
         Self {
             tx,
             resolved,
             extracted,
             recorder,
+            strategy,
+            store: ContentStore::new(),
+            import_method,
+            hoisted: vec![],
         }
     }
 
     fn build_linker_artifacts(&mut self) -> Vec<LinkArtifactItem> {
+        match self.strategy {
+            InstallStrategy::Hoisted => self.build_hoisted_artifacts(),
+            _ => self.build_nested_artifacts(),
+        }
+    }
+
+    /// node_modules/a/node_modules/b for the location [a, b]
+    fn location_path(location: &[String]) -> PathBuf {
+        let mut path = NODE_MODULES.clone();
+        for (index, folder) in location.iter().enumerate() {
+            if index > 0 {
+                path.push("node_modules");
+            }
+            path.push(folder);
+        }
+        path
+    }
+
+    /// Puts every package at the highest folder that has no other version of it
+    fn build_hoisted_artifacts(&mut self) -> Vec<LinkArtifactItem> {
+        let dependencies: HashMap<RegistryKey, Vec<RegistryKey>> = self
+            .recorder
+            .main_packages
+            .iter()
+            .chain(self.recorder.sub_dependencies.iter())
+            .map(|(key, meta)| {
+                let resolved = meta.resolved_dependencies.iter().flatten();
+                let dependencies = resolved
+                    .map(|(name, version)| RegistryKey {
+                        name: name.clone(),
+                        version: version.clone(),
+                    })
+                    .collect();
+                (key.clone(), dependencies)
+            })
+            .collect();
+
+        let mut roots: Vec<(String, RegistryKey)> = self
+            .resolved
+            .iter()
+            .filter(|r| r.parent.is_none())
+            .map(|r| {
+                let folder = r.alias().unwrap_or_else(|| r.package.name.clone());
+                (folder, r.package.clone().into())
+            })
+            .collect();
+        roots.sort_by(|a, b| a.0.cmp(&b.0));
+
+        self.hoisted = Hoister::new(&dependencies).hoist(roots);
+        self.hoisted
+            .iter()
+            .filter_map(|(location, key)| {
+                let from = self.extracted.get(&key.to_string())?;
+                Some(LinkArtifactItem::new(
+                    key.clone(),
+                    from.unzip_at.clone(),
+                    Self::location_path(location),
+                ))
+            })
+            .collect()
+    }
+
+    /// Nests every package below the chain of packages that pulled it in
+    fn build_nested_artifacts(&mut self) -> Vec<LinkArtifactItem> {
         let mut linker_artifacts = vec![];
 
         // So that the parents (things in our package.json come first)
         self.resolved.reverse();
 
+        // Children of an aliased package live below the alias folder
+        let aliases: HashMap<RegistryKey, String> = self
+            .resolved
+            .iter()
+            .filter_map(|r| Some((r.package.clone().into(), r.alias()?)))
+            .collect();
+
         for resolved in &self.resolved {
             let pkg = &resolved.package;
             let parent = &resolved.parent;
@@ -62,39 +157,54 @@ impl LinkerPipe {
             }
 
             let from = self.extracted.get(&pkg.to_string()).unwrap().clone();
+            let folder = resolved.alias().unwrap_or_else(|| pkg.name.clone());
 
             // If it is a child
             let to = if let Some(path_vec) = parent {
                 let mut path = PathBuf::new();
 
                 for p in path_vec {
-                    path.push(&p.name);
+                    path.push(aliases.get(p).unwrap_or(&p.name));
                     path.push("node_modules")
                 }
-                NODE_MODULES.join(&path).join(&pkg.name)
+                NODE_MODULES.join(&path).join(&folder)
             } else {
-                NODE_MODULES.join(&pkg.name)
+                NODE_MODULES.join(&folder)
             };
 
-            linker_artifacts.push(LinkArtifactItem::new(from.unzip_at, to));
+            linker_artifacts.push(LinkArtifactItem::new(pkg.clone().into(), from.unzip_at, to));
         }
 
         linker_artifacts
     }
 
+    /// Registry packages never change under the same version, git and local ones may
+    fn is_registry_package(&self, key: &RegistryKey) -> bool {
+        let meta = self
+            .recorder
+            .main_packages
+            .get(key)
+            .or_else(|| self.recorder.sub_dependencies.get(key));
+        meta.is_some_and(|meta| {
+            matches!(
+                meta.resolution,
+                None | Some(LockfileResolution::Integrity(_))
+            )
+        })
+    }
+
     async fn link(&mut self, artifacts: &Vec<LinkArtifactItem>) {
         for artifact in artifacts {
-            if let Err(e) = fs::create_dir_all(&artifact.to) {
-                CraftLogger::error(format!(
-                    "Failed to create directory: {}",
-                    artifact.to.display()
-                ));
-                CraftLogger::error(format!("Error: {}", e));
+            // Keeps what the install scripts of an earlier install built
+            if self.is_registry_package(&artifact.key)
+                && BuilderPipe::is_built(&artifact.key, &artifact.to)
+            {
+                continue;
             }
-
-            if let Err(e) = copy_dir(&artifact.from.join("package"), &artifact.to) {
+            let from = artifact.from.join("package");
+            if let Err(e) = self.import_package(&artifact.key, &from, &artifact.to) {
                 CraftLogger::error(format!(
-                    "Failed to copy from: {} to: {}: Error: {}",
+                    "Failed to import from: {} to: {}: Error: {}",
                     artifact.from.display(),
                     artifact.to.display(),
                     e
@@ -104,6 +214,157 @@ impl LinkerPipe {
         }
     }
 
+    /// Folder of a package in the virtual store, `@babel/core@7.0.0` becomes `@babel+core@7.0.0`
+    fn store_folder(key: &RegistryKey) -> String {
+        key.to_string().replace('/', "+")
+    }
+
+    /// node_modules/.craft/<name>@<version>/node_modules, holding the package and its dependencies
+    fn store_node_modules(key: &RegistryKey) -> PathBuf {
+        NODE_MODULES
+            .join(VIRTUAL_STORE)
+            .join(Self::store_folder(key))
+            .join("node_modules")
+    }
+
+    /// Replaces whatever is at `link` with a symlink to `target`
+    fn replace_with_symlink(target: &Path, link: &Path) -> io::Result<()> {
+        if let Ok(meta) = fs::symlink_metadata(link) {
+            match meta.is_dir() {
+                true => fs::remove_dir_all(link)?,
+                false => fs::remove_file(link)?,
+            }
+        }
+        if let Some(parent) = link.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        symlink_dir(target, link)
+    }
+
+    /// Puts a package at `to` from the content store, `link:` packages stay symlinks
+    /// and local directories, which are not in the store, are linked from their copy
+    fn import_package(&self, key: &RegistryKey, from: &Path, to: &Path) -> io::Result<()> {
+        if let Ok(meta) = fs::symlink_metadata(to) {
+            match meta.is_dir() {
+                true => fs::remove_dir_all(to)?,
+                false => fs::remove_file(to)?,
+            }
+        }
+
+        if fs::symlink_metadata(from)?.is_symlink() {
+            return Self::replace_with_symlink(&fs::read_link(from)?, to);
+        }
+        // Git, tarball and local packages are stored under their source
+        let stored = match self.extracted.get(&key.to_string()) {
+            Some(item) => item.package.store_key(),
+            None => key.clone(),
+        };
+        match self.store.index(&stored) {
+            Some(index) => self.store.import(&index, to, &self.import_method),
+            None => hardlink_dir(from, to),
+        }
+    }
+
+    /// pnpm style layout: every package lives at node_modules/.craft/<name>@<version>/node_modules/<name>
+    /// with its dependencies symlinked next to it, only direct dependencies are linked at the top
+    fn link_isolated(&self) -> io::Result<Vec<LinkArtifactItem>> {
+        let mut keys: HashSet<RegistryKey> = HashSet::new();
+        let mut linked = vec![];
+
+        for resolved in &self.resolved {
+            let key: RegistryKey = resolved.package.clone().into();
+            if !keys.insert(key.clone()) {
+                continue;
+            }
+            let Some(from) = self.extracted.get(&key.to_string()) else {
+                continue;
+            };
+            let to = Self::store_node_modules(&key).join(&key.name);
+            linked.push(LinkArtifactItem::new(
+                key.clone(),
+                from.unzip_at.clone(),
+                to.clone(),
+            ));
+            // Git and local packages may change under the same version
+            if fs::symlink_metadata(&to).is_ok() && resolved.package.dist.resolution.is_none() {
+                continue;
+            }
+            self.import_package(&key, &from.unzip_at.join("package"), &to)?;
+        }
+
+        for (key, meta) in self
+            .recorder
+            .main_packages
+            .iter()
+            .chain(self.recorder.sub_dependencies.iter())
+        {
+            if !keys.contains(key) {
+                continue;
+            }
+            for (name, version) in meta.resolved_dependencies.iter().flatten() {
+                let dependency = RegistryKey {
+                    name: name.clone(),
+                    version: version.clone(),
+                };
+                let target = Self::store_node_modules(&dependency).join(name);
+                Self::replace_with_symlink(&target, &Self::store_node_modules(key).join(name))?;
+            }
+        }
+
+        let mut direct: HashSet<String> = HashSet::new();
+        for resolved in self.resolved.iter().filter(|r| r.parent.is_none()) {
+            let key: RegistryKey = resolved.package.clone().into();
+            let folder = resolved.alias().unwrap_or_else(|| key.name.clone());
+            let target = Self::store_node_modules(&key).join(&key.name);
+            Self::replace_with_symlink(&target, &NODE_MODULES.join(&folder))?;
+            direct.insert(folder);
+        }
+
+        Self::prune_isolated(&direct, &keys)?;
+        Ok(linked)
+    }
+
+    /// Removes top level packages that are no longer direct dependencies and stale store entries
+    fn prune_isolated(direct: &HashSet<String>, keys: &HashSet<RegistryKey>) -> io::Result<()> {
+        let store: HashSet<String> = keys.iter().map(Self::store_folder).collect();
+        let store_dir = NODE_MODULES.join(VIRTUAL_STORE);
+        if !store_dir.exists() {
+            return Ok(());
+        }
+
+        for entry in fs::read_dir(&store_dir)? {
+            let entry = entry?;
+            if !store.contains(entry.file_name().to_string_lossy().as_ref()) {
+                fs::remove_dir_all(entry.path())?;
+            }
+        }
+
+        let mut folders = vec![];
+        for entry in fs::read_dir(NODE_MODULES.as_path())? {
+            let name = entry?.file_name().to_string_lossy().to_string();
+            match name {
+                name if name.starts_with('.') => {}
+                scope if scope.starts_with('@') => {
+                    for entry in fs::read_dir(NODE_MODULES.join(&scope))? {
+                        let name = entry?.file_name().to_string_lossy().to_string();
+                        folders.push(format!("{}/{}", scope, name));
+                    }
+                }
+                name => folders.push(name),
+            }
+        }
+
+        for folder in folders.iter().filter(|f| !direct.contains(*f)) {
+            let path = NODE_MODULES.join(folder);
+            match fs::symlink_metadata(&path)?.is_dir() {
+                true => fs::remove_dir_all(&path)?,
+                false => fs::remove_file(&path)?,
+            }
+        }
+
+        Ok(())
+    }
+
     fn prepare_bin_dir(bin_dir_to_create: &PathBuf, rb: &ResolvedBinary) {
         if fs::metadata(bin_dir_to_create).is_err() {
             let result = fs::create_dir(bin_dir_to_create);
@@ -136,6 +397,14 @@ impl LinkerPipe {
                 log::error!("Error: {}", e);
                 return;
             }
+
+            // Scripts and exec run the shim straight from PATH
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                let shim = bin_dir_to_create.join(&rb.name);
+                let _ = fs::set_permissions(shim, fs::Permissions::from_mode(0o755));
+            }
         }
 
         if fs::metadata(bin_dir_to_create.join(format!("{}.CMD", rb.name))).is_err() {
@@ -196,47 +465,137 @@ impl LinkerPipe {
             }
 
             if let Some(bin) = &p.1.bin {
-                match bin {
-                    BinType::Bin(s) => {
-                        let path_to_bin = PathBuf::from("node_modules").join(".bin");
-                        let resolved_binary = ResolvedBinary {
-                            name: s.rsplit('/').next().unwrap().replace(".js", ""),
-                            path: s.clone(),
-                            package_name: p.1.name.clone(),
-                        };
-                        Self::prepare_bin_dir(&path_to_bin, &resolved_binary);
-                    }
-                    BinType::BinMappings(a) => {
-                        a.iter().for_each(|s| {
-                            let resolved_binary = ResolvedBinary {
-                                name: s.0.to_string(),
-                                path: s.1.clone(),
-                                package_name: p.1.name.clone(),
-                            };
-                            let path_to_bin = PathBuf::from("node_modules").join(".bin");
-                            Self::prepare_bin_dir(&path_to_bin, &resolved_binary);
-                        });
-                    }
+                let path_to_bin = PathBuf::from("node_modules").join(".bin");
+                for resolved_binary in Self::binaries(&p.1.name, bin) {
+                    Self::prepare_bin_dir(&path_to_bin, &resolved_binary);
                 }
             }
         })
     }
+
+    fn binaries(package_name: &str, bin: &BinType) -> Vec<ResolvedBinary> {
+        match bin {
+            BinType::Bin(s) => vec![ResolvedBinary {
+                name: s.rsplit('/').next().unwrap().replace(".js", ""),
+                path: s.clone(),
+                package_name: package_name.to_string(),
+            }],
+            BinType::BinMappings(a) => a
+                .iter()
+                .map(|s| ResolvedBinary {
+                    name: s.0.to_string(),
+                    path: s.1.clone(),
+                    package_name: package_name.to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Hoisted packages at the top level expose their binaries like direct dependencies,
+    /// those of direct dependencies are linked first and win name clashes
+    fn link_hoisted_binaries(&self) {
+        let path_to_bin = PathBuf::from("node_modules").join(".bin");
+
+        for (_, key) in self.hoisted.iter().filter(|(l, _)| l.len() == 1) {
+            let Some(bin) = self
+                .recorder
+                .sub_dependencies
+                .get(key)
+                .and_then(|p| p.bin.as_ref())
+            else {
+                continue;
+            };
+            for resolved_binary in Self::binaries(&key.name, bin) {
+                Self::prepare_bin_dir(&path_to_bin, &resolved_binary);
+            }
+        }
+    }
 }
 
 // ─────────────────────────────────────────────────────────────────────────────
 
 #[async_trait]
-impl Pipe<()> for LinkerPipe {
-    async fn run(&mut self) -> Result<(), ExecutionError> {
+/// Returns where each package was placed, packages of the nested layout may appear twice
+impl Pipe<Vec<LinkArtifactItem>> for LinkerPipe {
+    async fn run(&mut self) -> Result<Vec<LinkArtifactItem>, ExecutionError> {
         let _ = self.tx.send(ProgressAction::new(Phase::Linking));
 
-        let artifacts = self.build_linker_artifacts();
-
-        self.link(&artifacts).await;
+        let linked = if self.strategy == InstallStrategy::Linked {
+            self.link_isolated().map_err(|e| {
+                ExecutionError::JobExecutionFailed(
+                    "Failed to link packages".to_string(),
+                    e.to_string(),
+                )
+            })?
+        } else {
+            // Left over from a previous isolated install
+            let _ = fs::remove_dir_all(NODE_MODULES.join(VIRTUAL_STORE));
+            let artifacts = self.build_linker_artifacts();
+            self.link(&artifacts).await;
+            artifacts
+        };
         self.link_binaries().await;
+        self.link_hoisted_binaries();
 
-        Ok(())
+        Ok(linked)
     }
 }
 
 // ─────────────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_store_folder() {
+        let key = RegistryKey {
+            name: "@babel/core".to_string(),
+            version: "7.0.0".to_string(),
+        };
+        assert_eq!(LinkerPipe::store_folder(&key), "@babel+core@7.0.0");
+    }
+
+    #[test]
+    fn test_import_package() {
+        let root = tempfile::tempdir().unwrap();
+        let mut linker = LinkerPipe::new(
+            std::sync::mpsc::channel().0,
+            vec![],
+            HashMap::new(),
+            PackageRecorder::default(),
+            InstallStrategy::Linked,
+            PackageImportMethod::Auto,
+        );
+        linker.store = ContentStore::at(root.path().join("cas"));
+        let key = RegistryKey {
+            name: "lib".to_string(),
+            version: "1.0.0".to_string(),
+        };
+
+        let extracted = root.path().join("extracted");
+        fs::create_dir_all(extracted.join("lib")).unwrap();
+        fs::write(extracted.join("package.json"), "{}").unwrap();
+        fs::write(extracted.join("lib/index.js"), "").unwrap();
+        // Left behind by the nested layout, must not leak into the store
+        fs::create_dir_all(extracted.join("node_modules")).unwrap();
+        symlink_dir(&extracted, extracted.join("node_modules/self")).unwrap();
+
+        let stored = root.path().join("store/node_modules/lib");
+        linker.import_package(&key, &extracted, &stored).unwrap();
+        assert!(stored.join("lib/index.js").is_file());
+        assert!(!stored.join("node_modules/self").exists());
+
+        // Once indexed, the files come from the content store
+        linker.store.add_package(&key, &extracted).unwrap();
+        fs::remove_file(extracted.join("lib/index.js")).unwrap();
+        linker.import_package(&key, &extracted, &stored).unwrap();
+        assert!(stored.join("lib/index.js").is_file());
+
+        let linked = root.path().join("linked");
+        symlink_dir(&extracted, &linked).unwrap();
+        let stored = root.path().join("store/node_modules/linked");
+        linker.import_package(&key, &linked, &stored).unwrap();
+        assert!(fs::symlink_metadata(&stored).unwrap().is_symlink());
+    }
+}
@@ -0,0 +1,552 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::path::Path;
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use super::artifacts::{ExtractArtifactsMap, ResolveArtifacts, ResolvedItem};
+use crate::actors::{PackageType, PreprocessDependencyInstall};
+use crate::cache::RegistryKey;
+use crate::contracts::{Lockfile, Phase, Pipe, ProgressAction, LOCK_FILE_NAME};
+use crate::errors::ExecutionError;
+use crate::lockfile::constants::CURRENT_IMPORTER;
+use crate::lockfile::lock_file_actor::LockFileActor;
+use crate::lockfile::lockfile_structure::{
+    ImporterSections, LockfileResolution, LockfileStructure, ResolvedDependencies,
+    ResolvedDependency,
+};
+use crate::package::{
+    BinType, Distribution, NpmPackage, Overrides, PackageJson, PackageMetaRecorder, PackageRecorder,
+};
+use crate::registry::NpmRegistry;
+
+// ─── LockfileResolverPipe ────────────────────────────────────────────────────
+
+/// Builds the resolve artifacts straight from craft-lock.yaml, without any registry calls
+pub struct LockfileResolverPipe {
+    packages: Vec<PackageType>,
+    registry: Arc<NpmRegistry>,
+    tx: Sender<ProgressAction>,
+}
+
+/// A locked sub dependency together with the trace of packages that pulled it in
+type LockedDependency = (RegistryKey, Vec<RegistryKey>, NpmPackage, Option<bool>);
+
+#[derive(Deserialize)]
+struct PackageBinaries {
+    bin: Option<BinType>,
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+
+impl LockfileResolverPipe {
+    pub fn new(
+        packages: Vec<PackageType>,
+        registry: Arc<NpmRegistry>,
+        tx: Sender<ProgressAction>,
+    ) -> Self {
+        Self {
+            packages,
+            registry,
+            tx,
+        }
+    }
+
+    fn locked_section<'a>(
+        importer: &'a ImporterSections,
+        package_type: &PackageType,
+    ) -> Option<&'a ResolvedDependencies> {
+        match package_type {
+            PackageType::Prod(_) => importer.dependencies.as_ref(),
+            PackageType::Dev(_) => importer.dev_dependencies.as_ref(),
+            PackageType::Optional(_) => importer.optional_dependencies.as_ref(),
+            PackageType::Peer(_) => importer.peer_dependencies.as_ref(),
+            PackageType::Global(_) => None,
+        }
+    }
+
+    /// Returns the importer entry recorded for a direct dependency
+    pub fn locked_dependency<'a>(
+        importer: &'a ImporterSections,
+        package_type: &PackageType,
+    ) -> Option<&'a ResolvedDependency> {
+        let (name, _) = package_type.get_parts();
+        Self::locked_section(importer, package_type).and_then(|s| s.get(&name))
+    }
+
+    fn compare_section(
+        title: &str,
+        wanted: Option<&HashMap<String, String>>,
+        locked: Option<&ResolvedDependencies>,
+        problems: &mut Vec<String>,
+    ) {
+        let empty_wanted = HashMap::new();
+        let empty_locked = HashMap::new();
+        let wanted = wanted.unwrap_or(&empty_wanted);
+        let locked = locked.unwrap_or(&empty_locked);
+
+        for (name, specifier) in wanted {
+            match locked.get(name) {
+                None => problems.push(format!("{} {}@{} is not locked", title, name, specifier)),
+                Some(l) if !l.matches_specifier(specifier) => problems.push(format!(
+                    "{} {} wants {} but {} is locked",
+                    title, name, specifier, l.specifier
+                )),
+                _ => {}
+            }
+        }
+
+        for name in locked.keys() {
+            if !wanted.contains_key(name) {
+                problems.push(format!("{} {} is no longer in package.json", title, name));
+            }
+        }
+    }
+
+    /// Checks that the importer entries still describe what package.json asks for
+    pub fn verify_importer(
+        importer: &ImporterSections,
+        package_json: &PackageJson,
+    ) -> Result<(), ExecutionError> {
+        let mut problems = vec![];
+
+        Self::compare_section(
+            "dependencies",
+            package_json.dependencies.as_ref(),
+            importer.dependencies.as_ref(),
+            &mut problems,
+        );
+        Self::compare_section(
+            "devDependencies",
+            package_json.dev_dependencies.as_ref(),
+            importer.dev_dependencies.as_ref(),
+            &mut problems,
+        );
+        Self::compare_section(
+            "optionalDependencies",
+            package_json.optional_dependencies.as_ref(),
+            importer.optional_dependencies.as_ref(),
+            &mut problems,
+        );
+
+        if problems.is_empty() {
+            return Ok(());
+        }
+
+        problems.sort();
+        Err(ExecutionError::FrozenLockfile(format!(
+            "{} is not up to date with package.json: {}",
+            LOCK_FILE_NAME,
+            problems.join(", ")
+        )))
+    }
+
+    /// Checks that the lockfile was resolved with the overrides package.json asks for
+    pub fn verify_overrides(
+        lockfile: &LockfileStructure,
+        package_json: &PackageJson,
+    ) -> Result<(), ExecutionError> {
+        let changed = Overrides::new(package_json).changed_since(lockfile.overrides.as_ref());
+        if changed.is_empty() {
+            return Ok(());
+        }
+
+        let mut changed: Vec<_> = changed.into_iter().collect();
+        changed.sort();
+        Err(ExecutionError::FrozenLockfile(format!(
+            "{} is not up to date with the overrides of package.json: {}",
+            LOCK_FILE_NAME,
+            changed.join(", ")
+        )))
+    }
+
+    fn snapshot_dependencies(
+        lockfile: &LockfileStructure,
+        key: &RegistryKey,
+    ) -> Option<HashMap<String, String>> {
+        let snapshot = lockfile.snapshots.as_ref()?.get(&key.to_string())?;
+
+        let mut dependencies = snapshot.dependencies.clone().unwrap_or_default();
+        if let Some(optional) = &snapshot.optional_dependencies {
+            dependencies.extend(optional.clone());
+        }
+
+        if dependencies.is_empty() {
+            return None;
+        }
+        Some(dependencies)
+    }
+
+    fn to_npm_package(
+        lockfile: &LockfileStructure,
+        registry: &NpmRegistry,
+        key: &RegistryKey,
+    ) -> Result<(NpmPackage, Option<bool>), ExecutionError> {
+        let meta = lockfile
+            .packages
+            .as_ref()
+            .and_then(|p| p.get(&key.to_string()))
+            .ok_or_else(|| {
+                ExecutionError::FrozenLockfile(format!("{} is missing from packages", key))
+            })?;
+
+        let package = NpmPackage {
+            name: key.name.clone(),
+            version: key.version.clone(),
+            dependencies: Self::snapshot_dependencies(lockfile, key),
+            peer_dependencies: meta.peer_dependencies.clone(),
+            peer_dependencies_meta: meta.peer_dependencies_meta.clone(),
+            cpu: meta.cpu.clone(),
+            os: meta.os.clone(),
+            bin: meta.bin.clone(),
+            dist: Distribution {
+                integrity: meta
+                    .resolution
+                    .as_ref()
+                    .and_then(|r| r.integrity())
+                    .map(|i| i.to_string()),
+                tarball: match &meta.resolution {
+                    Some(LockfileResolution::Tarball(t)) => t.tarball.clone(),
+                    _ => registry.tarball_url(&key.name, &key.version),
+                },
+                resolution: meta
+                    .resolution
+                    .clone()
+                    .filter(|r| !matches!(r, LockfileResolution::Integrity(_))),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        Ok((package, meta.has_bin))
+    }
+
+    fn to_recorder(package: NpmPackage, has_bin: Option<bool>) -> PackageMetaRecorder {
+        let mut meta: PackageMetaRecorder = package.into();
+        meta.has_bin = has_bin;
+        meta
+    }
+
+    /// Walks the snapshots below `root`, failing if any package is missing from the lockfile
+    fn collect_tree(
+        lockfile: &LockfileStructure,
+        registry: &NpmRegistry,
+        root: &RegistryKey,
+        dependencies: Option<HashMap<String, String>>,
+    ) -> Result<Vec<LockedDependency>, ExecutionError> {
+        let mut tree = vec![];
+        let mut visited = HashSet::from([root.clone()]);
+        let mut queue = VecDeque::from([(root.clone(), vec![], dependencies)]);
+
+        while let Some((key, mut trace, dependencies)) = queue.pop_front() {
+            trace.push(key);
+
+            for (dep_name, dep_version) in dependencies.unwrap_or_default() {
+                let dep_key = RegistryKey {
+                    name: dep_name,
+                    version: dep_version,
+                };
+                let (package, has_bin) = Self::to_npm_package(lockfile, registry, &dep_key)?;
+
+                if visited.insert(dep_key.clone()) {
+                    queue.push_back((dep_key.clone(), trace.clone(), package.dependencies.clone()));
+                }
+                tree.push((dep_key, trace.clone(), package, has_bin));
+            }
+        }
+
+        Ok(tree)
+    }
+
+    /// Restores a direct dependency and everything it depends on from the lockfile.
+    /// Nothing is recorded unless the whole tree is present in the lockfile.
+    pub fn restore_package(
+        lockfile: &LockfileStructure,
+        registry: &NpmRegistry,
+        name: &str,
+        locked: &ResolvedDependency,
+        package_type: PackageType,
+        artifacts: &mut ResolveArtifacts,
+        recorder: &mut PackageRecorder,
+    ) -> Result<(), ExecutionError> {
+        let root = locked.package_key(name);
+        let (package, has_bin) = Self::to_npm_package(lockfile, registry, &root)?;
+        let tree = Self::collect_tree(lockfile, registry, &root, package.dependencies.clone())?;
+
+        let optional = matches!(package_type, PackageType::Optional(_));
+        let item = ResolvedItem::new(
+            package.clone(),
+            None,
+            locked.specifier.clone(),
+            package_type,
+        );
+        artifacts.insert(
+            ResolvedItem::artifact_key(&package, item.alias().as_deref()),
+            item,
+        );
+        let meta = recorder
+            .main_packages
+            .entry(root)
+            .or_insert_with(|| PackageMetaRecorder {
+                optional,
+                ..Self::to_recorder(package, has_bin)
+            });
+        meta.optional &= optional;
+
+        for (dep_key, trace, mut package, has_bin) in tree {
+            match recorder.sub_dependencies.get_mut(&dep_key) {
+                Some(existing) => match existing.depth_traces {
+                    Some(ref mut d) => d.push(trace.clone()),
+                    None => existing.depth_traces = Some(vec![trace.clone()]),
+                },
+                None => {
+                    package.depth_traces = Some(vec![trace.clone()]);
+                    recorder
+                        .sub_dependencies
+                        .insert(dep_key.clone(), Self::to_recorder(package.clone(), has_bin));
+                }
+            }
+
+            if artifacts.get(&dep_key.to_string()).is_none() {
+                artifacts.insert(
+                    dep_key.to_string(),
+                    ResolvedItem::new(
+                        package,
+                        Some(trace),
+                        dep_key.version.clone(),
+                        PackageType::Prod(dep_key.to_string()),
+                    ),
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The lockfile only records `hasBin`, so the binaries are read back from the extracted packages
+    pub fn restore_binaries(recorder: &mut PackageRecorder, extracted: &ExtractArtifactsMap) {
+        recorder
+            .main_packages
+            .values_mut()
+            .chain(recorder.sub_dependencies.values_mut())
+            .filter(|p| p.has_bin == Some(true) && p.bin.is_none())
+            .for_each(|p| {
+                let Some(item) = extracted.get(&p.to_string()) else {
+                    return;
+                };
+                let manifest = item.unzip_at.join("package").join("package.json");
+
+                if let Ok(content) = fs::read_to_string(manifest) {
+                    if let Ok(binaries) = serde_json::from_str::<PackageBinaries>(&content) {
+                        p.bin = binaries.bin;
+                    }
+                }
+            });
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[async_trait]
+impl Pipe<(ResolveArtifacts, PackageRecorder)> for LockfileResolverPipe {
+    async fn run(&mut self) -> Result<(ResolveArtifacts, PackageRecorder), ExecutionError> {
+        let _ = self.tx.send(ProgressAction::new(Phase::Resolving));
+
+        let lock_path = Path::new(LOCK_FILE_NAME);
+        if !lock_path.exists() {
+            return Err(ExecutionError::FrozenLockfile(format!(
+                "{} was not found",
+                LOCK_FILE_NAME
+            )));
+        }
+
+        let lockfile = LockFileActor::read_lock_file(lock_path)
+            .map_err(|e| ExecutionError::FrozenLockfile(e.to_string()))?;
+        let importer = lockfile
+            .importers
+            .as_ref()
+            .and_then(|i| i.get(CURRENT_IMPORTER))
+            .cloned()
+            .unwrap_or_default();
+
+        let package_json = PreprocessDependencyInstall::read_package_json()?;
+        Self::verify_importer(&importer, &package_json)?;
+        Self::verify_overrides(&lockfile, &package_json)?;
+
+        let mut artifacts = ResolveArtifacts::new();
+        let mut recorder = PackageRecorder::default();
+
+        for package in &self.packages {
+            let (name, _) = package.get_parts();
+            let locked = Self::locked_dependency(&importer, package)
+                .ok_or_else(|| ExecutionError::FrozenLockfile(format!("{} is not locked", name)))?;
+
+            Self::restore_package(
+                &lockfile,
+                &self.registry,
+                &name,
+                locked,
+                package.clone(),
+                &mut artifacts,
+                &mut recorder,
+            )?;
+        }
+
+        Ok((artifacts, recorder))
+    }
+}
+
+// ─── Tests ───────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conf::NpmConfig;
+    use crate::contracts::PipeArtifact;
+    use crate::network::Http;
+    use std::collections::BTreeMap;
+
+    const LOCKFILE: &str = r#"
+lockfileVersion: '1.0'
+
+importers:
+
+  .:
+    dependencies:
+      react:
+        specifier: ^18.0.0
+        version: 18.2.0
+
+packages:
+
+  js-tokens@4.0.0:
+    resolution: {integrity: sha512-tokens}
+
+  loose-envify@1.4.0:
+    resolution: {integrity: sha512-envify}
+    hasBin: true
+
+  react@18.2.0:
+    resolution: {integrity: sha512-react}
+
+snapshots:
+
+  js-tokens@4.0.0: {}
+
+  loose-envify@1.4.0:
+    dependencies:
+      js-tokens: 4.0.0
+    hasBin: true
+
+  react@18.2.0:
+    dependencies:
+      loose-envify: 1.4.0
+"#;
+
+    fn package_json(dependencies: &[(&str, &str)]) -> PackageJson {
+        let deps = dependencies
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+
+        PackageJson {
+            dependencies: Some(deps),
+            dev_dependencies: None,
+            optional_dependencies: None,
+            scripts: None,
+            overrides: None,
+            resolutions: None,
+            pnpm: None,
+        }
+    }
+
+    fn importer(lockfile: &LockfileStructure) -> ImporterSections {
+        lockfile.importers.as_ref().unwrap()[CURRENT_IMPORTER].clone()
+    }
+
+    #[test]
+    fn test_verify_importer() {
+        let lockfile: LockfileStructure = serde_yaml_ng::from_str(LOCKFILE).unwrap();
+        let importer = importer(&lockfile);
+
+        let up_to_date = package_json(&[("react", "^18.0.0")]);
+        assert!(LockfileResolverPipe::verify_importer(&importer, &up_to_date).is_ok());
+
+        let changed = package_json(&[("react", "^17.0.0")]);
+        assert!(LockfileResolverPipe::verify_importer(&importer, &changed).is_err());
+
+        let added = package_json(&[("react", "^18.0.0"), ("lodash", "^4")]);
+        assert!(LockfileResolverPipe::verify_importer(&importer, &added).is_err());
+
+        let removed = package_json(&[]);
+        assert!(LockfileResolverPipe::verify_importer(&importer, &removed).is_err());
+    }
+
+    #[test]
+    fn test_verify_overrides() {
+        let mut lockfile: LockfileStructure = serde_yaml_ng::from_str(LOCKFILE).unwrap();
+        let mut package_json = package_json(&[("react", "^18.0.0")]);
+        assert!(LockfileResolverPipe::verify_overrides(&lockfile, &package_json).is_ok());
+
+        package_json.overrides = Some(serde_json::json!({ "js-tokens": "4.0.0" }));
+        assert!(LockfileResolverPipe::verify_overrides(&lockfile, &package_json).is_err());
+
+        lockfile.overrides = Overrides::new(&package_json).to_lockfile();
+        assert!(LockfileResolverPipe::verify_overrides(&lockfile, &package_json).is_ok());
+    }
+
+    #[test]
+    fn test_restore_package() {
+        let lockfile: LockfileStructure = serde_yaml_ng::from_str(LOCKFILE).unwrap();
+        let importer = importer(&lockfile);
+        let locked = &importer.dependencies.as_ref().unwrap()["react"];
+
+        let mut artifacts = ResolveArtifacts::new();
+        let mut recorder = PackageRecorder::default();
+        let config = NpmConfig::new(BTreeMap::new());
+        let registry = NpmRegistry::new(&config, Http::new(&config).unwrap());
+        LockfileResolverPipe::restore_package(
+            &lockfile,
+            &registry,
+            "react",
+            locked,
+            PackageType::Prod("react@^18.0.0".to_string()),
+            &mut artifacts,
+            &mut recorder,
+        )
+        .unwrap();
+
+        assert_eq!(artifacts.get_artifacts().len(), 3);
+        assert!(artifacts.get("react@18.2.0").unwrap().parent.is_none());
+
+        let tokens = artifacts.get("js-tokens@4.0.0").unwrap();
+        let parents: Vec<String> = tokens
+            .parent
+            .as_ref()
+            .unwrap()
+            .iter()
+            .map(|p| p.to_string())
+            .collect();
+        assert_eq!(parents, vec!["react@18.2.0", "loose-envify@1.4.0"]);
+        assert_eq!(
+            tokens.package.dist.tarball,
+            "https://registry.npmjs.org/js-tokens/-/js-tokens-4.0.0.tgz"
+        );
+
+        assert_eq!(recorder.main_packages.len(), 1);
+        assert_eq!(recorder.sub_dependencies.len(), 2);
+        let envify = recorder
+            .sub_dependencies
+            .values()
+            .find(|p| p.name == "loose-envify")
+            .unwrap();
+        assert_eq!(envify.has_bin, Some(true));
+        assert_eq!(
+            envify.resolution.as_ref().unwrap().integrity(),
+            Some("sha512-envify")
+        );
+    }
+}
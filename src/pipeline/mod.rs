@@ -1,20 +1,25 @@
 mod artifacts;
 pub mod binary_templates;
+mod builder;
 mod cache_clean;
 mod config_reader;
 mod downloader;
 mod extractor;
+mod hoist;
 mod linker;
+mod lockfile_resolver;
 mod resolver;
 
 pub use resolver::ResolverPipe;
 
+pub use builder::{BuildPolicy, BuilderPipe};
+
 pub use downloader::DownloaderPipe;
 pub use extractor::ExtractorPipe;
 pub use linker::LinkerPipe;
+pub use lockfile_resolver::LockfileResolverPipe;
 
 pub use artifacts::ResolvedItem;
 pub use cache_clean::CacheCleanPipe;
-pub use config_reader::determine_global_config_file_location;
-pub use config_reader::parse_config;
+pub use config_reader::config_file_location;
 pub use config_reader::ConfigReader;
@@ -1,125 +1,205 @@
-use crate::actors::PackageType;
-use crate::cache::{RegistryCache, RegistryKey};
-use crate::contracts::{PersistentCache, Phase, Pipe, ProgressAction, Registry};
+use crate::actors::{PackageType, PreprocessDependencyInstall};
+use crate::cache::RegistryKey;
+use crate::contracts::{Lockfile, Logger, Phase, Pipe, ProgressAction, LOCK_FILE_NAME};
 use crate::errors::{ExecutionError, NetworkError};
+use crate::lockfile::constants::CURRENT_IMPORTER;
+use crate::lockfile::lock_file_actor::LockFileActor;
+use crate::lockfile::lockfile_structure::LockfileStructure;
 use crate::logger::CraftLogger;
-use crate::package::{NpmPackage, Package, PackageRecorder};
-use crate::registry::GitRegistry;
-use crate::registry::NpmRegistry;
+use crate::package::{Overrides, Package, PackageMetaRecorder, PackageRecorder, Registry};
+use crate::registry::{LocalRegistry, Registries};
 use async_recursion::async_recursion;
 use async_trait::async_trait;
 use futures::future;
 use futures::future::join_all;
 use futures::lock::Mutex;
+use std::collections::HashSet;
+use std::path::Path;
 use std::sync::mpsc::Sender;
 use std::sync::Arc;
 
 use super::artifacts::{ResolveArtifacts, ResolvedItem};
+use super::LockfileResolverPipe;
 
 // ─── ResolverPipe ────────────────────────────────────────────────────────────
 
-#[derive(Debug)]
-pub struct ResolverPipe<C: PersistentCache<NpmPackage>> {
+pub struct ResolverPipe {
     packages: Vec<PackageType>,
-    cache: Arc<Mutex<C>>,
-
-    #[allow(dead_code)]
-    git_registry: GitRegistry,
 
     artifacts: Arc<Mutex<ResolveArtifacts>>,
 
+    lockfile: Option<LockfileStructure>,
+
+    registries: Arc<Registries>,
+
+    overrides: Arc<Overrides>,
+    /// Packages whose override changed since the lockfile was written
+    changed_overrides: HashSet<String>,
+
     tx: Sender<ProgressAction>,
 }
 
 // ─────────────────────────────────────────────────────────────────────────────
 
-impl ResolverPipe<RegistryCache> {
-    pub fn new(packages: Vec<PackageType>, tx: Sender<ProgressAction>) -> Self {
-        let un_arced_cache = RegistryCache::default();
+impl ResolverPipe {
+    pub fn new(
+        packages: Vec<PackageType>,
+        registries: Arc<Registries>,
+        tx: Sender<ProgressAction>,
+    ) -> Self {
         let un_arced_articated = ResolveArtifacts::new();
         Self {
             packages,
-            cache: Arc::new(Mutex::new(un_arced_cache)),
-            git_registry: GitRegistry::new(),
             artifacts: Arc::new(Mutex::new(un_arced_articated)),
+            lockfile: None,
+            registries,
+            overrides: Arc::new(Overrides::default()),
+            changed_overrides: HashSet::new(),
             tx,
         }
     }
 
-    #[async_recursion]
-    async fn resolve_pkg(
-        package: &Package,
-        parent: Option<Vec<RegistryKey>>,
-        package_recorder: Arc<Mutex<PackageRecorder>>,
-        cache_arc: Arc<Mutex<RegistryCache>>,
-        artifacts: Arc<Mutex<ResolveArtifacts>>,
-    ) -> Result<(), NetworkError> {
-        CraftLogger::verbose(format!("Resolving package: {}", package));
-        let mut cache = { cache_arc.lock().await.clone() };
-
-        let cached_pkg = cache.get(&package.clone().into()).await;
+    fn read_lockfile() -> Option<LockfileStructure> {
+        let path = Path::new(LOCK_FILE_NAME);
+        if !path.exists() {
+            return None;
+        }
 
-        if let Some(pkg) = cached_pkg.clone() {
-            if artifacts.lock().await.get(&pkg.to_string()).is_some() {
-                CraftLogger::verbose(format!("Package found in artifacts: {}", package));
+        match LockFileActor::read_lock_file(path) {
+            Ok(lockfile) => Some(lockfile),
+            Err(e) => {
+                CraftLogger::warn(format!("Ignoring {}: {}", LOCK_FILE_NAME, e));
+                None
             }
         }
+    }
 
-        let final_key: RegistryKey;
-        if let Some(pkg) = cached_pkg {
-            CraftLogger::verbose(format!("Package found in cache: {}", package));
-            final_key = pkg.clone().into();
-            artifacts.lock().await.insert(
-                pkg.to_string().clone(),
-                ResolvedItem::new(
-                    pkg.clone(),
-                    parent.clone(),
-                    package.raw_version.clone(),
-                    package.package_type.clone(),
-                ),
-            );
-        } else {
-            let remote_package = NpmRegistry::new().fetch(package).await.unwrap();
-
-            let pkg_cache_key = remote_package.to_string();
-            final_key = remote_package.clone().into();
-            {
-                artifacts.lock().await.insert(
-                    pkg_cache_key.clone(),
-                    ResolvedItem::new(
-                        remote_package.clone(),
-                        parent.clone(),
-                        package.raw_version.clone(),
-                        package.package_type.clone(),
-                    ),
-                );
-            }
+    /// Keeps the locked version of a direct dependency when its specifier did not change.
+    /// Returns false when the package has to be resolved against the registry.
+    async fn restore_locked(
+        &self,
+        pkg: &PackageType,
+        package_recorder: Arc<Mutex<PackageRecorder>>,
+    ) -> bool {
+        let Some(lockfile) = &self.lockfile else {
+            return false;
+        };
+        let Some(importer) = lockfile
+            .importers
+            .as_ref()
+            .and_then(|i| i.get(CURRENT_IMPORTER))
+        else {
+            return false;
+        };
 
-            {
-                let mut cache = cache_arc.lock().await;
-                cache
-                    .set(&remote_package.clone().into(), remote_package.clone())
-                    .await;
+        // Local packages are read again, their manifest may have changed since the last install
+        let package = Package::new(pkg.clone());
+        if package.registry == Registry::Local {
+            return false;
+        }
+        let Some(locked) = LockfileResolverPipe::locked_dependency(importer, pkg) else {
+            return false;
+        };
+        if !locked.matches_specifier(&package.specifier()) {
+            return false;
+        }
+        if self.has_changed_override(lockfile, &locked.package_key(&package.name)) {
+            CraftLogger::verbose(format!("Re-resolving {}: overrides changed", package));
+            return false;
+        }
+
+        let mut artifacts = self.artifacts.lock().await;
+        let mut recorder = package_recorder.lock().await;
+        match LockfileResolverPipe::restore_package(
+            lockfile,
+            &self.registries.npm,
+            &package.name,
+            locked,
+            pkg.clone(),
+            &mut artifacts,
+            &mut recorder,
+        ) {
+            Ok(()) => {
+                CraftLogger::verbose(format!("Package restored from lockfile: {}", package));
+                true
+            }
+            Err(e) => {
+                CraftLogger::verbose(format!("Re-resolving {}: {}", package, e));
+                false
             }
         }
+    }
+
+    /// Whether the locked tree of a dependency contains a package whose override changed
+    fn has_changed_override(&self, lockfile: &LockfileStructure, root: &RegistryKey) -> bool {
+        if self.changed_overrides.is_empty() {
+            return false;
+        }
+
+        lockfile
+            .reachable_packages(vec![root.to_string()])
+            .iter()
+            .any(|key| {
+                self.changed_overrides
+                    .iter()
+                    .any(|name| key.starts_with(&format!("{}@", name)))
+            })
+    }
 
-        let mut package = {
-            let mut cache = cache_arc.lock().await;
-            cache.get(&final_key).await.clone().unwrap()
+    #[async_recursion]
+    async fn resolve_pkg(
+        package: &Package,
+        parent: Option<Vec<RegistryKey>>,
+        package_recorder: Arc<Mutex<PackageRecorder>>,
+        artifacts: Arc<Mutex<ResolveArtifacts>>,
+        registries: Arc<Registries>,
+        overrides: Arc<Overrides>,
+    ) -> Result<(), NetworkError> {
+        // Direct dependencies are taken from package.json as written
+        let overridden;
+        let package = match parent
+            .as_deref()
+            .and_then(|parents| overrides.version_for(package, parents))
+        {
+            Some(version) => {
+                CraftLogger::verbose(format!("Overriding {} with {}", package, version));
+                overridden = package.with_version(version);
+                &overridden
+            }
+            None => package,
         };
+        CraftLogger::verbose(format!("Resolving package: {}", package));
+
+        // Registry packuments are cached and revalidated by the npm registry, git references
+        // move and local or remote tarballs can change, they are resolved on every install
+        let remote_package = registries.fetch(package).await?;
+        let final_key: RegistryKey = remote_package.clone().into();
+        artifacts.lock().await.insert(
+            ResolvedItem::artifact_key(&remote_package, package.alias.as_deref()),
+            ResolvedItem::new(
+                remote_package.clone(),
+                parent.clone(),
+                package.specifier(),
+                package.package_type.clone(),
+            ),
+        );
+        let optional = matches!(package.package_type, PackageType::Optional(_));
+        let mut package = remote_package;
 
         {
             let mut package_recorder = package_recorder.lock().await;
             match parent {
                 None => {
                     // This is okay as we only insert the same version
-                    if !package_recorder.main_packages.contains_key(&final_key) {
-                        package_recorder
-                            .main_packages
-                            .insert(final_key.clone(), package.clone().into());
-                    }
-
-                    // Else can be skipped because we won't update the parent as a consequence
+                    let meta = package_recorder
+                        .main_packages
+                        .entry(final_key.clone())
+                        .or_insert_with(|| PackageMetaRecorder {
+                            optional,
+                            ..package.clone().into()
+                        });
+                    // Also a required dependency, its build has to succeed
+                    meta.optional &= optional;
                 }
                 Some(ref parents) => {
                     // It can be that multiple dependencies have this as a sub dependency
@@ -140,17 +220,20 @@ impl ResolverPipe<RegistryCache> {
                             }
                         },
                     }
-                    package_recorder
-                        .sub_dependencies
-                        .insert(package.clone().into(), package.clone().into());
                 }
             }
         }
 
         let mut jobs = Vec::new();
+        let declaring_dir = LocalRegistry::declaring_dir(&package).map(str::to_string);
         if let Some(deps) = package.dependencies {
             // This is correct because sub dependencies are always only dependencies
             for (name, version) in deps {
+                // Local paths are relative to the manifest declaring them
+                let version = match &declaring_dir {
+                    Some(dir) => LocalRegistry::rebase(&version, dir),
+                    None => version,
+                };
                 let pkg = format!("{}@{}", name, version);
 
                 let package = Package::new(PackageType::Prod(pkg));
@@ -163,29 +246,19 @@ impl ResolverPipe<RegistryCache> {
                     Some(vec![final_key.clone()])
                 };
                 let pra = package_recorder.clone();
-                let cache = cache_arc.clone();
                 let artifacts = artifacts.clone();
+                let registries = registries.clone();
+                let overrides = overrides.clone();
                 let handle = tokio::spawn(async move {
-                    Self::resolve_pkg(&package, parent, pra, cache, artifacts).await
+                    Self::resolve_pkg(&package, parent, pra, artifacts, registries, overrides).await
                 });
                 jobs.push(handle);
             }
         }
 
-        let results: Vec<_> = future::join_all(jobs).await;
-        // Iterate over the results
-        for result in results.into_iter() {
-            let jh_handle = result;
-            match jh_handle {
-                Ok(jh_handle) => {
-                    if let Err(e) = jh_handle {
-                        log::error!("Error is {}", e.to_string())
-                    }
-                }
-                Err(e) => {
-                    log::error!("{}", e)
-                }
-            }
+        // A dependency that cannot be resolved fails the install before anything is written
+        for result in future::join_all(jobs).await {
+            result??;
         }
 
         Ok(())
@@ -199,37 +272,51 @@ impl ResolverPipe<RegistryCache> {
         let mut jobs = vec![];
 
         for pkg in self.packages.clone() {
+            if self
+                .restore_locked(&pkg, package_recorder_arc.clone())
+                .await
+            {
+                continue;
+            }
+
             let pra = package_recorder_arc.clone();
-            let cache = self.cache.clone();
             let artifacts = self.artifacts.clone();
+            let registries = self.registries.clone();
+            let overrides = self.overrides.clone();
             let job = tokio::spawn(async move {
                 {
                     let package = Package::new(pkg);
-                    Self::resolve_pkg(&package, None, pra, cache, artifacts).await
+                    Self::resolve_pkg(&package, None, pra, artifacts, registries, overrides).await
                 }
             });
             jobs.push(job)
         }
 
-        let results = join_all(jobs).await;
-        for result in results.into_iter() {
-            let jh_handle = result.unwrap();
-            if let Err(e) = jh_handle {
-                log::error!("Error is {}", e.to_string())
-            }
+        for result in join_all(jobs).await {
+            result??;
         }
         Ok(package_recorder_arc.clone().lock().await.clone())
     }
 }
 
 #[async_trait]
-impl Pipe<(ResolveArtifacts, PackageRecorder)> for ResolverPipe<RegistryCache> {
+impl Pipe<(ResolveArtifacts, PackageRecorder)> for ResolverPipe {
     async fn run(&mut self) -> Result<(ResolveArtifacts, PackageRecorder), ExecutionError> {
-        {
-            self.cache.lock().await.init().await.unwrap();
+        self.lockfile = Self::read_lockfile();
+        if let Ok(package_json) = PreprocessDependencyInstall::read_package_json() {
+            self.overrides = Arc::new(Overrides::new(&package_json));
+        }
+        if let Some(lockfile) = &self.lockfile {
+            self.changed_overrides = self.overrides.changed_since(lockfile.overrides.as_ref());
+        }
+
+        let resolved = self.resolve().await;
+        let missing = self.registries.missing();
+        if !missing.is_empty() {
+            return Err(ExecutionError::Offline(missing));
         }
 
-        match self.resolve().await {
+        match resolved {
             Ok(e) => {
                 let artifacts = { self.artifacts.lock().await.clone() };
                 Ok((artifacts, e))
@@ -241,3 +328,39 @@ impl Pipe<(ResolveArtifacts, PackageRecorder)> for ResolverPipe<RegistryCache> {
         }
     }
 }
+
+// ─── Tests ───────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conf::NpmConfig;
+    use crate::network::Http;
+    use crate::registry::NpmRegistry;
+    use std::collections::BTreeMap;
+
+    #[tokio::test]
+    async fn test_sub_dependency_errors_fail_the_resolve() {
+        let root = tempfile::tempdir().unwrap();
+        let a = root.path().join("a");
+        std::fs::create_dir_all(&a).unwrap();
+        std::fs::write(
+            a.join("package.json"),
+            r#"{"name": "a", "version": "1.0.0", "dependencies": {"gone": "file:../gone"}}"#,
+        )
+        .unwrap();
+
+        let config = NpmConfig::new(BTreeMap::new());
+        let http = Http::new(&config).unwrap();
+        let npm = Arc::new(NpmRegistry::new(&config, http.clone()));
+        let (tx, _rx) = std::sync::mpsc::channel();
+        let resolver = ResolverPipe::new(
+            vec![PackageType::Prod(format!("a@file:{}", a.display()))],
+            Arc::new(Registries::new(npm, http)),
+            tx,
+        );
+
+        let error = resolver.resolve().await.unwrap_err();
+        assert!(matches!(error, NetworkError::LocalPackage(spec, _) if spec.contains("gone")));
+    }
+}
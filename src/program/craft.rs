@@ -4,7 +4,7 @@ use crate::contracts::{Logger, Pipe};
 use crate::logger::CraftLogger;
 use crate::pipeline::ConfigReader;
 use crate::{
-    actors::{CacheCleanActor, InstallActor},
+    actors::{CacheCleanActor, InstallActor, InstallMode},
     command::{Command, SubCommand},
     contracts::{Actor, Progress, ProgressAction},
     errors::ExecutionError,
@@ -33,18 +33,31 @@ impl Program {
             SubCommand::Install(args_install) => {
                 if args.is_install_without_args() {
                     let program_desire: ProgramDesire = args_install.into();
+                    let mode = if program_desire.frozen_lockfile {
+                        InstallMode::Frozen
+                    } else {
+                        InstallMode::PackageJson
+                    };
                     let deps_to_install = PreprocessDependencyInstall::new(program_desire)
                         .run()
                         .await?;
+                    let config = ConfigReader::new(args.config_overrides()).run().await?;
 
-                    let err = InstallActor::new(deps_to_install).start().await;
-                    if let Err(err) = err {
-                        CraftLogger::error(format!("{}", err));
-                    }
+                    InstallActor::new(deps_to_install, mode, config)
+                        .start()
+                        .await?;
 
                     return Ok(());
                 }
 
+                // Adding packages changes the lockfile, which a frozen install must not do
+                if args_install.frozen_lockfile {
+                    return Err(ExecutionError::FrozenLockfile(
+                        "packages cannot be added, run the install without --frozen-lockfile"
+                            .to_string(),
+                    ));
+                }
+
                 let packages = args_install
                     .packages
                     .clone()
@@ -65,7 +78,11 @@ impl Program {
                     })
                     .collect::<Vec<PackageType>>();
 
-                InstallActor::new(packages).start().await?;
+                let config = ConfigReader::new(args.config_overrides()).run().await?;
+
+                InstallActor::new(packages, InstallMode::Add, config)
+                    .start()
+                    .await?;
 
                 Ok(())
             }
@@ -75,60 +92,57 @@ impl Program {
                 Ok(())
             }
             SubCommand::Run(r) => {
-                let json = PreprocessDependencyInstall::get_script()?;
+                let config = ConfigReader::new(args.config_overrides()).run().await?;
 
-                if json.is_empty() {
-                    return Err(ExecutionError::JobExecutionFailed(
-                        "script must be exactly 1".to_string(),
-                        "script must be exactly 1".to_string(),
-                    ));
-                }
-
-                if let Some(script) = json.get(&r.script) {
-                    CraftLogger::info(format!("Running script: {}", script));
-                    CraftLogger::info(format!("Command: {}", script));
-                    RunActor::new(script.clone(), r.directory).start().await?;
-
-                    Ok(())
-                } else {
-                    CraftLogger::error(format!("Script {} not found", r.script));
-                    Err(ExecutionError::ScriptNotFound(format!(
-                        "Script {} not found",
-                        r.script
-                    )))
-                }
+                RunActor::new(r.script, r.args, r.directory, config)
+                    .start()
+                    .await
             }
             SubCommand::Exec(e) => {
                 CraftLogger::info(format!("Running command: {}", e.command));
                 CraftLogger::info(format!("Args: {:?}", e.args));
-                ExecActor::new(e.command, e.args).start().await?;
-
-                Ok(())
+                let config = ConfigReader::new(args.config_overrides()).run().await?;
+                ExecActor::new(e.command, e.args, e.packages, config)
+                    .start()
+                    .await
+            }
+            SubCommand::Dlx(mut e) => {
+                // Without --package the command names the package to run
+                if e.packages.is_empty() {
+                    e.packages.push(e.command.clone());
+                }
+                let config = ConfigReader::new(args.config_overrides()).run().await?;
+                ExecActor::new(e.command, e.args, e.packages, config)
+                    .start()
+                    .await
             }
             SubCommand::Config(c) => {
                 UIProgress::default();
                 match c {
                     ConfigSubCommand::Set(s) => {
-                        log::info!("{}", format!("Setting configuration: {:?}", s));
-                        let mut conf = ConfigReader::new().run().await?;
+                        log::info!("{}", format!("Setting configuration: {}", s.key));
+                        let mut conf = ConfigReader::new(args.config_overrides()).run().await?;
                         conf.switch_global(s.global);
                         conf.switch_location(s.location);
                         conf.set_value(&s.key, Some(s.value))?;
                         Ok(())
                     }
                     ConfigSubCommand::Get(g) => {
-                        let mut conf = ConfigReader::new().run().await?;
+                        let mut conf = ConfigReader::new(args.config_overrides()).run().await?;
                         conf.get_value(g.key)?;
                         Ok(())
                     }
                     ConfigSubCommand::List(l) => {
-                        let mut conf = ConfigReader::new().run().await?;
+                        let mut conf = ConfigReader::new(args.config_overrides()).run().await?;
                         conf.switch_json(l.json);
                         conf.list_value()?;
                         Ok(())
                     }
-                    _ => {
-                        CraftLogger::info("Reading configuration".to_string());
+                    ConfigSubCommand::Delete(d) => {
+                        let mut conf = ConfigReader::new(args.config_overrides()).run().await?;
+                        conf.switch_global(d.global);
+                        conf.switch_location(d.location);
+                        conf.delete_value(&d.key)?;
                         Ok(())
                     }
                 }
@@ -142,3 +156,20 @@ impl Default for Program {
         Self
     }
 }
+
+// ─── Tests ───────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[tokio::test]
+    async fn test_frozen_lockfile_rejects_new_packages() {
+        let args = Command::try_parse_from(["craft", "install", "lodash", "--frozen-lockfile"]);
+
+        let error = Program.execute(args.unwrap()).await.unwrap_err();
+        assert!(matches!(error, ExecutionError::FrozenLockfile(_)));
+        assert!(error.to_string().contains("--frozen-lockfile"));
+    }
+}
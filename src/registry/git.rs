@@ -1,45 +1,375 @@
+use std::path::{Path, PathBuf};
+
 use async_trait::async_trait;
+use nodejs_semver::{Range, Version};
+use tokio::process::Command;
 
 use crate::{
-    contracts::Registry,
+    cache::GIT_CACHE_FOLDER,
+    contracts::{Logger, Registry},
     errors::NetworkError,
-    package::{FullPackage, NpmPackage, Package},
+    fs::get_config_dir,
+    lockfile::lockfile_structure::{GitRepositoryResolution, LockfileResolution},
+    logger::CraftLogger,
+    network::Http,
+    package::{NpmPackage, Package},
 };
 
-#[allow(unused_variables)]
+const SEMVER_PREFIX: &str = "semver:";
+
+// ─── GitSpec ─────────────────────────────────────────────────────────────────
+
+/// What the part after `#` points at
+#[derive(Debug, Clone, PartialEq)]
+pub enum GitReference {
+    Default,
+    Committish(String),
+    Semver(String),
+}
+
+/// A git dependency specifier, e.g. github:owner/repo#semver:^1
+#[derive(Debug, Clone, PartialEq)]
+pub struct GitSpec {
+    pub repo: String,
+    pub reference: GitReference,
+}
+
+impl GitSpec {
+    pub fn parse(spec: &str) -> Self {
+        let (url, fragment) = spec.split_once('#').unwrap_or((spec, ""));
+
+        let hosted = [
+            ("github:", "https://github.com/"),
+            ("gitlab:", "https://gitlab.com/"),
+            ("bitbucket:", "https://bitbucket.org/"),
+        ];
+        let repo = hosted
+            .iter()
+            .find_map(|(prefix, host)| {
+                let path = url.strip_prefix(prefix)?;
+                let path = path.strip_suffix(".git").unwrap_or(path);
+                Some(format!("{}{}.git", host, path))
+            })
+            .unwrap_or_else(|| url.strip_prefix("git+").unwrap_or(url).to_string());
+
+        let reference = match fragment {
+            "" => GitReference::Default,
+            f => match f.strip_prefix(SEMVER_PREFIX) {
+                Some(range) => GitReference::Semver(range.to_string()),
+                None => GitReference::Committish(f.to_string()),
+            },
+        };
+
+        Self { repo, reference }
+    }
+}
+
+// ─── GitRegistry ─────────────────────────────────────────────────────────────
+
+/// Resolves git dependencies with the system `git` binary.
+/// Repositories are mirrored once under ~/.craft/cache/git and fetched on later installs.
+///
+/// The package is the `git archive` of the commit: unlike npm, its `prepare` script is not
+/// run and the `files` list of the manifest is not applied, everything committed is installed.
 #[derive(Debug)]
 pub struct GitRegistry {
-    #[allow(dead_code)]
-    http: reqwest::Client,
+    directory: PathBuf,
+    /// Proxy and TLS settings shared with the HTTP client
+    env: Vec<(&'static str, String)>,
 }
 
 impl GitRegistry {
-    pub fn new() -> Self {
+    pub fn new(http: &Http) -> Self {
         Self {
-            http: reqwest::Client::new(),
+            directory: get_config_dir(GIT_CACHE_FOLDER.clone()),
+            env: http.git_env(),
         }
     }
-}
 
-impl GitRegistry {
-    async fn get_archive(&self, _package: &Package) -> Result<FullPackage, NetworkError> {
-        todo!()
+    #[cfg(test)]
+    fn at(directory: PathBuf) -> Self {
+        Self {
+            directory,
+            env: vec![],
+        }
+    }
+
+    /// Repositories and references are user input, one starting with `-` would be read as an
+    /// option by git
+    fn reject_option(value: &str) -> Result<&str, NetworkError> {
+        match value.starts_with('-') {
+            true => Err(NetworkError::Git(
+                value.to_string(),
+                "not a repository or reference".to_string(),
+            )),
+            false => Ok(value),
+        }
+    }
+
+    async fn git(&self, args: &[&str], cwd: &Path) -> Result<String, NetworkError> {
+        let output = Command::new("git")
+            .args(args)
+            .current_dir(cwd)
+            .env("GIT_TERMINAL_PROMPT", "0")
+            .envs(self.env.iter().cloned())
+            .output()
+            .await?;
+
+        if !output.status.success() {
+            return Err(NetworkError::Git(
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            ));
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    fn repo_directory(&self, repo: &str) -> PathBuf {
+        let name: String = repo
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+            .collect();
+        self.directory.join(name)
+    }
+
+    /// Clones the repository on first use, fetches it afterwards
+    async fn sync_repo(&self, repo: &str) -> Result<PathBuf, NetworkError> {
+        let repo = Self::reject_option(repo)?;
+        let directory = self.repo_directory(repo);
+
+        if directory.exists() {
+            CraftLogger::verbose(format!("Fetching git repository: {}", repo));
+            self.git(&["fetch", "--quiet", "--tags", "--force"], &directory)
+                .await?;
+            return Ok(directory);
+        }
+
+        CraftLogger::verbose(format!("Cloning git repository: {}", repo));
+        let target = directory.to_string_lossy().to_string();
+        self.git(
+            &["clone", "--mirror", "--quiet", "--", repo, &target],
+            &self.directory,
+        )
+        .await?;
+        Ok(directory)
+    }
+
+    async fn rev_parse(&self, directory: &Path, reference: &str) -> Result<String, NetworkError> {
+        let reference = Self::reject_option(reference)?;
+        self.git(
+            &[
+                "rev-parse",
+                "--verify",
+                "--end-of-options",
+                &format!("{}^{{commit}}", reference),
+            ],
+            directory,
+        )
+        .await
+    }
+
+    /// Highest tag satisfying the range, tags may carry a `v` prefix
+    async fn resolve_semver(&self, directory: &Path, range: &str) -> Result<String, NetworkError> {
+        let invalid = || NetworkError::FailedToFetchVersion(format!("{}{}", SEMVER_PREFIX, range));
+        let range: Range = range.parse().map_err(|_| invalid())?;
+
+        let tags = self.git(&["tag", "--list"], directory).await?;
+        let tag = tags
+            .lines()
+            .filter_map(|tag| {
+                let version: Version = tag.trim_start_matches('v').parse().ok()?;
+                version.satisfies(&range).then_some((version, tag))
+            })
+            .max_by(|a, b| a.0.cmp(&b.0))
+            .map(|(_, tag)| tag.to_string())
+            .ok_or_else(invalid)?;
+
+        self.rev_parse(directory, &tag).await
+    }
+
+    /// Resolves the reference of the spec to a commit SHA
+    pub async fn resolve(&self, spec: &GitSpec) -> Result<(PathBuf, String), NetworkError> {
+        let directory = self.sync_repo(&spec.repo).await?;
+
+        let commit = match &spec.reference {
+            GitReference::Default => self.rev_parse(&directory, "HEAD").await?,
+            GitReference::Committish(committish) => self.rev_parse(&directory, committish).await?,
+            GitReference::Semver(range) => self.resolve_semver(&directory, range).await?,
+        };
+
+        Ok((directory, commit))
+    }
+
+    /// Packs the commit into a tarball with a `package/` root, like registry tarballs
+    pub async fn archive(
+        &self,
+        resolution: &GitRepositoryResolution,
+        destination: &Path,
+    ) -> Result<(), NetworkError> {
+        let commit = Self::reject_option(&resolution.commit)?;
+        let mut directory = self.repo_directory(&resolution.repo);
+        if !directory.exists()
+            || self
+                .rev_parse(&directory, &resolution.commit)
+                .await
+                .is_err()
+        {
+            directory = self.sync_repo(&resolution.repo).await?;
+        }
+
+        let output = destination.to_string_lossy().to_string();
+        self.git(
+            &[
+                "archive",
+                "--format=tar.gz",
+                "--prefix=package/",
+                "-o",
+                &output,
+                commit,
+            ],
+            &directory,
+        )
+        .await?;
+        Ok(())
     }
 }
 
 #[async_trait]
 impl Registry for GitRegistry {
     async fn fetch(&self, package: &Package) -> Result<NpmPackage, NetworkError> {
-        let pkg = self.get_archive(package).await?;
+        let spec = GitSpec::parse(&package.raw_version);
+        let (directory, commit) = self.resolve(&spec).await?;
+
+        let manifest = self
+            .git(&["show", &format!("{}:package.json", commit)], &directory)
+            .await?;
+        let mut remote_package = NpmPackage::from_manifest(&manifest)
+            .map_err(|_| NetworkError::FailedToFetchVersion(package.raw_version.clone()))?;
+        if remote_package
+            .scripts
+            .as_ref()
+            .is_some_and(|scripts| scripts.contains_key("prepare"))
+        {
+            CraftLogger::warn(format!(
+                "{}: the prepare script of git dependencies is not run",
+                package
+            ));
+        }
+
+        remote_package.dist.tarball = format!("{}#{}", spec.repo, commit);
+        remote_package.dist.resolution =
+            Some(LockfileResolution::GitRepository(GitRepositoryResolution {
+                repo: spec.repo,
+                commit,
+                path: None,
+            }));
+
+        Ok(remote_package)
+    }
+}
+
+// ─── Tests ───────────────────────────────────────────────────────────────────
 
-        if let Some((_, remote_package)) = pkg.versions.iter().next() {
-            return Ok(remote_package.clone());
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actors::PackageType;
+
+    fn run(args: &[&str], cwd: &Path) {
+        let status = std::process::Command::new("git")
+            .args(args)
+            .current_dir(cwd)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {:?} failed", args);
+    }
+
+    /// Bare repo with tags v1.0.0, v1.2.0 and v2.0.0 on master, and a `next` branch
+    fn create_repo(root: &Path) -> String {
+        let work = root.join("work");
+        std::fs::create_dir_all(&work).unwrap();
+        run(&["init", "--quiet", "-b", "master"], &work);
+        run(&["config", "user.email", "craft@example.com"], &work);
+        run(&["config", "user.name", "craft"], &work);
+
+        for version in ["1.0.0", "1.2.0", "2.0.0"] {
+            let manifest = format!(r#"{{"name": "lib", "version": "{}"}}"#, version);
+            std::fs::write(work.join("package.json"), manifest).unwrap();
+            run(&["add", "."], &work);
+            run(&["commit", "--quiet", "-m", version], &work);
+            run(&["tag", &format!("v{}", version)], &work);
         }
+        run(&["checkout", "--quiet", "-b", "next", "v1.0.0"], &work);
+        std::fs::write(
+            work.join("package.json"),
+            r#"{"name": "lib", "version": "1.1.0-next"}"#,
+        )
+        .unwrap();
+        run(&["commit", "--quiet", "-am", "next"], &work);
+        run(&["checkout", "--quiet", "master"], &work);
+
+        let bare = root.join("lib.git");
+        run(
+            &["clone", "--bare", "--quiet", "work", bare.to_str().unwrap()],
+            root,
+        );
+        format!("file://{}", bare.display())
+    }
+
+    #[test]
+    fn test_parse_spec() {
+        let spec = GitSpec::parse("github:owner/lib#semver:^1");
+        assert_eq!(spec.repo, "https://github.com/owner/lib.git");
+        assert_eq!(spec.reference, GitReference::Semver("^1".to_string()));
+
+        let spec = GitSpec::parse("git+ssh://git@github.com/owner/lib.git#main");
+        assert_eq!(spec.repo, "ssh://git@github.com/owner/lib.git");
+        assert_eq!(spec.reference, GitReference::Committish("main".to_string()));
+
+        let spec = GitSpec::parse("git+file:///tmp/lib.git");
+        assert_eq!(spec.repo, "file:///tmp/lib.git");
+        assert_eq!(spec.reference, GitReference::Default);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_references() {
+        let root = tempfile::tempdir().unwrap();
+        let repo = create_repo(root.path());
+        let cache = root.path().join("cache");
+        std::fs::create_dir_all(&cache).unwrap();
+        let registry = GitRegistry::at(cache);
+
+        let version_of = |fragment: &str| {
+            let spec = format!("lib@git+{}{}", repo, fragment);
+            Package::new(PackageType::Prod(spec))
+        };
+
+        let head = registry.fetch(&version_of("")).await.unwrap();
+        assert_eq!(head.version, "2.0.0");
+        let branch = registry.fetch(&version_of("#next")).await.unwrap();
+        assert_eq!(branch.version, "1.1.0-next");
+        let tag = registry.fetch(&version_of("#v1.0.0")).await.unwrap();
+        assert_eq!(tag.version, "1.0.0");
+        let range = registry.fetch(&version_of("#semver:^1")).await.unwrap();
+        assert_eq!(range.version, "1.2.0");
+
+        let Some(LockfileResolution::GitRepository(resolution)) = range.dist.resolution else {
+            panic!("expected a git resolution");
+        };
+        assert_eq!(resolution.repo, repo);
+        let pinned = version_of(&format!("#{}", resolution.commit));
+        assert_eq!(registry.fetch(&pinned).await.unwrap().version, "1.2.0");
 
-        println!("Failed to fetch version: {}", package);
+        let tarball = root.path().join("lib.tgz");
+        registry.archive(&resolution, &tarball).await.unwrap();
+        assert!(tarball.metadata().unwrap().len() > 0);
 
-        Err(NetworkError::FailedToFetchVersion(
-            package.raw_version.clone(),
-        ))
+        // Leading dashes never reach git as options
+        let option = registry.fetch(&version_of("#--output=/tmp/x")).await;
+        assert!(matches!(option, Err(NetworkError::Git(arg, _)) if arg == "--output=/tmp/x"));
+        let repo = Package::new(PackageType::Prod("lib@git+--upload-pack=touch".to_string()));
+        assert!(registry.fetch(&repo).await.is_err());
     }
 }
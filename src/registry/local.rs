@@ -0,0 +1,282 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use homedir::my_home;
+use path_clean::clean;
+
+use crate::{
+    contracts::Registry,
+    errors::{NetworkError, ZipError},
+    fs::symlink_dir,
+    lockfile::lockfile_structure::{DirectoryResolution, LockfileResolution, TarballResolution},
+    package::{NpmPackage, Package, Registry as Source},
+    tar::Gzip,
+};
+
+const FILE_PREFIX: &str = "file:";
+const LINK_PREFIX: &str = "link:";
+pub const DIRECTORY_TYPE: &str = "directory";
+pub const LINK_TYPE: &str = "link";
+
+// ─── LocalRegistry ───────────────────────────────────────────────────────────
+
+/// Resolves `file:` and `link:` directories and local tarballs.
+/// Paths are relative to the project root, the lockfile keeps them as written.
+/// Local dependencies of local packages are rebased onto the project root first.
+#[derive(Debug)]
+pub struct LocalRegistry;
+
+impl LocalRegistry {
+    fn strip_prefix(spec: &str) -> (&str, bool) {
+        if let Some(path) = spec.strip_prefix(LINK_PREFIX) {
+            return (path, true);
+        }
+        (spec.strip_prefix(FILE_PREFIX).unwrap_or(spec), false)
+    }
+
+    fn to_absolute(path: &str) -> PathBuf {
+        let path = match path.strip_prefix("~/") {
+            Some(rest) => match my_home() {
+                Ok(Some(home)) => home.join(rest),
+                _ => PathBuf::from(path),
+            },
+            None => PathBuf::from(path),
+        };
+
+        match path.is_absolute() {
+            true => path,
+            false => std::env::current_dir().unwrap_or_default().join(path),
+        }
+    }
+
+    /// The directory of a local package as written in the lockfile, where its own
+    /// `file:` and `link:` dependencies start from
+    pub fn declaring_dir(package: &NpmPackage) -> Option<&str> {
+        match package.dist.resolution.as_ref()? {
+            LockfileResolution::Directory(d) => Some(&d.directory),
+            _ => None,
+        }
+    }
+
+    /// Rewrites a relative local specifier declared by the manifest in `dir` so it is
+    /// relative to the project root, e.g. `file:../shared` in packages/a => `file:packages/shared`
+    pub fn rebase(spec: &str, dir: &str) -> String {
+        if !Source::is_local(spec) {
+            return spec.to_string();
+        }
+        let (path, link) = Self::strip_prefix(spec);
+        if path.starts_with("~/") || Path::new(path).is_absolute() {
+            return spec.to_string();
+        }
+
+        let prefix = if link { LINK_PREFIX } else { FILE_PREFIX };
+        let rebased = clean(Path::new(dir).join(path));
+        format!("{}{}", prefix, rebased.to_string_lossy().replace('\\', "/"))
+    }
+
+    fn is_tarball(path: &str) -> bool {
+        path.ends_with(".tgz") || path.ends_with(".tar.gz")
+    }
+
+    /// Where the files of a local package live on disk, None for remote packages
+    pub fn location(package: &NpmPackage) -> Option<PathBuf> {
+        match package.dist.resolution.as_ref()? {
+            LockfileResolution::Directory(d) => Some(Self::to_absolute(&d.directory)),
+            LockfileResolution::Tarball(t) => {
+                let path = t.tarball.strip_prefix(FILE_PREFIX)?;
+                Some(Self::to_absolute(path))
+            }
+            _ => None,
+        }
+    }
+
+    /// Puts a local directory at `target`, `link:` symlinks it and `file:` copies it
+    pub fn materialize(
+        resolution: &DirectoryResolution,
+        source: &Path,
+        target: &Path,
+    ) -> Result<(), std::io::Error> {
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        if resolution.r#type.as_deref() == Some(LINK_TYPE) {
+            return symlink_dir(source, target);
+        }
+        Self::copy_package(source, target)
+    }
+
+    fn copy_package(from: &Path, to: &Path) -> Result<(), std::io::Error> {
+        fs::create_dir_all(to)?;
+
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            if name == "node_modules" || name == ".git" {
+                continue;
+            }
+
+            let target = to.join(&name);
+            if entry.file_type()?.is_dir() {
+                Self::copy_package(&entry.path(), &target)?;
+            } else {
+                fs::copy(entry.path(), target)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Registry for LocalRegistry {
+    async fn fetch(&self, package: &Package) -> Result<NpmPackage, NetworkError> {
+        let (path, link) = Self::strip_prefix(&package.raw_version);
+        let location = Self::to_absolute(path);
+        let failure = |e: String| NetworkError::LocalPackage(package.raw_version.clone(), e);
+
+        let (manifest, resolution) = if Self::is_tarball(path) {
+            let manifest = fs::File::open(&location)
+                .map_err(ZipError::from)
+                .and_then(Gzip::read_manifest)
+                .map_err(|e| failure(e.to_string()))?;
+            let resolution = LockfileResolution::Tarball(TarballResolution {
+                r#type: None,
+                tarball: format!("{}{}", FILE_PREFIX, path),
+                integrity: None,
+                path: None,
+            });
+            (manifest, resolution)
+        } else {
+            let manifest = fs::read_to_string(location.join("package.json"))
+                .map_err(|e| failure(e.to_string()))?;
+            let resolution = LockfileResolution::Directory(DirectoryResolution {
+                r#type: Some(if link { LINK_TYPE } else { DIRECTORY_TYPE }.to_string()),
+                directory: path.to_string(),
+            });
+            (manifest, resolution)
+        };
+
+        let mut local_package =
+            NpmPackage::from_manifest(&manifest).map_err(|e| failure(e.to_string()))?;
+        local_package.dist.tarball = package.raw_version.clone();
+        local_package.dist.resolution = Some(resolution);
+
+        Ok(local_package)
+    }
+}
+
+// ─── Tests ───────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actors::PackageType;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    const MANIFEST: &str = r#"{"name": "shared", "version": "1.0.0", "dependencies": {"a": "^1"}}"#;
+
+    fn package(spec: &str) -> Package {
+        Package::new(PackageType::Prod(format!("shared@{}", spec)))
+    }
+
+    #[tokio::test]
+    async fn test_fetch_directory() {
+        let root = tempfile::tempdir().unwrap();
+        let shared = root.path().join("shared");
+        fs::create_dir_all(shared.join("node_modules")).unwrap();
+        fs::write(shared.join("package.json"), MANIFEST).unwrap();
+        let spec = format!("file:{}", shared.display());
+
+        let fetched = LocalRegistry.fetch(&package(&spec)).await.unwrap();
+        assert_eq!(fetched.to_string(), "shared@1.0.0");
+        assert!(fetched.dependencies.unwrap().contains_key("a"));
+        let Some(LockfileResolution::Directory(resolution)) = fetched.dist.resolution else {
+            panic!("expected a directory resolution");
+        };
+        assert_eq!(resolution.r#type.as_deref(), Some(DIRECTORY_TYPE));
+
+        let copied = root.path().join("copied");
+        LocalRegistry::materialize(&resolution, &shared, &copied).unwrap();
+        assert!(copied.join("package.json").is_file());
+        assert!(!copied.join("node_modules").exists());
+
+        let linked = LocalRegistry
+            .fetch(&package(&format!("link:{}", shared.display())))
+            .await
+            .unwrap();
+        let Some(LockfileResolution::Directory(resolution)) = linked.dist.resolution else {
+            panic!("expected a directory resolution");
+        };
+        let target = root.path().join("linked");
+        LocalRegistry::materialize(&resolution, &shared, &target).unwrap();
+        assert!(fs::symlink_metadata(&target).unwrap().is_symlink());
+    }
+
+    #[tokio::test]
+    async fn test_fetch_tarball() {
+        let root = tempfile::tempdir().unwrap();
+        let tarball = root.path().join("shared.tgz");
+
+        let encoder = GzEncoder::new(fs::File::create(&tarball).unwrap(), Compression::fast());
+        let mut builder = tar::Builder::new(encoder);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(MANIFEST.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "package/package.json", MANIFEST.as_bytes())
+            .unwrap();
+        builder.into_inner().unwrap().finish().unwrap();
+
+        let spec = tarball.display().to_string();
+        let fetched = LocalRegistry.fetch(&package(&spec)).await.unwrap();
+        assert_eq!(fetched.to_string(), "shared@1.0.0");
+        assert_eq!(LocalRegistry::location(&fetched), Some(tarball));
+    }
+
+    #[test]
+    fn test_rebase() {
+        assert_eq!(
+            LocalRegistry::rebase("file:../shared", "packages/a"),
+            "file:packages/shared"
+        );
+        assert_eq!(
+            LocalRegistry::rebase("link:./lib", "packages/a"),
+            "link:packages/a/lib"
+        );
+        assert_eq!(LocalRegistry::rebase("../../x.tgz", "a"), "file:../x.tgz");
+        assert_eq!(
+            LocalRegistry::rebase("file:/opt/shared", "a"),
+            "file:/opt/shared"
+        );
+        assert_eq!(LocalRegistry::rebase("^1.0.0", "a"), "^1.0.0");
+    }
+
+    #[tokio::test]
+    async fn test_fetch_nested_directory() {
+        let root = tempfile::tempdir().unwrap();
+        let member = root.path().join("packages").join("a");
+        let shared = root.path().join("packages").join("shared");
+        fs::create_dir_all(&member).unwrap();
+        fs::create_dir_all(&shared).unwrap();
+        fs::write(
+            member.join("package.json"),
+            r#"{"name": "a", "version": "1.0.0", "dependencies": {"shared": "file:../shared"}}"#,
+        )
+        .unwrap();
+        fs::write(shared.join("package.json"), MANIFEST).unwrap();
+
+        let spec = format!("file:{}", member.display());
+        let a = LocalRegistry.fetch(&package(&spec)).await.unwrap();
+        let dir = LocalRegistry::declaring_dir(&a).unwrap();
+        let declared = &a.dependencies.as_ref().unwrap()["shared"];
+
+        let rebased = LocalRegistry::rebase(declared, dir);
+        let fetched = LocalRegistry.fetch(&package(&rebased)).await.unwrap();
+        assert_eq!(fetched.to_string(), "shared@1.0.0");
+        assert_eq!(LocalRegistry::location(&fetched), Some(shared));
+    }
+}
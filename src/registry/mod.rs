@@ -1,5 +1,11 @@
 mod git;
+mod local;
 mod npm;
+mod registries;
+mod tarball;
 
 pub use git::GitRegistry;
+pub use local::LocalRegistry;
 pub use npm::NpmRegistry;
+pub use registries::Registries;
+pub use tarball::TarballRegistry;
@@ -1,36 +1,199 @@
+use std::time::Duration;
+
 use async_trait::async_trait;
 
+use futures::lock::Mutex;
+use nodejs_semver::Version;
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::{StatusCode, Url};
+
 use crate::{
+    cache::{CachedPackument, RegistryCache},
+    conf::NpmConfig,
     contracts::Registry,
     errors::NetworkError,
+    network::{FetchPolicy, Http},
     package::{FullPackage, NpmPackage, Package},
 };
 
-const NPM_REGISTRY_URL: &str = "https://registry.npmjs.org";
+const NPMJS_HOST: &str = "registry.npmjs.org";
 
 #[derive(Debug)]
 pub struct NpmRegistry {
-    http: reqwest::Client,
+    http: Http,
+    config: NpmConfig,
+    cache: Mutex<RegistryCache>,
 }
 
 impl NpmRegistry {
-    pub fn new() -> Self {
+    pub fn new(config: &NpmConfig, http: Http) -> Self {
         Self {
-            http: reqwest::Client::new(),
+            http,
+            config: config.clone(),
+            cache: Mutex::new(RegistryCache::default()),
+        }
+    }
+
+    /// Location of the tarball for a package version, following the registry layout
+    /// e.g. https://registry.npmjs.org/@babel/core/-/core-7.0.0.tgz
+    pub fn tarball_url(&self, name: &str, version: &str) -> String {
+        let file_name = name.rsplit('/').next().unwrap_or(name);
+        format!(
+            "{}/{}/-/{}-{}.tgz",
+            self.config.registry_for(name),
+            name,
+            file_name,
+            version
+        )
+    }
+
+    fn should_replace_host(&self, host: &str) -> bool {
+        match self.config.replace_registry_host.as_str() {
+            "never" => false,
+            "always" => true,
+            "npmjs" => host == NPMJS_HOST,
+            replaced => host == replaced,
+        }
+    }
+
+    /// Tag a bare `craft add foo` resolves to, set with `craft config set tag next`
+    pub fn default_tag(&self) -> &str {
+        &self.config.tag
+    }
+
+    /// Picks the version for the specifier like npm does.
+    /// A dist-tag resolves through `dist-tags`, a range prefers the version of the
+    /// configured tag when it satisfies and falls back to the highest satisfying one.
+    fn pick_version(&self, package: &Package, pkg: &FullPackage) -> Option<NpmPackage> {
+        let tagged = |tag: &str| {
+            let version = pkg.dist_tags.get(tag)?;
+            pkg.versions.get(version).cloned()
+        };
+        let wildcard = matches!(package.raw_version.as_str(), "*" | "");
+
+        if let Some(tag) = package.dist_tag(self.default_tag()).filter(|_| !wildcard) {
+            return tagged(tag);
+        }
+        if let Some(default) = tagged(self.default_tag()) {
+            if wildcard || package.satisfies(&default.version) {
+                return Some(default);
+            }
+        }
+
+        pkg.versions
+            .values()
+            .filter(|remote_package| package.satisfies(&remote_package.version))
+            .filter_map(|remote_package| {
+                let version = Version::parse(&remote_package.version).ok()?;
+                Some((version, remote_package))
+            })
+            .max_by(|a, b| a.0.cmp(&b.0))
+            .map(|(_, remote_package)| remote_package.clone())
+    }
+
+    /// Points the tarball at the configured registry, following `replace-registry-host`.
+    /// Mirrors usually serve the upstream packuments untouched.
+    fn rewrite_tarball(&self, package: &mut NpmPackage) {
+        let registry = Url::parse(self.config.registry_for(&package.name));
+        let tarball = Url::parse(&package.dist.tarball);
+        let (Ok(registry), Ok(mut tarball)) = (registry, tarball) else {
+            return;
+        };
+
+        let Some(host) = tarball.host_str() else {
+            return;
+        };
+        if registry.host_str() == Some(host) || !self.should_replace_host(host) {
+            return;
+        }
+
+        let replaced = tarball.set_scheme(registry.scheme()).is_ok()
+            && tarball.set_host(registry.host_str()).is_ok()
+            && tarball.set_port(registry.port()).is_ok();
+        if replaced {
+            package.dist.tarball = tarball.to_string();
         }
     }
 }
 
+// ─── Packument cache ─────────────────────────────────────────────────────────
+
 impl NpmRegistry {
+    fn seconds(value: i32) -> Duration {
+        Duration::from_secs(value.max(0) as u64)
+    }
+
+    /// Whether the cached packument can answer without asking the registry.
+    /// Online it has to be younger than `cache-min`, offline modes take anything
+    /// that satisfies the specifier.
+    fn use_cached(&self, package: &Package, cached: &CachedPackument) -> bool {
+        let fresh = match self.http.policy() {
+            FetchPolicy::Online => cached.age() < Self::seconds(self.config.cache_min),
+            FetchPolicy::PreferOffline | FetchPolicy::Offline => true,
+        };
+        fresh && self.pick_version(package, &cached.packument).is_some()
+    }
+
     async fn get_full_package(&self, package: &Package) -> Result<FullPackage, NetworkError> {
-        let url = format!("{}/{}", NPM_REGISTRY_URL, package.name);
+        let cached = self.cache.lock().await.get(&package.name);
+        if let Some(cached) = &cached {
+            let validated = self.cache.lock().await.is_validated(&package.name);
+            if validated || self.use_cached(package, cached) {
+                log::info!("Packument of {} served from cache", package.name);
+                return Ok(cached.packument.clone());
+            }
+        }
+
+        // Past `cache-max` the validators are not trusted anymore
+        let cached = cached.filter(|cached| cached.age() < Self::seconds(self.config.cache_max));
+        let packument = self.revalidate(package, cached).await?;
+        let full_package = packument.packument.clone();
+
+        if let Err(e) = self.cache.lock().await.set(&package.name, packument) {
+            log::warn!("Failed to cache the packument of {}: {}", package.name, e);
+        }
+        Ok(full_package)
+    }
+
+    /// Fetches the packument, conditionally when a cached copy has validators
+    async fn revalidate(
+        &self,
+        package: &Package,
+        cached: Option<CachedPackument>,
+    ) -> Result<CachedPackument, NetworkError> {
+        let url = format!(
+            "{}/{}",
+            self.config.registry_for(&package.name),
+            package.name
+        );
 
-        let response = self
+        let mut request = self
             .http
             .get(&url)
-            .header("Accept", "application/vnd.npm.install-v1+json")
-            .send()
-            .await?;
+            .header("Accept", "application/vnd.npm.install-v1+json");
+        if let Some(cached) = &cached {
+            if let Some(etag) = &cached.etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &cached.last_modified {
+                request = request.header(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+        let response = self.http.send(request).await?;
+
+        if response.status() == StatusCode::NOT_MODIFIED {
+            if let Some(mut cached) = cached {
+                log::info!("Packument of {} not modified", package.name);
+                cached.touch();
+                return Ok(cached);
+            }
+        }
+
+        let header = |name| {
+            let value = response.headers().get(name)?.to_str().ok()?;
+            Some(value.to_string())
+        };
+        let (etag, last_modified) = (header(ETAG), header(LAST_MODIFIED));
 
         let remote_package = match response.json::<FullPackage>().await {
             Ok(pkg) => pkg,
@@ -40,7 +203,7 @@ impl NpmRegistry {
             }
         };
 
-        Ok(remote_package)
+        Ok(CachedPackument::new(remote_package, etag, last_modified))
     }
 }
 
@@ -50,28 +213,10 @@ impl Registry for NpmRegistry {
         log::info!("Fetching package: {}", package.to_string());
 
         let pkg = self.get_full_package(package).await?;
-        let mut highest_satisfied_version: Option<NpmPackage> = None;
-
-        for (version, remote_package) in pkg.versions.iter() {
-            if package.satisfies(version) {
-                match highest_satisfied_version {
-                    Some(ref sv) => {
-                        let selected_version = nodejs_semver::Version::parse(&sv.version).unwrap();
-                        let current_version =
-                            nodejs_semver::Version::parse(&remote_package.version).unwrap();
-                        if selected_version < current_version {
-                            highest_satisfied_version = Some(remote_package.clone());
-                        }
-                    }
-                    None => {
-                        highest_satisfied_version = Some(remote_package.clone());
-                    }
-                }
-            }
-        }
 
-        if let Some(v) = highest_satisfied_version {
-            return Ok(v.clone());
+        if let Some(mut v) = self.pick_version(package, &pkg) {
+            self.rewrite_tarball(&mut v);
+            return Ok(v);
         }
 
         println!("Failed to fetch version: {}", package);
@@ -79,3 +224,182 @@ impl Registry for NpmRegistry {
         Err(NetworkError::FailedToFetchVersion(package.to_string()))
     }
 }
+
+// ─── Tests ───────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actors::PackageType;
+    use std::collections::BTreeMap;
+
+    fn registry(entries: &[(&str, &str)]) -> NpmRegistry {
+        let conf = entries
+            .iter()
+            .map(|(k, v)| (k.to_string(), Some(v.to_string())))
+            .collect::<BTreeMap<_, _>>();
+        let config = NpmConfig::new(conf);
+        NpmRegistry::new(&config, Http::new(&config).unwrap())
+    }
+
+    fn package(name: &str, tarball: &str) -> NpmPackage {
+        NpmPackage {
+            name: name.to_string(),
+            dist: crate::package::Distribution {
+                tarball: tarball.to_string(),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_tarball_url() {
+        let registry = registry(&[
+            ("registry", "http://mirror.local:4873/"),
+            ("@ourco:registry", "https://npm.ourco.dev/"),
+        ]);
+
+        assert_eq!(
+            registry.tarball_url("left-pad", "1.3.0"),
+            "http://mirror.local:4873/left-pad/-/left-pad-1.3.0.tgz"
+        );
+        assert_eq!(
+            registry.tarball_url("@ourco/ui", "2.0.0"),
+            "https://npm.ourco.dev/@ourco/ui/-/ui-2.0.0.tgz"
+        );
+    }
+
+    #[test]
+    fn test_rewrite_tarball() {
+        let registry = registry(&[("registry", "http://mirror.local:4873/")]);
+
+        let mut upstream = package(
+            "left-pad",
+            "https://registry.npmjs.org/left-pad/-/left-pad-1.3.0.tgz",
+        );
+        registry.rewrite_tarball(&mut upstream);
+        assert_eq!(
+            upstream.dist.tarball,
+            "http://mirror.local:4873/left-pad/-/left-pad-1.3.0.tgz"
+        );
+
+        let other_host = "https://cdn.example.com/left-pad-1.3.0.tgz";
+        let mut untouched = package("left-pad", other_host);
+        registry.rewrite_tarball(&mut untouched);
+        assert_eq!(untouched.dist.tarball, other_host);
+    }
+
+    fn packument() -> FullPackage {
+        let versions = ["1.0.0", "1.1.0", "2.0.0-rc.1"]
+            .iter()
+            .map(|version| {
+                let package = NpmPackage {
+                    name: "lib".to_string(),
+                    version: version.to_string(),
+                    ..Default::default()
+                };
+                (version.to_string(), package)
+            })
+            .collect();
+        let dist_tags = [("latest", "1.0.0"), ("next", "2.0.0-rc.1")]
+            .iter()
+            .map(|(tag, version)| (tag.to_string(), version.to_string()))
+            .collect();
+        FullPackage {
+            versions,
+            dist_tags,
+        }
+    }
+
+    #[test]
+    fn test_pick_version() {
+        let pick = |registry: &NpmRegistry, spec: &str| {
+            let package = Package::new(PackageType::Prod(format!("lib@{}", spec)));
+            registry
+                .pick_version(&package, &packument())
+                .map(|p| p.version)
+        };
+
+        let default = registry(&[]);
+        assert_eq!(pick(&default, "next").as_deref(), Some("2.0.0-rc.1"));
+        assert_eq!(pick(&default, "*").as_deref(), Some("1.0.0"));
+        assert_eq!(pick(&default, "^1.0.0").as_deref(), Some("1.0.0"));
+        assert_eq!(pick(&default, "^1.1.0").as_deref(), Some("1.1.0"));
+        assert_eq!(pick(&default, "beta"), None);
+
+        let next = registry(&[("tag", "next")]);
+        assert_eq!(pick(&next, "*").as_deref(), Some("2.0.0-rc.1"));
+        assert_eq!(pick(&next, "^1.0.0").as_deref(), Some("1.1.0"));
+    }
+
+    /// Answers each connection with the next response, reports the requests it received
+    async fn serve(responses: Vec<String>) -> (String, tokio::task::JoinHandle<Vec<String>>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let handle = tokio::spawn(async move {
+            let mut requests = vec![];
+            for response in responses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = [0; 2048];
+                let read = socket.read(&mut request).await.unwrap();
+                requests.push(String::from_utf8_lossy(&request[..read]).to_lowercase());
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+            requests
+        });
+
+        (format!("http://{}/", address), handle)
+    }
+
+    #[tokio::test]
+    async fn test_revalidate_packument() {
+        let dist = r#""dist":{"shasum":"","tarball":"http://localhost/lib-1.0.0.tgz"}"#;
+        let body = format!(
+            r#"{{"versions":{{"1.0.0":{{"name":"lib","version":"1.0.0",{}}}}},"dist-tags":{{"latest":"1.0.0"}}}}"#,
+            dist
+        );
+        let (url, requests) = serve(vec![
+            format!(
+                "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            ),
+            "HTTP/1.1 304 Not Modified\r\nConnection: close\r\n\r\n".to_string(),
+        ])
+        .await;
+        let directory = tempfile::tempdir().unwrap();
+        let registry = |entries: &[(&str, &str)]| {
+            let mut registry = registry(entries);
+            registry.cache = Mutex::new(RegistryCache::at(directory.path().to_path_buf()));
+            registry
+        };
+        let lib = Package::new(PackageType::Prod("lib@^1.0.0".to_string()));
+
+        let first = registry(&[("registry", &url)]);
+        assert_eq!(first.fetch(&lib).await.unwrap().version, "1.0.0");
+        // Resolved once per run
+        assert_eq!(first.fetch(&lib).await.unwrap().version, "1.0.0");
+
+        // A later run asks again, conditionally, and keeps the cached copy on 304
+        let second = registry(&[("registry", &url)]);
+        assert_eq!(second.fetch(&lib).await.unwrap().version, "1.0.0");
+        let requests = requests.await.unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(!requests[0].contains("if-none-match"));
+        assert!(requests[1].contains("if-none-match: \"v1\""));
+
+        // Within cache-min and offline the registry is not contacted at all
+        let fresh = registry(&[("registry", &url), ("cache-min", "60")]);
+        assert_eq!(fresh.fetch(&lib).await.unwrap().version, "1.0.0");
+        let offline = registry(&[("registry", &url), ("offline", "true")]);
+        assert_eq!(offline.fetch(&lib).await.unwrap().version, "1.0.0");
+        let newer = Package::new(PackageType::Prod("lib@^2.0.0".to_string()));
+        assert!(matches!(
+            offline.fetch(&newer).await,
+            Err(NetworkError::Offline(_))
+        ));
+    }
+}
@@ -0,0 +1,60 @@
+use std::sync::{Arc, Mutex};
+
+use crate::{
+    contracts::Registry as _,
+    errors::NetworkError,
+    network::{FetchPolicy, Http},
+    package::{NpmPackage, Package, Registry},
+};
+
+use super::{GitRegistry, LocalRegistry, NpmRegistry, TarballRegistry};
+
+// ─── Registries ──────────────────────────────────────────────────────────────
+
+/// Every source a dependency can be resolved from, picked by the kind of specifier
+#[derive(Debug)]
+pub struct Registries {
+    pub npm: Arc<NpmRegistry>,
+    git: GitRegistry,
+    tarball: TarballRegistry,
+    policy: FetchPolicy,
+    /// Packages that could not be resolved from the caches in offline mode
+    missing: Mutex<Vec<String>>,
+}
+
+impl Registries {
+    pub fn new(npm: Arc<NpmRegistry>, http: Http) -> Self {
+        Self {
+            npm,
+            git: GitRegistry::new(&http),
+            policy: http.policy(),
+            tarball: TarballRegistry::new(http),
+            missing: Mutex::new(vec![]),
+        }
+    }
+
+    pub async fn fetch(&self, package: &Package) -> Result<NpmPackage, NetworkError> {
+        let result = match package.registry {
+            Registry::Local => return LocalRegistry.fetch(package).await,
+            // Git runs outside of the HTTP client, so it is stopped here
+            Registry::Git if self.policy == FetchPolicy::Offline => {
+                Err(NetworkError::Offline(package.to_string()))
+            }
+            Registry::Npm => self.npm.fetch(package).await,
+            Registry::Git => self.git.fetch(package).await,
+            Registry::Tarball => self.tarball.fetch(package).await,
+        };
+
+        if let Err(NetworkError::Offline(_)) = &result {
+            self.missing.lock().unwrap().push(package.to_string());
+        }
+        result
+    }
+
+    pub fn missing(&self) -> Vec<String> {
+        let mut missing = self.missing.lock().unwrap().clone();
+        missing.sort();
+        missing.dedup();
+        missing
+    }
+}
@@ -0,0 +1,149 @@
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+
+use crate::{
+    cache::PackagesCache,
+    contracts::Registry,
+    errors::NetworkError,
+    lockfile::lockfile_structure::{LockfileResolution, TarballResolution},
+    network::{sha512_integrity, Http},
+    package::{NpmPackage, Package},
+    tar::Gzip,
+};
+
+// ─── TarballRegistry ─────────────────────────────────────────────────────────
+
+/// Resolves dependencies pointing at a remote .tgz.
+/// The archive has to be downloaded to know its name, version and dependencies,
+/// so it is stored in the packages cache right away, under the key the downloader looks for.
+#[derive(Debug)]
+pub struct TarballRegistry {
+    http: Http,
+    directory: PathBuf,
+}
+
+impl TarballRegistry {
+    pub fn new(http: Http) -> Self {
+        Self {
+            http,
+            directory: PackagesCache::default().get_cache_directory().clone(),
+        }
+    }
+
+    #[cfg(test)]
+    fn at(http: Http, directory: PathBuf) -> Self {
+        Self { http, directory }
+    }
+}
+
+#[async_trait]
+impl Registry for TarballRegistry {
+    async fn fetch(&self, package: &Package) -> Result<NpmPackage, NetworkError> {
+        let url = &package.raw_version;
+        let failure = || NetworkError::FailedToFetchVersion(url.clone());
+
+        let content = self
+            .http
+            .send(self.http.get(url))
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        let manifest = Gzip::read_manifest(content.as_ref()).map_err(|_| failure())?;
+        let mut remote_package = NpmPackage::from_manifest(&manifest).map_err(|_| failure())?;
+
+        let integrity = sha512_integrity(&content);
+        remote_package.dist.tarball = url.clone();
+        remote_package.dist.integrity = Some(integrity.clone());
+        remote_package.dist.resolution = Some(LockfileResolution::Tarball(TarballResolution {
+            r#type: None,
+            tarball: url.clone(),
+            integrity: Some(integrity),
+            path: None,
+        }));
+
+        // Never at the path of a registry release with the same name@version
+        let path = self.directory.join(remote_package.store_key().to_string());
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(path, &content).await?;
+
+        Ok(remote_package)
+    }
+}
+
+// ─── Tests ───────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actors::PackageType;
+    use crate::conf::NpmConfig;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn archive(manifest: &str) -> Vec<u8> {
+        let mut builder = tar::Builder::new(GzEncoder::new(vec![], Compression::fast()));
+        let mut header = tar::Header::new_gnu();
+        header.set_size(manifest.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "package/package.json", manifest.as_bytes())
+            .unwrap();
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    /// Serves `body` to a single request
+    async fn serve(body: Vec<u8>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = [0; 1024];
+            let _ = socket.read(&mut request).await.unwrap();
+            let head = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            );
+            socket.write_all(head.as_bytes()).await.unwrap();
+            socket.write_all(&body).await.unwrap();
+        });
+
+        format!("http://{}/lib-1.2.3.tgz", address)
+    }
+
+    #[tokio::test]
+    async fn test_fetch_tarball_url() {
+        let cache = tempfile::tempdir().unwrap();
+
+        let content =
+            archive(r#"{"name": "lib", "version": "1.2.3", "dependencies": {"a": "^1"}}"#);
+        let url = serve(content.clone()).await;
+        let config = NpmConfig::new(Default::default());
+        let registry = TarballRegistry::at(Http::new(&config).unwrap(), cache.path().to_path_buf());
+
+        let package = Package::new(PackageType::Prod(format!("lib@{}", url)));
+        let fetched = registry.fetch(&package).await.unwrap();
+
+        assert_eq!(fetched.to_string(), "lib@1.2.3");
+        assert!(fetched.dependencies.as_ref().unwrap().contains_key("a"));
+        assert_eq!(
+            fetched.dist.resolution,
+            Some(LockfileResolution::Tarball(TarballResolution {
+                r#type: None,
+                tarball: url,
+                integrity: Some(sha512_integrity(&content)),
+                path: None,
+            }))
+        );
+        let cached = std::fs::read(cache.path().join(fetched.store_key().to_string())).unwrap();
+        assert_eq!(cached, content);
+        assert!(!cache.path().join("lib@1.2.3").exists());
+    }
+}
@@ -1,5 +1,6 @@
 use std::{
     fs::File,
+    io::Read,
     path::{Path, PathBuf},
 };
 
@@ -27,4 +28,23 @@ impl Gzip {
 
         Ok(())
     }
+
+    /// Reads the package.json at the root of the archive without unpacking it
+    pub fn read_manifest<R: Read>(source: R) -> Result<String, ZipError> {
+        let mut archive = Archive::new(GzDecoder::new(source));
+
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let path = entry.path()?.into_owned();
+            if path.components().count() == 2 && path.ends_with("package.json") {
+                let mut manifest = String::new();
+                entry.read_to_string(&mut manifest)?;
+                return Ok(manifest);
+            }
+        }
+
+        Err(ZipError::FailedToUnzip(
+            "No package.json in the archive".to_string(),
+        ))
+    }
 }
@@ -3,5 +3,6 @@ use console::Emoji;
 pub static RESOLVING: Emoji<'_, '_> = Emoji("🔍  ", "");
 pub static DOWNLOADING: Emoji<'_, '_> = Emoji("🚚  ", "");
 pub static LINKING: Emoji<'_, '_> = Emoji("🔗  ", "");
+pub static BUILDING: Emoji<'_, '_> = Emoji("🔨  ", "");
 pub static EXTRACTING: Emoji<'_, '_> = Emoji("📦  ", "");
 pub static COMPLETED: Emoji<'_, '_> = Emoji("✅  ", "");
@@ -9,7 +9,7 @@ use indicatif_log_bridge::LogWrapper;
 use log::{Level, LevelFilter};
 use std::time::Duration;
 
-use super::constants::{COMPLETED, DOWNLOADING, EXTRACTING, LINKING, RESOLVING};
+use super::constants::{BUILDING, COMPLETED, DOWNLOADING, EXTRACTING, LINKING, RESOLVING};
 
 // ─────────────────────────────────────────────────────────────────────────────
 
@@ -21,6 +21,7 @@ pub struct UIProgress {
     downloading_spinner: ProgressBar,
     extracting_spinner: ProgressBar,
     linking_spinner: ProgressBar,
+    building_spinner: ProgressBar,
 
     is_only_verbose: bool,
 }
@@ -60,6 +61,7 @@ impl Default for UIProgress {
         let downloading_spinner = multi_pb.add(ProgressBar::new_spinner());
         let extracting_spinner = multi_pb.add(ProgressBar::new_spinner());
         let linking_spinner = multi_pb.add(ProgressBar::new_spinner());
+        let building_spinner = multi_pb.add(ProgressBar::new_spinner());
 
         let is_only_verbose = std::env::var(CRAFT_VERBOSE_LOGGING)
             .unwrap_or("false".to_string())
@@ -77,6 +79,7 @@ impl Default for UIProgress {
             downloading_spinner,
             extracting_spinner,
             linking_spinner,
+            building_spinner,
             is_only_verbose,
         }
     }
@@ -118,16 +121,33 @@ impl Progress for UIProgress {
                 self.linking_spinner
                     .enable_steady_tick(Duration::from_millis(100));
             }
+            Phase::Building => {
+                self.linking_spinner.finish();
+                self.linking_spinner
+                    .set_message(format!("{} Linked in {}ms", COMPLETED, took));
+                self.building_spinner
+                    .set_message(format!("{} Building ...", BUILDING));
+                self.building_spinner
+                    .enable_steady_tick(Duration::from_millis(100));
+            }
         }
     }
 
     fn finish(&self) {
-        self.linking_spinner
-            .set_message(format!("{} Linked", COMPLETED));
+        // Only installs running lifecycle scripts get to the build phase
+        match self.building_spinner.message().is_empty() {
+            true => self
+                .linking_spinner
+                .set_message(format!("{} Linked", COMPLETED)),
+            false => self
+                .building_spinner
+                .set_message(format!("{} Built", COMPLETED)),
+        }
         self.resolving_spinner.finish();
         self.downloading_spinner.finish();
         self.extracting_spinner.finish();
         self.linking_spinner.finish();
+        self.building_spinner.finish();
     }
 
     fn start(&self, rx: std::sync::mpsc::Receiver<ProgressAction>) {